@@ -0,0 +1,25 @@
+#![no_main]
+
+use std::fs::File;
+use std::io::{BufReader, Write};
+use std::path::PathBuf;
+
+use libfuzzer_sys::fuzz_target;
+use mcircuit::parsers::blif::BlifParser;
+use mcircuit::Parse;
+
+// The BLIF parser only reads from files, so each input gets written out to a scratch file first
+fn scratch_path() -> PathBuf {
+    std::env::temp_dir().join(format!("sv_circuit_fuzz_{}.blif", std::process::id()))
+}
+
+fuzz_target!(|data: &[u8]| {
+    let path = scratch_path();
+    File::create(&path)
+        .and_then(|mut f| f.write_all(data))
+        .expect("Failed to write scratch file");
+
+    let reader = BufReader::new(File::open(&path).expect("Failed to open scratch file"));
+    // Errors are fine; panics and hangs are not
+    let _ = sv_circuit::flatten(BlifParser::<bool>::new(reader));
+});
@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // Errors are fine; panics and hangs are not
+    let _ = sv_circuit::parse::witness(data);
+});
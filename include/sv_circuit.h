@@ -0,0 +1,132 @@
+#ifndef SV_CIRCUIT_H
+#define SV_CIRCUIT_H
+
+/* Generated by cbindgen from src/capi.rs. Do not edit by hand. */
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#define WITNESS_LEN 656
+
+typedef enum SvcStatus {
+  SVC_STATUS_OK = 0,
+  // A required pointer argument was NULL
+  SVC_STATUS_NULL_POINTER,
+  // Reading the input or writing to the file descriptor failed
+  SVC_STATUS_IO,
+  // The BLIF or witness couldn't be parsed
+  SVC_STATUS_PARSE,
+  // The netlist parsed, but doesn't describe a circuit that can be flattened or exported
+  SVC_STATUS_CIRCUIT,
+  // A bug in sv_circuit. The message holds the panic message.
+  SVC_STATUS_PANIC,
+} SvcStatus;
+
+// A flattened boolean circuit. Owned by the caller, who must release it with `svc_circuit_free`.
+typedef struct SvcCircuit SvcCircuit;
+
+// A parsed witness. Owned by the caller, who must release it with `svc_witness_free`.
+typedef struct SvcWitness SvcWitness;
+
+// Options for `svc_circuit_write_ir_with_options`. Fields are only ever added at the end, so
+// start from `svc_export_options_default()` and set the ones you need.
+typedef struct SvcExportOptions {
+  // `sizeof(SvcExportOptions)` as the caller was compiled. Fields past it keep their defaults,
+  // so callers built against an older header still work.
+  size_t size;
+  // Add comments naming ports and module instances
+  bool annotate;
+  // Add `@delete` directives for wires once they're dead
+  bool delete_dead;
+} SvcExportOptions;
+
+// Describes the last error on this thread, or returns NULL if there hasn't been one. The string
+// is owned by sv_circuit and valid until the next call on this thread.
+const char *svc_last_error(void);
+
+// Flattens the top (first) module of the BLIF netlist in `data[0..len]`. On success, `*out`
+// holds the circuit.
+//
+// # Safety
+// `data` must point to `len` readable bytes and `out` to writable memory.
+enum SvcStatus svc_flatten_blif(const uint8_t *data, size_t len, struct SvcCircuit **out);
+
+// Releases a circuit. Does nothing if `circuit` is NULL.
+//
+// # Safety
+// `circuit` must have come from `svc_flatten_blif`, and not have been freed already.
+void svc_circuit_free(struct SvcCircuit *circuit);
+
+// Number of gates in the circuit, or 0 if `circuit` is NULL
+//
+// # Safety
+// `circuit` must be NULL or a live circuit.
+size_t svc_circuit_gate_count(const struct SvcCircuit *circuit);
+
+// Number of wires in the circuit, or 0 if `circuit` is NULL
+//
+// # Safety
+// `circuit` must be NULL or a live circuit.
+size_t svc_circuit_wire_count(const struct SvcCircuit *circuit);
+
+// Number of input wires of the circuit, or 0 if `circuit` is NULL
+//
+// # Safety
+// `circuit` must be NULL or a live circuit.
+size_t svc_circuit_input_count(const struct SvcCircuit *circuit);
+
+// Number of output wires of the circuit, or 0 if `circuit` is NULL
+//
+// # Safety
+// `circuit` must be NULL or a live circuit.
+size_t svc_circuit_output_count(const struct SvcCircuit *circuit);
+
+// Parses the witness in `data[0..len]`, in the same format `sv-compositor` reads. On success,
+// `*out` holds the witness.
+//
+// # Safety
+// `data` must point to `len` readable bytes and `out` to writable memory.
+enum SvcStatus svc_witness_parse(const uint8_t *data, size_t len, struct SvcWitness **out);
+
+// Releases a witness. Does nothing if `witness` is NULL.
+//
+// # Safety
+// `witness` must have come from `svc_witness_parse`, and not have been freed already.
+void svc_witness_free(struct SvcWitness *witness);
+
+// The default export options, with `size` filled in
+struct SvcExportOptions svc_export_options_default(void);
+
+// Writes the circuit as IR that checks `witness`, like the `.circuit` file written by
+// `sv-compositor`. `annotate` adds comments naming ports and module instances. The file
+// descriptor is left open. Fails with `Circuit` unless the witness has at least two steps and
+// the circuit takes two inputs as wide as a step and has a 1-bit output, since each step is
+// checked by calling it on a pair of steps.
+//
+// # Safety
+// `circuit` and `witness` must be live, and `fd` an open, writable file descriptor.
+enum SvcStatus svc_circuit_write_ir(const struct SvcCircuit *circuit,
+                                    const struct SvcWitness *witness,
+                                    int fd,
+                                    bool annotate);
+
+// Like `svc_circuit_write_ir`, with the options in `*options`. A NULL `options` uses the
+// defaults.
+//
+// # Safety
+// `circuit` and `witness` must be live, `fd` an open, writable file descriptor, and `options`
+// NULL or a pointer to at least `options->size` readable bytes.
+enum SvcStatus svc_circuit_write_ir_with_options(const struct SvcCircuit *circuit,
+                                                 const struct SvcWitness *witness,
+                                                 int fd,
+                                                 const struct SvcExportOptions *options);
+
+// Writes the circuit's inputs, gates and output assertions as a bincode-encoded sequence of
+// operations. The file descriptor is left open.
+//
+// # Safety
+// `circuit` must be live, and `fd` an open, writable file descriptor.
+enum SvcStatus svc_circuit_write_bincode(const struct SvcCircuit *circuit, int fd);
+
+#endif /* SV_CIRCUIT_H */
@@ -0,0 +1,579 @@
+//! Reads and writes combinational circuits in the [AIGER](https://fmv.jku.at/aiger/) format, in
+//! both its ASCII (`.aag`) and binary (`.aig`) flavours. This is what ABC and most other
+//! equivalence checkers and synthesis tools speak.
+//!
+//! And-inverter graphs only have AND gates and negated edges. When reading, every negated
+//! literal becomes a NOT gate and every output gets a buffer of its own. When writing, XORs are
+//! lowered to three ANDs and constants are folded away.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+use mcircuit::{Identity, Operation};
+use petgraph::algo::is_cyclic_directed;
+use thiserror::Error;
+
+use crate::generic::ports::{Direction, Port};
+use crate::generic::Wire;
+use crate::{BoolCircuit, SVCircuitError};
+
+#[derive(Error, Debug)]
+pub enum AigerError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("Malformed AIGER file: {0}")]
+    Malformed(String),
+
+    #[error("Only combinational circuits are supported, but this one has {0} latches")]
+    Latches(usize),
+
+    #[error("Gate {0} can't be represented in AIGER")]
+    UnsupportedGate(String),
+
+    #[error(transparent)]
+    Circuit(#[from] SVCircuitError),
+}
+
+fn malformed<S: Into<String>>(message: S) -> AigerError {
+    AigerError::Malformed(message.into())
+}
+
+/// Reads a single line, without the trailing newline
+fn read_line<R: BufRead>(reader: &mut R) -> Result<Option<String>, AigerError> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(line.trim_end_matches(&['\n', '\r'][..]).to_string()))
+}
+
+/// Parses a line made up of exactly `count` unsigned integers
+fn read_numbers<R: BufRead>(reader: &mut R, count: usize) -> Result<Vec<usize>, AigerError> {
+    let line = read_line(reader)?.ok_or_else(|| malformed("unexpected end of file"))?;
+    let numbers = line
+        .split_whitespace()
+        .map(|n| {
+            n.parse()
+                .map_err(|_| malformed(format!("bad number {:?}", n)))
+        })
+        .collect::<Result<Vec<usize>, _>>()?;
+    if numbers.len() != count {
+        return Err(malformed(format!(
+            "expected {} numbers, got {:?}",
+            count, line
+        )));
+    }
+    Ok(numbers)
+}
+
+/// Decodes one of the variable-length deltas used for AND gates in binary files
+fn read_delta<R: BufRead>(reader: &mut R) -> Result<usize, AigerError> {
+    let mut value: usize = 0;
+    for shift in (0..usize::BITS).step_by(7) {
+        let mut byte = [0u8; 1];
+        reader.read_exact(&mut byte)?;
+        value |= ((byte[0] & 0x7f) as usize) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(malformed("AND gate delta is too large"))
+}
+
+fn write_delta<W: Write>(writer: &mut W, mut value: usize) -> io::Result<()> {
+    while value >= 0x80 {
+        writer.write_all(&[(value & 0x7f) as u8 | 0x80])?;
+        value >>= 7;
+    }
+    writer.write_all(&[value as u8])
+}
+
+/// Turns AIGER literals into wires, adding NOT gates (and constants) as they're needed
+struct LiteralMap {
+    circuit: BoolCircuit,
+    max_var: usize,
+    /// Negated copies of variables get wires above every variable
+    negated: HashMap<usize, Wire>,
+    constant: bool,
+    next: Wire,
+}
+
+impl LiteralMap {
+    fn new(max_var: usize) -> Result<Self, AigerError> {
+        let next = max_var
+            .checked_mul(2)
+            .and_then(|n| n.checked_add(2))
+            .ok_or_else(|| malformed("maximum variable index is too large"))?;
+        Ok(LiteralMap {
+            circuit: BoolCircuit {
+                name: "aiger".to_string(),
+                ..Default::default()
+            },
+            max_var,
+            negated: HashMap::new(),
+            constant: false,
+            next,
+        })
+    }
+
+    fn fresh(&mut self) -> Wire {
+        self.next += 1;
+        self.next - 1
+    }
+
+    fn variable(&self, literal: usize) -> Result<usize, AigerError> {
+        let var = literal / 2;
+        if var > self.max_var {
+            return Err(malformed(format!("literal {} is out of range", literal)));
+        }
+        Ok(var)
+    }
+
+    fn wire(&mut self, literal: usize) -> Result<Wire, AigerError> {
+        let var = self.variable(literal)?;
+
+        // Variable 0 is the constant false
+        if var == 0 && !self.constant {
+            self.circuit._add_gate(Operation::Const(0, false))?;
+            self.constant = true;
+        }
+        if literal & 1 == 0 {
+            return Ok(var);
+        }
+
+        if let Some(wire) = self.negated.get(&var) {
+            return Ok(*wire);
+        }
+        let wire = self.max_var + 1 + var;
+        if var == 0 {
+            self.circuit._add_gate(Operation::Const(wire, true))?;
+        } else {
+            self.circuit
+                ._add_gate(Operation::AddConst(wire, var, true))?;
+        }
+        self.negated.insert(var, wire);
+        Ok(wire)
+    }
+}
+
+/// Reads a combinational AIGER file, in either the ASCII or binary format
+pub fn read<R: BufRead>(mut reader: R) -> Result<BoolCircuit, AigerError> {
+    let header = read_line(&mut reader)?.ok_or_else(|| malformed("empty file"))?;
+    let mut fields = header.split_whitespace();
+    let binary = match fields.next() {
+        Some("aag") => false,
+        Some("aig") => true,
+        _ => return Err(malformed(format!("bad header {:?}", header))),
+    };
+    let counts = fields
+        .map(|n| {
+            n.parse()
+                .map_err(|_| malformed(format!("bad header {:?}", header)))
+        })
+        .collect::<Result<Vec<usize>, _>>()?;
+    if counts.len() < 5 {
+        return Err(malformed(format!("bad header {:?}", header)));
+    }
+    let (max_var, num_inputs, num_latches, num_outputs, num_ands) =
+        (counts[0], counts[1], counts[2], counts[3], counts[4]);
+    if num_latches > 0 {
+        return Err(AigerError::Latches(num_latches));
+    }
+    // AIGER 1.9 adds bad state, invariant, justice and fairness properties
+    if counts[5..].iter().any(|n| *n > 0) {
+        return Err(malformed("properties other than outputs aren't supported"));
+    }
+    if num_inputs.saturating_add(num_ands) > max_var {
+        return Err(malformed("more inputs and AND gates than variables"));
+    }
+
+    let mut map = LiteralMap::new(max_var)?;
+
+    let mut inputs: Vec<Wire> = Vec::new();
+    for idx in 0..num_inputs {
+        let literal = if binary {
+            2 * (idx + 1)
+        } else {
+            read_numbers(&mut reader, 1)?[0]
+        };
+        let var = map.variable(literal)?;
+        if literal & 1 == 1 || var == 0 || !map.circuit.inputs.insert(var) {
+            return Err(malformed(format!("bad input literal {}", literal)));
+        }
+        inputs.push(var);
+    }
+
+    let mut output_literals: Vec<usize> = Vec::new();
+    for _ in 0..num_outputs {
+        output_literals.push(read_numbers(&mut reader, 1)?[0]);
+    }
+
+    for idx in 0..num_ands {
+        let (lhs, rhs0, rhs1) = if binary {
+            let lhs = 2 * (num_inputs + idx + 1);
+            let rhs0 = lhs
+                .checked_sub(read_delta(&mut reader)?)
+                .ok_or_else(|| malformed("bad AND gate delta"))?;
+            let rhs1 = rhs0
+                .checked_sub(read_delta(&mut reader)?)
+                .ok_or_else(|| malformed("bad AND gate delta"))?;
+            (lhs, rhs0, rhs1)
+        } else {
+            let numbers = read_numbers(&mut reader, 3)?;
+            (numbers[0], numbers[1], numbers[2])
+        };
+
+        let var = map.variable(lhs)?;
+        if lhs & 1 == 1 || var == 0 {
+            return Err(malformed(format!("bad AND gate literal {}", lhs)));
+        }
+        let (a, b) = (map.wire(rhs0)?, map.wire(rhs1)?);
+        map.circuit._add_gate(Operation::Mul(var, a, b))?;
+    }
+
+    // Give each output a buffer of its own, so that outputs never alias inputs or each other
+    let mut outputs: Vec<Wire> = Vec::new();
+    for literal in output_literals {
+        let src = map.wire(literal)?;
+        let dst = map.fresh();
+        map.circuit._add_gate(Operation::identity(dst, src))?;
+        map.circuit.outputs.insert(dst);
+        outputs.push(dst);
+    }
+
+    // The symbol table runs until the comment section
+    let mut input_names: Vec<Option<String>> = vec![None; inputs.len()];
+    let mut output_names: Vec<Option<String>> = vec![None; outputs.len()];
+    if binary {
+        // The binary AND section doesn't end in a newline, so we can't use `read_line`
+        let mut rest = String::new();
+        reader.read_to_string(&mut rest).map_err(AigerError::Io)?;
+        read_symbols(rest.lines(), &mut input_names, &mut output_names)?;
+    } else {
+        let lines = reader.lines().collect::<Result<Vec<String>, _>>()?;
+        read_symbols(
+            lines.iter().map(String::as_str),
+            &mut input_names,
+            &mut output_names,
+        )?;
+    }
+
+    let mut circuit = map.circuit;
+    for (direction, wires, names, prefix) in [
+        (Direction::Input, &inputs, &input_names, "i"),
+        (Direction::Output, &outputs, &output_names, "o"),
+    ] {
+        let names: Vec<(Wire, String)> = wires
+            .iter()
+            .zip(names.iter())
+            .enumerate()
+            .map(|(idx, (wire, name))| {
+                let name = name
+                    .clone()
+                    .unwrap_or_else(|| format!("{}[{}]", prefix, idx));
+                (*wire, name)
+            })
+            .collect();
+        for (wire, name) in names.iter() {
+            circuit.symbols.insert(*wire, name.clone());
+        }
+        circuit.ports.extend(Port::from_bit_names(
+            names.iter().map(|(w, n)| (*w, n.as_str())),
+            direction,
+        ));
+    }
+
+    circuit._build()?;
+    if is_cyclic_directed(&circuit.graph) {
+        return Err(SVCircuitError::CombinationalLoop {
+            name: circuit.name.clone(),
+        }
+        .into());
+    }
+
+    Ok(circuit)
+}
+
+fn read_symbols<'a>(
+    lines: impl Iterator<Item = &'a str>,
+    inputs: &mut [Option<String>],
+    outputs: &mut [Option<String>],
+) -> Result<(), AigerError> {
+    for line in lines {
+        if line == "c" {
+            break;
+        }
+        let (position, name) = line
+            .split_once(' ')
+            .ok_or_else(|| malformed(format!("bad symbol {:?}", line)))?;
+        let (table, idx) = match position.split_at(1) {
+            ("i", idx) => (&mut *inputs, idx),
+            ("o", idx) => (&mut *outputs, idx),
+            _ => continue,
+        };
+        let slot = idx
+            .parse::<usize>()
+            .ok()
+            .and_then(|idx| table.get_mut(idx))
+            .ok_or_else(|| malformed(format!("bad symbol {:?}", line)))?;
+        *slot = Some(name.to_string());
+    }
+    Ok(())
+}
+
+/// Builds up an and-inverter graph one (folded) gate at a time
+struct AigBuilder {
+    /// (lhs, rhs0, rhs1), with rhs0 >= rhs1 as the binary format requires
+    ands: Vec<(usize, usize, usize)>,
+    next_var: usize,
+}
+
+impl AigBuilder {
+    fn and(&mut self, a: usize, b: usize) -> usize {
+        match (a, b) {
+            (0, _) | (_, 0) => 0,
+            (1, x) | (x, 1) => x,
+            (a, b) if a == b => a,
+            (a, b) if a == b ^ 1 => 0,
+            (a, b) => {
+                let lhs = 2 * self.next_var;
+                self.next_var += 1;
+                self.ands.push((lhs, a.max(b), a.min(b)));
+                lhs
+            }
+        }
+    }
+
+    /// a ^ b = !(!(a & !b) & !(!a & b))
+    fn xor(&mut self, a: usize, b: usize) -> usize {
+        match (a, b) {
+            (0, x) | (x, 0) => x,
+            (1, x) | (x, 1) => x ^ 1,
+            (a, b) if a == b => 0,
+            (a, b) if a == b ^ 1 => 1,
+            (a, b) => {
+                let left = self.and(a, b ^ 1);
+                let right = self.and(a ^ 1, b);
+                self.and(left ^ 1, right ^ 1) ^ 1
+            }
+        }
+    }
+}
+
+/// Lowered form of a circuit, ready to be written in either format
+struct Aig {
+    inputs: Vec<(usize, Option<String>)>,
+    outputs: Vec<(usize, Option<String>)>,
+    ands: Vec<(usize, usize, usize)>,
+    max_var: usize,
+}
+
+fn lower(circuit: &BoolCircuit) -> Result<Aig, AigerError> {
+    if !circuit.flat {
+        return Err(SVCircuitError::NotFlat {
+            name: circuit.name.clone(),
+        }
+        .into());
+    }
+
+    let inputs = circuit.interface_names(Direction::Input);
+    let mut literals: HashMap<Wire, usize> = HashMap::new();
+    for (idx, (wire, _)) in inputs.iter().enumerate() {
+        literals.insert(*wire, 2 * (idx + 1));
+    }
+
+    let mut builder = AigBuilder {
+        ands: Vec::new(),
+        next_var: inputs.len() + 1,
+    };
+
+    for gate in circuit.topo_iter() {
+        let lit = |wire: &Wire| {
+            literals
+                .get(wire)
+                .copied()
+                .ok_or(SVCircuitError::UnassignedWire { wire: *wire })
+        };
+        let (dst, literal) = match gate {
+            Operation::Add(o, a, b) | Operation::Sub(o, a, b) => (o, builder.xor(lit(a)?, lit(b)?)),
+            Operation::AddConst(o, i, c) | Operation::SubConst(o, i, c) => {
+                (o, lit(i)? ^ (*c as usize))
+            }
+            Operation::Mul(o, a, b) => (o, builder.and(lit(a)?, lit(b)?)),
+            Operation::MulConst(o, i, c) => (o, if *c { lit(i)? } else { 0 }),
+            Operation::Const(o, c) => (o, *c as usize),
+            Operation::Input(_) | Operation::Random(_) | Operation::AssertZero(_) => {
+                return Err(AigerError::UnsupportedGate(format!("{:?}", gate)))
+            }
+        };
+        literals.insert(*dst, literal);
+    }
+
+    let outputs = circuit
+        .interface_names(Direction::Output)
+        .into_iter()
+        .map(|(wire, name)| {
+            literals
+                .get(&wire)
+                .map(|literal| (*literal, name))
+                .ok_or(SVCircuitError::UnassignedWire { wire })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Aig {
+        inputs: inputs
+            .into_iter()
+            .enumerate()
+            .map(|(idx, (_, name))| (2 * (idx + 1), name))
+            .collect(),
+        outputs,
+        max_var: builder.next_var - 1,
+        ands: builder.ands,
+    })
+}
+
+fn write_symbols<W: Write>(writer: &mut W, aig: &Aig) -> io::Result<()> {
+    for (prefix, entries) in [("i", &aig.inputs), ("o", &aig.outputs)] {
+        for (idx, (_, name)) in entries.iter().enumerate() {
+            if let Some(name) = name {
+                writeln!(writer, "{}{} {}", prefix, idx, name)?;
+            }
+        }
+    }
+    writeln!(writer, "c")?;
+    writeln!(writer, "Generated by sv_circuit")
+}
+
+/// Writes a flat boolean circuit in the ASCII AIGER format
+pub fn write_ascii<W: Write>(writer: &mut W, circuit: &BoolCircuit) -> Result<(), AigerError> {
+    let aig = lower(circuit)?;
+
+    writeln!(
+        writer,
+        "aag {} {} 0 {} {}",
+        aig.max_var,
+        aig.inputs.len(),
+        aig.outputs.len(),
+        aig.ands.len()
+    )?;
+    for (literal, _) in aig.inputs.iter() {
+        writeln!(writer, "{}", literal)?;
+    }
+    for (literal, _) in aig.outputs.iter() {
+        writeln!(writer, "{}", literal)?;
+    }
+    for (lhs, rhs0, rhs1) in aig.ands.iter() {
+        writeln!(writer, "{} {} {}", lhs, rhs0, rhs1)?;
+    }
+    write_symbols(writer, &aig)?;
+
+    Ok(())
+}
+
+/// Writes a flat boolean circuit in the binary AIGER format
+pub fn write_binary<W: Write>(writer: &mut W, circuit: &BoolCircuit) -> Result<(), AigerError> {
+    let aig = lower(circuit)?;
+
+    writeln!(
+        writer,
+        "aig {} {} 0 {} {}",
+        aig.max_var,
+        aig.inputs.len(),
+        aig.outputs.len(),
+        aig.ands.len()
+    )?;
+    for (literal, _) in aig.outputs.iter() {
+        writeln!(writer, "{}", literal)?;
+    }
+    for (lhs, rhs0, rhs1) in aig.ands.iter() {
+        write_delta(writer, lhs - rhs0)?;
+        write_delta(writer, rhs0 - rhs1)?;
+    }
+    write_symbols(writer, &aig)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aiger::{read, write_ascii, write_binary, AigerError};
+    use crate::equiv::{check, EquivalenceConfig};
+    use crate::{BoolCircuit, Direction};
+    use mcircuit::Operation;
+    use std::collections::HashSet;
+    use std::iter::FromIterator;
+
+    fn example() -> Result<BoolCircuit, AigerError> {
+        let mut circuit = BoolCircuit {
+            name: "example".to_string(),
+            inputs: HashSet::from_iter([2, 3, 4]),
+            outputs: HashSet::from_iter([9, 10, 11]),
+            ..Default::default()
+        };
+        circuit._add_gate(Operation::Add(5, 2, 3))?;
+        circuit._add_gate(Operation::Mul(6, 5, 4))?;
+        circuit._add_gate(Operation::AddConst(9, 6, true))?;
+        circuit._add_gate(Operation::Const(7, true))?;
+        circuit._add_gate(Operation::Add(10, 7, 4))?;
+        circuit._add_gate(Operation::Mul(11, 2, 2))?;
+        circuit._build()?;
+        Ok(circuit)
+    }
+
+    #[test]
+    fn test_read_ascii() -> Result<(), AigerError> {
+        // The half adder from the AIGER documentation
+        let aag = "aag 7 2 0 2 3\n2\n4\n6\n12\n6 13 15\n12 2 4\n14 3 5\ni0 x\ni1 y\no0 s\no1 c\n";
+        let circuit = read(aag.as_bytes())?;
+
+        for (x, y) in [(false, false), (false, true), (true, false), (true, true)] {
+            assert_eq!(circuit.simulate(&[x, y])?, vec![x ^ y, x & y]);
+        }
+        let names: Vec<&str> = circuit.ports.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["x", "y", "s", "c"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_roundtrip() -> Result<(), AigerError> {
+        let circuit = example()?;
+
+        for binary in [false, true] {
+            let mut written: Vec<u8> = Vec::new();
+            if binary {
+                write_binary(&mut written, &circuit)?;
+            } else {
+                write_ascii(&mut written, &circuit)?;
+            }
+            let read_back = read(written.as_slice())?;
+
+            assert_eq!(read_back.interface(Direction::Input).len(), 3);
+            let result = check(&circuit, &read_back, &EquivalenceConfig::default())?;
+            assert!(result.is_equivalent(), "{:?}", result);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rejects_bad_files() {
+        assert!(matches!(
+            read("aag 1 0 1 0 0\n2 3\n".as_bytes()),
+            Err(AigerError::Latches(1))
+        ));
+        assert!(matches!(
+            read("aag 2 1 0 1 0\n2\n8\n".as_bytes()),
+            Err(AigerError::Malformed(_))
+        ));
+        assert!(matches!(
+            read("aag 3 1 0 1 1\n2\n6\n6 2 4\n".as_bytes()),
+            Err(AigerError::Circuit(_))
+        ));
+        assert!(matches!(
+            read("aag 3 1 0 1 2\n2\n6\n6 2 4\n4 6 2\n".as_bytes()),
+            Err(AigerError::Circuit(_))
+        ));
+    }
+}
@@ -0,0 +1,81 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::PathBuf;
+
+use anyhow::Result;
+use clap::Parser;
+
+use sv_circuit::connection::ConnectionSpec;
+use sv_circuit::CircuitCompositor;
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+struct Cli {
+    /// Boolean half of the composite circuit
+    #[clap(short, long, value_name = "BLIF")]
+    boolean: PathBuf,
+
+    /// Arithmetic half of the composite circuit
+    #[clap(short, long, value_name = "BLIF")]
+    arithmetic: PathBuf,
+
+    /// JSON file listing the B2A bundles and random challenges, by port name
+    #[clap(short, long, value_name = "JSON")]
+    connections: PathBuf,
+
+    /// Where to write the composite circuit, as a bincode container
+    #[clap(short, long, value_name = "OUTPUT")]
+    output: PathBuf,
+
+    /// Store a checksum of the circuit in the container header
+    #[clap(long)]
+    checksum: bool,
+
+    /// Values of the boolean inputs, as `0`s and `1`s in order of ascending wire ID. Generates a
+    /// composite witness for the circuit.
+    #[clap(short, long, value_name = "BITS", requires = "witness_output")]
+    witness: Option<PathBuf>,
+
+    /// Value of a random challenge, in program order. Repeat once for each challenge.
+    #[clap(long, value_name = "VALUE", requires = "witness")]
+    challenge: Vec<u64>,
+
+    /// Where to write the bincode-serialized composite witness
+    #[clap(long, value_name = "WITNESS_OUTPUT", requires = "witness")]
+    witness_output: Option<PathBuf>,
+}
+
+/// Composes a boolean and an arithmetic circuit into a single program
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+
+    let spec = File::open(cli.connections)
+        .map(BufReader::new)
+        .map(ConnectionSpec::from_reader)??;
+    let compositor = CircuitCompositor::from_blif(&cli.boolean, &cli.arithmetic, &spec)?;
+
+    let (bool_gates, bool_wires, connections, arith_gates, arith_wires) = compositor.gate_stats();
+    log::info!(
+        "Boolean: {} gates, {} wires. Connections: {}. Arithmetic: {} gates, {} wires.",
+        bool_gates,
+        bool_wires,
+        connections,
+        arith_gates,
+        arith_wires
+    );
+
+    let writer = File::create(cli.output).map(BufWriter::new)?;
+    compositor.write(writer, cli.checksum)?;
+
+    if let (Some(witness), Some(witness_output)) = (cli.witness, cli.witness_output) {
+        let bits = File::open(witness)
+            .map(BufReader::new)
+            .map(sv_circuit::parse::bits)??;
+        let witness = compositor.witness(&bits, &cli.challenge)?;
+        File::create(witness_output)
+            .map(BufWriter::new)
+            .map(|writer| witness.write(writer))??;
+    }
+
+    Ok(())
+}
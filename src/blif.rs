@@ -0,0 +1,362 @@
+//! Writes boolean circuits back out as BLIF, in the same dialect Yosys produces and
+//! [`flatten`](crate::flatten) reads: `.gate` lines using the `AND`, `XOR`, `NOT` and `BUF` cells,
+//! with constants read from the `$false` and `$true` nets.
+//!
+//! Wires keep the names they had in the source design wherever we know them. Flattened circuits
+//! have their names qualified with the name of the top module, which is stripped off again here.
+
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
+
+use mcircuit::{HasIO, Operation};
+use thiserror::Error;
+
+use crate::generic::ports::Direction;
+use crate::generic::Wire;
+use crate::{BoolCircuit, SVCircuitError};
+
+#[derive(Error, Debug)]
+pub enum BlifError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("Gate {0} can't be represented in BLIF")]
+    UnsupportedGate(String),
+
+    #[error(transparent)]
+    Circuit(#[from] SVCircuitError),
+}
+
+const FALSE: &str = "$false";
+const TRUE: &str = "$true";
+
+/// Makes a name safe to use as a BLIF token
+fn sanitize(name: &str) -> String {
+    let mut name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_whitespace() || c == '=' || c == '#' {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect();
+    // A trailing backslash would continue the line
+    if name.ends_with('\\') {
+        name.push('_');
+    }
+    name
+}
+
+/// Unique BLIF names for every wire a circuit uses
+struct NetNames {
+    names: HashMap<Wire, String>,
+    taken: HashSet<String>,
+}
+
+impl NetNames {
+    fn new(circuit: &BoolCircuit) -> Self {
+        let mut nets = NetNames {
+            names: HashMap::new(),
+            // The constant nets are created by the parser, so nothing else may use their names
+            taken: [FALSE, TRUE].iter().map(|s| s.to_string()).collect(),
+        };
+
+        let scope = format!("{}.", circuit.name);
+        let interface = circuit
+            .interface_names(Direction::Input)
+            .into_iter()
+            .chain(circuit.interface_names(Direction::Output));
+        for (wire, port_name) in interface {
+            let name = circuit
+                .symbols
+                .get(wire)
+                .map(|name| name.strip_prefix(&scope).unwrap_or(name).to_string())
+                .or(port_name);
+            nets.name(wire, name);
+        }
+
+        let mut wires: Vec<Wire> = circuit
+            .graph
+            .node_weights()
+            .flat_map(|gate| gate.inputs().chain(gate.outputs()))
+            .chain(circuit.subcircuits.iter().flat_map(|sub| {
+                sub.inputs
+                    .iter()
+                    .chain(sub.outputs.iter())
+                    .map(|(parent, _)| *parent)
+            }))
+            .collect();
+        wires.sort_unstable();
+        wires.dedup();
+        for wire in wires {
+            let name = circuit
+                .symbols
+                .get(wire)
+                .map(|name| name.strip_prefix(&scope).unwrap_or(name).to_string());
+            nets.name(wire, name);
+        }
+
+        nets
+    }
+
+    fn name(&mut self, wire: Wire, name: Option<String>) {
+        if self.names.contains_key(&wire) {
+            return;
+        }
+
+        let base = name
+            .map(|name| sanitize(&name))
+            .unwrap_or_else(|| format!("w{}", wire));
+        let mut name = base.clone();
+        let mut suffix = 0;
+        while self.taken.contains(&name) {
+            suffix += 1;
+            name = format!("{}${}", base, suffix);
+        }
+
+        self.taken.insert(name.clone());
+        self.names.insert(wire, name);
+    }
+
+    fn get(&self, wire: Wire) -> &str {
+        &self.names[&wire]
+    }
+}
+
+fn write_model<W: Write>(
+    writer: &mut W,
+    circuit: &BoolCircuit,
+    nets: &NetNames,
+    models: &HashMap<String, NetNames>,
+) -> Result<(), BlifError> {
+    writeln!(writer, ".model {}", sanitize(&circuit.name))?;
+    for (directive, direction) in [
+        (".inputs", Direction::Input),
+        (".outputs", Direction::Output),
+    ] {
+        write!(writer, "{}", directive)?;
+        for wire in circuit.interface(direction) {
+            write!(writer, " {}", nets.get(wire))?;
+        }
+        writeln!(writer)?;
+    }
+
+    for gate in circuit.topo_iter() {
+        match gate {
+            Operation::Add(o, a, b) | Operation::Sub(o, a, b) => writeln!(
+                writer,
+                ".gate XOR A={} B={} OUT={}",
+                nets.get(*a),
+                nets.get(*b),
+                nets.get(*o)
+            )?,
+            Operation::Mul(o, a, b) => writeln!(
+                writer,
+                ".gate AND A={} B={} OUT={}",
+                nets.get(*a),
+                nets.get(*b),
+                nets.get(*o)
+            )?,
+            Operation::AddConst(o, i, c) | Operation::SubConst(o, i, c) => writeln!(
+                writer,
+                ".gate {} IN={} OUT={}",
+                if *c { "NOT" } else { "BUF" },
+                nets.get(*i),
+                nets.get(*o)
+            )?,
+            Operation::MulConst(o, i, c) => writeln!(
+                writer,
+                ".gate BUF IN={} OUT={}",
+                if *c { nets.get(*i) } else { FALSE },
+                nets.get(*o)
+            )?,
+            Operation::Const(o, c) => writeln!(
+                writer,
+                ".gate BUF IN={} OUT={}",
+                if *c { TRUE } else { FALSE },
+                nets.get(*o)
+            )?,
+            Operation::Input(_) | Operation::Random(_) | Operation::AssertZero(_) => {
+                return Err(BlifError::UnsupportedGate(format!("{:?}", gate)))
+            }
+        }
+    }
+
+    for sub in circuit.subcircuits.iter() {
+        let sub_nets = models
+            .get(&sub.name)
+            .ok_or_else(|| SVCircuitError::MissingDependency {
+                dependency: sub.name.clone(),
+                parent: circuit.name.clone(),
+            })?;
+        write!(writer, ".subckt {}", sanitize(&sub.name))?;
+        for (parent, child) in sub.inputs.iter().chain(sub.outputs.iter()) {
+            write!(writer, " {}={}", sub_nets.get(*child), nets.get(*parent))?;
+        }
+        writeln!(writer)?;
+    }
+
+    writeln!(writer, ".end")?;
+    Ok(())
+}
+
+/// Writes a flat boolean circuit as a single BLIF model
+pub fn write<W: Write>(writer: &mut W, circuit: &BoolCircuit) -> Result<(), BlifError> {
+    write_hierarchy(writer, circuit, &HashMap::new())
+}
+
+/// Writes a hierarchical design: `top` first, followed by the models of its subcircuits, each of
+/// which is instantiated with a `.subckt` line. `models` may contain models that aren't used, but
+/// they won't be written.
+pub fn write_hierarchy<W: Write>(
+    writer: &mut W,
+    top: &BoolCircuit,
+    models: &HashMap<String, BoolCircuit>,
+) -> Result<(), BlifError> {
+    // Work out which models are reachable from the top, in the order we first come across them
+    let mut order: Vec<&BoolCircuit> = vec![top];
+    let mut seen: HashSet<&str> = HashSet::new();
+    let mut idx = 0;
+    while idx < order.len() {
+        for sub in order[idx].subcircuits.iter() {
+            if let Some(model) = models.get(&sub.name) {
+                if sub.name != top.name && seen.insert(&sub.name) {
+                    order.push(model);
+                }
+            }
+        }
+        idx += 1;
+    }
+
+    let named: HashMap<String, NetNames> = order[1..]
+        .iter()
+        .map(|model| (model.name.clone(), NetNames::new(model)))
+        .collect();
+
+    writeln!(writer, "# Generated by sv_circuit")?;
+    writeln!(writer)?;
+    write_model(writer, top, &NetNames::new(top), &named)?;
+    for model in order[1..].iter() {
+        writeln!(writer)?;
+        write_model(writer, model, &named[&model.name], &named)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::blif::{sanitize, write, write_hierarchy};
+    use crate::equiv::{check, EquivalenceConfig};
+    use crate::{BoolCircuit, CircuitFlattener, SVCircuitError};
+    use mcircuit::parsers::blif::BlifParser;
+    use mcircuit::{Operation, Parse};
+    use std::collections::{HashMap, HashSet};
+    use std::convert::TryFrom;
+    use std::fs::File;
+    use std::io::{BufReader, Write};
+    use std::iter::FromIterator;
+    use std::path::PathBuf;
+
+    fn reflatten(blif: &[u8], name: &str) -> BoolCircuit {
+        let path: PathBuf = std::env::temp_dir().join(format!("sv_circuit_{}.blif", name));
+        File::create(&path)
+            .and_then(|mut f| f.write_all(blif))
+            .expect("Failed to write BLIF");
+        let reader = BufReader::new(File::open(&path).expect("Failed to open BLIF"));
+        crate::flatten(BlifParser::<bool>::new(reader))
+            .expect("Failed to flatten BLIF")
+            .0
+    }
+
+    fn half_adder() -> Result<BoolCircuit, SVCircuitError> {
+        let mut circuit = BoolCircuit {
+            name: "half_adder".to_string(),
+            inputs: HashSet::from_iter([2, 3]),
+            outputs: HashSet::from_iter([4, 5]),
+            ..Default::default()
+        };
+        circuit._add_gate(Operation::Add(4, 2, 3))?;
+        circuit._add_gate(Operation::Mul(5, 2, 3))?;
+        circuit._build()?;
+        Ok(circuit)
+    }
+
+    #[test]
+    fn test_sanitize() {
+        assert_eq!(sanitize("a b=c#d"), "a_b_c_d");
+        assert_eq!(sanitize("$techmap\\i0.out[3]"), "$techmap\\i0.out[3]");
+        assert_eq!(sanitize("trailing\\"), "trailing\\_");
+    }
+
+    #[test]
+    fn test_roundtrip_flat() -> Result<(), SVCircuitError> {
+        let mut circuit = half_adder()?;
+        circuit._add_gate(Operation::Const(6, true))?;
+        circuit._add_gate(Operation::Add(7, 6, 4))?;
+        circuit.outputs.insert(7);
+        circuit.symbols.insert(2, "a".to_string());
+        circuit.symbols.insert(7, "$true".to_string());
+        circuit._build()?;
+
+        let mut written: Vec<u8> = Vec::new();
+        write(&mut written, &circuit).expect("Failed to write BLIF");
+        let text = String::from_utf8(written.clone()).unwrap();
+        assert!(text.contains(".inputs a w3\n"), "{}", text);
+        assert!(text.contains(".gate BUF IN=$true OUT=w6\n"), "{}", text);
+        assert!(text.contains("OUT=$true$1\n"), "{}", text);
+
+        let read_back = reflatten(&written, "roundtrip_flat");
+        assert!(check(&circuit, &read_back, &EquivalenceConfig::default())?.is_equivalent());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_roundtrip_hierarchy() -> Result<(), SVCircuitError> {
+        let adder = half_adder()?;
+
+        // A full adder built from two half adders
+        let mut top = BoolCircuit {
+            name: "full_adder".to_string(),
+            inputs: HashSet::from_iter([2, 3, 4]),
+            outputs: HashSet::from_iter([6, 9]),
+            ..Default::default()
+        };
+        top.add_named_subcircuit(
+            "half_adder".to_string(),
+            "first".to_string(),
+            vec![(2, 2), (3, 3)],
+            vec![(5, 4), (7, 5)],
+        );
+        top.add_named_subcircuit(
+            "half_adder".to_string(),
+            "second".to_string(),
+            vec![(5, 2), (4, 3)],
+            vec![(6, 4), (8, 5)],
+        );
+        top._add_gate(Operation::Add(9, 7, 8))?;
+
+        let mut models: HashMap<String, BoolCircuit> =
+            HashMap::from_iter([(adder.name.clone(), adder)]);
+
+        let mut written: Vec<u8> = Vec::new();
+        write_hierarchy(&mut written, &top, &models).expect("Failed to write BLIF");
+        let text = String::from_utf8(written.clone()).unwrap();
+        assert_eq!(text.matches(".subckt half_adder").count(), 2);
+        assert_eq!(text.matches(".model").count(), 2);
+
+        // Without the models, the subcircuits can't be written
+        assert!(write(&mut Vec::new(), &top).is_err());
+
+        models.insert(top.name.clone(), top);
+        let flat = CircuitFlattener::try_from(("full_adder".to_string(), models))
+            .and_then(|mut flattener| flattener.flatten())?;
+        let read_back = reflatten(&written, "roundtrip_hierarchy");
+        assert!(check(&flat, &read_back, &EquivalenceConfig::default())?.is_equivalent());
+
+        Ok(())
+    }
+}
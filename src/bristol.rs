@@ -0,0 +1,409 @@
+//! Reads and writes boolean circuits in
+//! [Bristol Fashion](https://nigelsmart.github.io/MPC-Circuits/), the format most MPC and
+//! garbled-circuit frameworks exchange circuits in.
+//!
+//! Bristol Fashion has no names. Inputs and outputs are split into groups (one per port when we
+//! know them) and laid out in order: input bits take the first wires of the circuit and output
+//! bits the last ones.
+
+use std::collections::{HashMap, HashSet};
+use std::io::{self, BufRead, Write};
+
+use mcircuit::Operation;
+use petgraph::algo::is_cyclic_directed;
+use thiserror::Error;
+
+use crate::generic::ports::{Direction, Port};
+use crate::generic::Wire;
+use crate::{BoolCircuit, SVCircuitError};
+
+#[derive(Error, Debug)]
+pub enum BristolError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("Malformed Bristol circuit: {0}")]
+    Malformed(String),
+
+    #[error("Gate {0} can't be represented in Bristol Fashion")]
+    UnsupportedGate(String),
+
+    #[error(transparent)]
+    Circuit(#[from] SVCircuitError),
+}
+
+fn malformed<S: Into<String>>(message: S) -> BristolError {
+    BristolError::Malformed(message.into())
+}
+
+fn parse_number(token: &str) -> Result<usize, BristolError> {
+    token
+        .parse()
+        .map_err(|_| malformed(format!("bad number {:?}", token)))
+}
+
+/// Parses a line like `2 64 64`: a count, followed by that many group sizes
+fn parse_groups(line: Option<String>) -> Result<Vec<usize>, BristolError> {
+    let line = line.ok_or_else(|| malformed("unexpected end of file"))?;
+    let numbers = line
+        .split_whitespace()
+        .map(parse_number)
+        .collect::<Result<Vec<usize>, _>>()?;
+    match numbers.split_first() {
+        Some((count, sizes)) if *count == sizes.len() => Ok(sizes.to_vec()),
+        _ => Err(malformed(format!("bad group sizes {:?}", line))),
+    }
+}
+
+/// Reads a circuit in Bristol Fashion
+pub fn read<R: BufRead>(reader: R) -> Result<BoolCircuit, BristolError> {
+    let mut lines = reader
+        .lines()
+        .filter(|line| !matches!(line, Ok(line) if line.trim().is_empty()));
+    let mut next_line = || lines.next().transpose();
+
+    let header = next_line()?.ok_or_else(|| malformed("empty file"))?;
+    let header = header
+        .split_whitespace()
+        .map(parse_number)
+        .collect::<Result<Vec<usize>, _>>()?;
+    let (num_gates, num_wires) = match header[..] {
+        [gates, wires] => (gates, wires),
+        _ => return Err(malformed("expected gate and wire counts")),
+    };
+    let input_groups = parse_groups(next_line()?)?;
+    let output_groups = parse_groups(next_line()?)?;
+
+    let total = |groups: &[usize]| {
+        groups
+            .iter()
+            .try_fold(0usize, |acc, n| acc.checked_add(*n))
+            .filter(|n| *n <= num_wires)
+            .ok_or_else(|| malformed("more input or output bits than wires"))
+    };
+    let num_inputs = total(&input_groups)?;
+    let num_outputs = total(&output_groups)?;
+
+    let mut circuit = BoolCircuit {
+        name: "bristol".to_string(),
+        inputs: (0..num_inputs).collect(),
+        outputs: (num_wires - num_outputs..num_wires).collect(),
+        ..Default::default()
+    };
+
+    let wire = |token: &str| -> Result<Wire, BristolError> {
+        let wire = parse_number(token)?;
+        if wire >= num_wires {
+            return Err(malformed(format!("wire {} is out of range", wire)));
+        }
+        Ok(wire)
+    };
+
+    let mut driven: HashSet<Wire> = circuit.inputs.clone();
+    for _ in 0..num_gates {
+        let line = next_line()?.ok_or_else(|| malformed("fewer gates than expected"))?;
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let (op, tokens) = tokens.split_last().ok_or_else(|| malformed("empty gate"))?;
+        let (arity, tokens) = match tokens {
+            [ins, outs, rest @ ..] => ((parse_number(ins)?, parse_number(outs)?), rest),
+            _ => return Err(malformed(format!("bad gate {:?}", line))),
+        };
+        if tokens.len() != arity.0.saturating_add(arity.1) {
+            return Err(malformed(format!("bad gate {:?}", line)));
+        }
+        let (ins, outs) = tokens.split_at(arity.0);
+
+        let gates = match (*op, arity, ins, outs) {
+            ("XOR", (2, 1), [a, b], [o]) => vec![Operation::Add(wire(o)?, wire(a)?, wire(b)?)],
+            ("AND", (2, 1), [a, b], [o]) => vec![Operation::Mul(wire(o)?, wire(a)?, wire(b)?)],
+            ("INV", (1, 1), [i], [o]) | ("NOT", (1, 1), [i], [o]) => {
+                vec![Operation::AddConst(wire(o)?, wire(i)?, true)]
+            }
+            ("EQW", (1, 1), [i], [o]) => vec![Operation::AddConst(wire(o)?, wire(i)?, false)],
+            ("EQ", (1, 1), ["0"], [o]) => vec![Operation::Const(wire(o)?, false)],
+            ("EQ", (1, 1), ["1"], [o]) => vec![Operation::Const(wire(o)?, true)],
+            ("MAND", (ins, outs), _, _) if ins == 2 * outs => {
+                let (lhs, rhs) = tokens[..ins].split_at(outs);
+                lhs.iter()
+                    .zip(rhs.iter())
+                    .zip(tokens[ins..].iter())
+                    .map(|((a, b), o)| Ok(Operation::Mul(wire(o)?, wire(a)?, wire(b)?)))
+                    .collect::<Result<Vec<_>, BristolError>>()?
+            }
+            _ => return Err(malformed(format!("bad gate {:?}", line))),
+        };
+
+        for gate in gates {
+            if let Some(dst) = mcircuit::Gate::dst(&gate) {
+                driven.insert(dst);
+            }
+            circuit._add_gate(gate)?;
+        }
+    }
+
+    let undriven: Vec<Wire> = (num_wires - num_outputs..num_wires)
+        .filter(|w| !driven.contains(w))
+        .collect();
+    if !undriven.is_empty() {
+        return Err(SVCircuitError::UndrivenOutput {
+            name: circuit.name,
+            wires: undriven,
+        }
+        .into());
+    }
+
+    // Bristol Fashion doesn't name anything, so name each group after its position
+    let mut start = 0;
+    let mut groups: Vec<(Direction, String, Vec<Wire>)> = Vec::new();
+    for (idx, size) in input_groups.iter().enumerate() {
+        groups.push((
+            Direction::Input,
+            format!("in{}", idx),
+            (start..start + size).collect(),
+        ));
+        start += size;
+    }
+    let mut start = num_wires - num_outputs;
+    for (idx, size) in output_groups.iter().enumerate() {
+        groups.push((
+            Direction::Output,
+            format!("out{}", idx),
+            (start..start + size).collect(),
+        ));
+        start += size;
+    }
+    for (direction, name, wires) in groups {
+        let names: Vec<(Wire, String)> = wires
+            .iter()
+            .enumerate()
+            .map(|(bit, wire)| (*wire, format!("{}[{}]", name, bit)))
+            .collect();
+        for (wire, name) in names.iter() {
+            circuit.symbols.insert(*wire, name.clone());
+        }
+        circuit.ports.extend(Port::from_bit_names(
+            names.iter().map(|(w, n)| (*w, n.as_str())),
+            direction,
+        ));
+    }
+
+    circuit._build()?;
+    if is_cyclic_directed(&circuit.graph) {
+        return Err(SVCircuitError::CombinationalLoop {
+            name: circuit.name.clone(),
+        }
+        .into());
+    }
+
+    Ok(circuit)
+}
+
+/// A wire in the circuit being written, before we know how many wires it has in total
+#[derive(Clone, Copy, Debug)]
+enum Slot {
+    Internal(usize),
+    Output(usize),
+}
+
+/// Groups of interface wires, one per port. Without ports, all the wires form a single group.
+fn groups(circuit: &BoolCircuit, direction: Direction) -> Vec<Vec<Wire>> {
+    let groups: Vec<Vec<Wire>> = circuit
+        .ports
+        .iter()
+        .filter(|port| port.direction == direction)
+        .map(|port| port.wires.clone())
+        .collect();
+
+    if groups.is_empty() {
+        let wires = circuit.interface(direction);
+        if wires.is_empty() {
+            Vec::new()
+        } else {
+            vec![wires]
+        }
+    } else {
+        groups
+    }
+}
+
+/// Writes a flat boolean circuit in Bristol Fashion
+pub fn write<W: Write>(writer: &mut W, circuit: &BoolCircuit) -> Result<(), BristolError> {
+    if !circuit.flat {
+        return Err(SVCircuitError::NotFlat {
+            name: circuit.name.clone(),
+        }
+        .into());
+    }
+
+    let inputs = groups(circuit, Direction::Input);
+    let outputs = groups(circuit, Direction::Output);
+
+    let mut slots: HashMap<Wire, Slot> = HashMap::new();
+    for (idx, wire) in inputs.iter().flatten().enumerate() {
+        slots.insert(*wire, Slot::Internal(idx));
+    }
+    let mut next = slots.len();
+
+    // Gates that drive an output write straight to its slot at the end of the circuit
+    let mut output_slots: HashMap<Wire, usize> = HashMap::new();
+    for (idx, wire) in outputs.iter().flatten().enumerate() {
+        output_slots.entry(*wire).or_insert(idx);
+    }
+
+    let mut gates: Vec<(&str, Vec<Slot>, Slot)> = Vec::new();
+    let mut constants: Vec<(bool, Slot)> = Vec::new();
+    for gate in circuit.topo_iter() {
+        let slot = |wire: &Wire| {
+            slots
+                .get(wire)
+                .copied()
+                .ok_or(SVCircuitError::UnassignedWire { wire: *wire })
+        };
+        let (op, ins) = match gate {
+            Operation::Add(_, a, b) | Operation::Sub(_, a, b) => ("XOR", vec![slot(a)?, slot(b)?]),
+            Operation::Mul(_, a, b) => ("AND", vec![slot(a)?, slot(b)?]),
+            Operation::AddConst(_, i, c) | Operation::SubConst(_, i, c) => {
+                (if *c { "INV" } else { "EQW" }, vec![slot(i)?])
+            }
+            Operation::MulConst(_, i, true) => ("EQW", vec![slot(i)?]),
+            Operation::MulConst(_, _, false) => ("EQ", vec![]),
+            Operation::Const(_, _) => ("EQ", vec![]),
+            Operation::Input(_) | Operation::Random(_) | Operation::AssertZero(_) => {
+                return Err(BristolError::UnsupportedGate(format!("{:?}", gate)))
+            }
+        };
+
+        let dst = mcircuit::Gate::dst(gate).expect("Only gates with outputs get this far");
+        let out = match output_slots.remove(&dst) {
+            Some(idx) => Slot::Output(idx),
+            None => {
+                next += 1;
+                Slot::Internal(next - 1)
+            }
+        };
+        slots.insert(dst, out);
+
+        match gate {
+            Operation::Const(_, c) => constants.push((*c, out)),
+            Operation::MulConst(_, _, false) => constants.push((false, out)),
+            _ => gates.push((op, ins, out)),
+        }
+    }
+
+    // Outputs that weren't written directly (inputs, or wires that appear in several outputs)
+    // get copied into place
+    for (idx, wire) in outputs.iter().flatten().enumerate() {
+        let src = slots
+            .get(wire)
+            .copied()
+            .ok_or(SVCircuitError::UnassignedWire { wire: *wire })?;
+        if !matches!(src, Slot::Output(o) if o == idx) {
+            gates.push(("EQW", vec![src], Slot::Output(idx)));
+        }
+    }
+
+    let num_outputs: usize = outputs.iter().map(Vec::len).sum();
+    let num_wires = next + num_outputs;
+    let number = |slot: Slot| match slot {
+        Slot::Internal(n) => n,
+        Slot::Output(n) => next + n,
+    };
+    let sizes = |groups: &[Vec<Wire>]| {
+        std::iter::once(groups.len())
+            .chain(groups.iter().map(Vec::len))
+            .map(|n| n.to_string())
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+
+    writeln!(writer, "{} {}", gates.len() + constants.len(), num_wires)?;
+    writeln!(writer, "{}", sizes(&inputs))?;
+    writeln!(writer, "{}", sizes(&outputs))?;
+    writeln!(writer)?;
+
+    // Constants can't depend on anything, so they all go first
+    for (value, out) in constants {
+        writeln!(writer, "1 1 {} {} EQ", value as u8, number(out))?;
+    }
+    for (op, ins, out) in gates {
+        write!(writer, "{} 1", ins.len())?;
+        for slot in ins {
+            write!(writer, " {}", number(slot))?;
+        }
+        writeln!(writer, " {} {}", number(out), op)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bristol::{read, write, BristolError};
+    use crate::equiv::{check, EquivalenceConfig};
+    use crate::BoolCircuit;
+    use mcircuit::Operation;
+    use std::collections::HashSet;
+    use std::iter::FromIterator;
+
+    #[test]
+    fn test_read() -> Result<(), BristolError> {
+        // A full adder, with each operand in its own group
+        let bristol = "5 8\n3 1 1 1\n1 2\n\n2 1 0 1 3 XOR\n2 1 3 2 6 XOR\n2 1 0 1 4 AND\n\
+                       2 1 3 2 5 AND\n2 1 4 5 7 XOR\n";
+        let circuit = read(bristol.as_bytes())?;
+
+        for x in 0..8u8 {
+            let bits: Vec<bool> = (0..3).map(|i| (x >> i) & 1 == 1).collect();
+            let sum = bits.iter().filter(|b| **b).count();
+            assert_eq!(circuit.simulate(&bits)?, vec![sum & 1 == 1, sum >= 2]);
+        }
+        assert_eq!(circuit.ports.len(), 4);
+        assert_eq!(circuit.ports[3].wires, vec![6, 7]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_roundtrip() -> Result<(), BristolError> {
+        let mut circuit = BoolCircuit {
+            name: "example".to_string(),
+            inputs: HashSet::from_iter([2, 3, 4]),
+            outputs: HashSet::from_iter([2, 6, 9, 10]),
+            ..Default::default()
+        };
+        circuit._add_gate(Operation::Add(5, 2, 3))?;
+        circuit._add_gate(Operation::Mul(6, 5, 4))?;
+        circuit._add_gate(Operation::AddConst(9, 6, true))?;
+        circuit._add_gate(Operation::Const(7, true))?;
+        circuit._add_gate(Operation::Mul(10, 7, 4))?;
+        circuit._build()?;
+
+        let mut written: Vec<u8> = Vec::new();
+        write(&mut written, &circuit)?;
+        let read_back = read(written.as_slice())?;
+
+        let result = check(&circuit, &read_back, &EquivalenceConfig::default())?;
+        assert!(result.is_equivalent(), "{:?}", result);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rejects_bad_files() {
+        // Wire out of range
+        assert!(matches!(
+            read("1 3\n1 2\n1 1\n\n2 1 0 1 3 XOR\n".as_bytes()),
+            Err(BristolError::Malformed(_))
+        ));
+        // Output never driven
+        assert!(matches!(
+            read("1 4\n1 2\n1 1\n\n2 1 0 1 2 XOR\n".as_bytes()),
+            Err(BristolError::Circuit(_))
+        ));
+        // Loop
+        assert!(matches!(
+            read("2 4\n1 2\n1 1\n\n2 1 0 3 2 XOR\n2 1 2 1 3 AND\n".as_bytes()),
+            Err(BristolError::Circuit(_))
+        ));
+    }
+}
@@ -0,0 +1,437 @@
+//! C API, for linking the flattener and exporter into C and C++ programs. `cbindgen` generates
+//! `include/sv_circuit.h` from this module (see `cbindgen.toml`).
+//!
+//! Every fallible function returns an `SvcStatus`. On failure, `svc_last_error` describes what
+//! went wrong, until the next call on the same thread. Panics are caught at the boundary and
+//! reported as `SVC_STATUS_PANIC`, so they never unwind into C.
+
+use std::cell::RefCell;
+use std::ffi::CString;
+use std::fmt::Display;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Write};
+use std::mem::{self, ManuallyDrop};
+use std::os::raw::{c_char, c_int};
+use std::os::unix::io::FromRawFd;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use mcircuit::parsers::blif::BlifParser;
+use mcircuit::Parse;
+
+use crate::export::{self, ExportError, ExportOptions};
+use crate::{BoolCircuit, Witness};
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SvcStatus {
+    Ok = 0,
+    /// A required pointer argument was NULL
+    NullPointer,
+    /// Reading the input or writing to the file descriptor failed
+    Io,
+    /// The BLIF or witness couldn't be parsed
+    Parse,
+    /// The netlist parsed, but doesn't describe a circuit that can be flattened or exported
+    Circuit,
+    /// A bug in sv_circuit. The message holds the panic message.
+    Panic,
+}
+
+/// A flattened boolean circuit. Owned by the caller, who must release it with `svc_circuit_free`.
+pub struct SvcCircuit(BoolCircuit);
+
+/// A parsed witness. Owned by the caller, who must release it with `svc_witness_free`.
+pub struct SvcWitness(Witness);
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: String) {
+    // Interior NULs would truncate the message anyway, so drop them rather than fail
+    let message = CString::new(message.replace('\0', "")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+/// A failed call: the status to return and the message to leave in `svc_last_error`
+struct Failure(SvcStatus, String);
+
+fn failure<E: Display>(status: SvcStatus) -> impl Fn(E) -> Failure {
+    move |e| Failure(status, e.to_string())
+}
+
+/// Runs `f`, turning its errors and panics into a status and a last error
+fn guard<F: FnOnce() -> Result<(), Failure>>(f: F) -> SvcStatus {
+    let result = panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_string());
+        Err(Failure(SvcStatus::Panic, message))
+    });
+    match result {
+        Ok(()) => SvcStatus::Ok,
+        Err(Failure(status, message)) => {
+            set_last_error(message);
+            status
+        }
+    }
+}
+
+/// Borrows `len` bytes from `data`, which may only be NULL if `len` is 0
+unsafe fn bytes<'a>(data: *const u8, len: usize) -> Result<&'a [u8], Failure> {
+    if len == 0 {
+        Ok(&[])
+    } else if data.is_null() {
+        Err(Failure(SvcStatus::NullPointer, "data is NULL".to_string()))
+    } else {
+        Ok(std::slice::from_raw_parts(data, len))
+    }
+}
+
+unsafe fn reference<'a, T>(ptr: *const T, name: &str) -> Result<&'a T, Failure> {
+    ptr.as_ref()
+        .ok_or_else(|| Failure(SvcStatus::NullPointer, format!("{} is NULL", name)))
+}
+
+/// Writes to a file descriptor owned by the caller, leaving it open afterwards
+unsafe fn write_fd<F>(fd: c_int, write: F) -> Result<(), Failure>
+where
+    F: FnOnce(&mut BufWriter<&File>) -> Result<(), Failure>,
+{
+    let file = ManuallyDrop::new(File::from_raw_fd(fd));
+    let mut writer = BufWriter::new(&*file);
+    write(&mut writer)?;
+    writer.flush().map_err(failure(SvcStatus::Io))
+}
+
+/// The BLIF parser only reads from files, so each buffer gets written out to a scratch file first
+fn scratch_path() -> std::path::PathBuf {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    std::env::temp_dir().join(format!(
+        "sv_circuit_capi_{}_{}.blif",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ))
+}
+
+fn flatten_blif(blif: &[u8]) -> Result<BoolCircuit, Failure> {
+    let path = scratch_path();
+    let flattened = File::create(&path)
+        .and_then(|mut f| f.write_all(blif))
+        .and_then(|_| File::open(&path))
+        .map_err(failure(SvcStatus::Io))
+        .and_then(|f| {
+            let parser = BlifParser::<bool>::new(BufReader::new(f));
+            crate::flatten(parser).map_err(failure(SvcStatus::Circuit))
+        });
+    let _ = fs::remove_file(&path);
+    Ok(flattened?.0)
+}
+
+/// Describes the last error on this thread, or returns NULL if there hasn't been one. The string
+/// is owned by sv_circuit and valid until the next call on this thread.
+#[no_mangle]
+pub extern "C" fn svc_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(ptr::null(), |message| message.as_ptr())
+    })
+}
+
+/// Flattens the top (first) module of the BLIF netlist in `data[0..len]`. On success, `*out`
+/// holds the circuit.
+///
+/// # Safety
+/// `data` must point to `len` readable bytes and `out` to writable memory.
+#[no_mangle]
+pub unsafe extern "C" fn svc_flatten_blif(
+    data: *const u8,
+    len: usize,
+    out: *mut *mut SvcCircuit,
+) -> SvcStatus {
+    guard(|| {
+        if out.is_null() {
+            return Err(Failure(SvcStatus::NullPointer, "out is NULL".to_string()));
+        }
+        let circuit = flatten_blif(bytes(data, len)?)?;
+        *out = Box::into_raw(Box::new(SvcCircuit(circuit)));
+        Ok(())
+    })
+}
+
+/// Releases a circuit. Does nothing if `circuit` is NULL.
+///
+/// # Safety
+/// `circuit` must have come from `svc_flatten_blif`, and not have been freed already.
+#[no_mangle]
+pub unsafe extern "C" fn svc_circuit_free(circuit: *mut SvcCircuit) {
+    if !circuit.is_null() {
+        drop(Box::from_raw(circuit));
+    }
+}
+
+/// Number of gates in the circuit, or 0 if `circuit` is NULL
+///
+/// # Safety
+/// `circuit` must be NULL or a live circuit.
+#[no_mangle]
+pub unsafe extern "C" fn svc_circuit_gate_count(circuit: *const SvcCircuit) -> usize {
+    circuit.as_ref().map_or(0, |c| c.0.ngate())
+}
+
+/// Number of wires in the circuit, or 0 if `circuit` is NULL
+///
+/// # Safety
+/// `circuit` must be NULL or a live circuit.
+#[no_mangle]
+pub unsafe extern "C" fn svc_circuit_wire_count(circuit: *const SvcCircuit) -> usize {
+    circuit.as_ref().map_or(0, |c| c.0.nwire())
+}
+
+/// Number of input wires of the circuit, or 0 if `circuit` is NULL
+///
+/// # Safety
+/// `circuit` must be NULL or a live circuit.
+#[no_mangle]
+pub unsafe extern "C" fn svc_circuit_input_count(circuit: *const SvcCircuit) -> usize {
+    circuit.as_ref().map_or(0, |c| c.0.inputs.len())
+}
+
+/// Number of output wires of the circuit, or 0 if `circuit` is NULL
+///
+/// # Safety
+/// `circuit` must be NULL or a live circuit.
+#[no_mangle]
+pub unsafe extern "C" fn svc_circuit_output_count(circuit: *const SvcCircuit) -> usize {
+    circuit.as_ref().map_or(0, |c| c.0.outputs.len())
+}
+
+/// Parses the witness in `data[0..len]`, in the same format `sv-compositor` reads. On success,
+/// `*out` holds the witness.
+///
+/// # Safety
+/// `data` must point to `len` readable bytes and `out` to writable memory.
+#[no_mangle]
+pub unsafe extern "C" fn svc_witness_parse(
+    data: *const u8,
+    len: usize,
+    out: *mut *mut SvcWitness,
+) -> SvcStatus {
+    guard(|| {
+        if out.is_null() {
+            return Err(Failure(SvcStatus::NullPointer, "out is NULL".to_string()));
+        }
+        let witness =
+            crate::parse::witness(bytes(data, len)?).map_err(failure(SvcStatus::Parse))?;
+        *out = Box::into_raw(Box::new(SvcWitness(witness)));
+        Ok(())
+    })
+}
+
+/// Releases a witness. Does nothing if `witness` is NULL.
+///
+/// # Safety
+/// `witness` must have come from `svc_witness_parse`, and not have been freed already.
+#[no_mangle]
+pub unsafe extern "C" fn svc_witness_free(witness: *mut SvcWitness) {
+    if !witness.is_null() {
+        drop(Box::from_raw(witness));
+    }
+}
+
+/// Options for `svc_circuit_write_ir_with_options`. Fields are only ever added at the end, so
+/// start from `svc_export_options_default()` and set the ones you need.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SvcExportOptions {
+    /// `sizeof(SvcExportOptions)` as the caller was compiled. Fields past it keep their defaults,
+    /// so callers built against an older header still work.
+    pub size: usize,
+    /// Add comments naming ports and module instances
+    pub annotate: bool,
+    /// Add `@delete` directives for wires once they're dead
+    pub delete_dead: bool,
+}
+
+impl Default for SvcExportOptions {
+    fn default() -> Self {
+        SvcExportOptions {
+            size: mem::size_of::<SvcExportOptions>(),
+            annotate: false,
+            delete_dead: false,
+        }
+    }
+}
+
+impl SvcExportOptions {
+    /// Reads the options the caller knows about, or the defaults if `options` is NULL
+    unsafe fn read(options: *const SvcExportOptions) -> ExportOptions {
+        let mut read = SvcExportOptions::default();
+        if !options.is_null() {
+            let size = ptr::addr_of!((*options).size)
+                .read_unaligned()
+                .min(read.size);
+            ptr::copy_nonoverlapping(
+                options as *const u8,
+                &mut read as *mut SvcExportOptions as *mut u8,
+                size,
+            );
+        }
+        ExportOptions {
+            annotate: read.annotate,
+            delete: read.delete_dead,
+        }
+    }
+}
+
+/// The default export options, with `size` filled in
+#[no_mangle]
+pub extern "C" fn svc_export_options_default() -> SvcExportOptions {
+    SvcExportOptions::default()
+}
+
+unsafe fn write_ir(
+    circuit: *const SvcCircuit,
+    witness: *const SvcWitness,
+    fd: c_int,
+    options: ExportOptions,
+) -> SvcStatus {
+    guard(|| {
+        let circuit = reference(circuit, "circuit")?;
+        let witness = reference(witness, "witness")?;
+        write_fd(fd, |writer| {
+            export::circuit_with(writer, &circuit.0, &witness.0, &options).map_err(|e| {
+                // Anything other than a circuit the exporter can't handle is a failed write
+                let status = if e.is::<ExportError>() {
+                    SvcStatus::Circuit
+                } else {
+                    SvcStatus::Io
+                };
+                Failure(status, e.to_string())
+            })
+        })
+    })
+}
+
+/// Writes the circuit as IR that checks `witness`, like the `.circuit` file written by
+/// `sv-compositor`. `annotate` adds comments naming ports and module instances. The file
+/// descriptor is left open. Fails with `Circuit` unless the witness has at least two steps and
+/// the circuit takes two inputs as wide as a step and has a 1-bit output, since each step is
+/// checked by calling it on a pair of steps.
+///
+/// # Safety
+/// `circuit` and `witness` must be live, and `fd` an open, writable file descriptor.
+#[no_mangle]
+pub unsafe extern "C" fn svc_circuit_write_ir(
+    circuit: *const SvcCircuit,
+    witness: *const SvcWitness,
+    fd: c_int,
+    annotate: bool,
+) -> SvcStatus {
+    let options = ExportOptions {
+        annotate,
+        ..Default::default()
+    };
+    write_ir(circuit, witness, fd, options)
+}
+
+/// Like `svc_circuit_write_ir`, with the options in `*options`. A NULL `options` uses the
+/// defaults.
+///
+/// # Safety
+/// `circuit` and `witness` must be live, `fd` an open, writable file descriptor, and `options`
+/// NULL or a pointer to at least `options->size` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn svc_circuit_write_ir_with_options(
+    circuit: *const SvcCircuit,
+    witness: *const SvcWitness,
+    fd: c_int,
+    options: *const SvcExportOptions,
+) -> SvcStatus {
+    write_ir(circuit, witness, fd, SvcExportOptions::read(options))
+}
+
+/// Writes the circuit's inputs, gates and output assertions as a bincode-encoded sequence of
+/// operations. The file descriptor is left open.
+///
+/// # Safety
+/// `circuit` must be live, and `fd` an open, writable file descriptor.
+#[no_mangle]
+pub unsafe extern "C" fn svc_circuit_write_bincode(
+    circuit: *const SvcCircuit,
+    fd: c_int,
+) -> SvcStatus {
+    guard(|| {
+        let circuit = reference(circuit, "circuit")?;
+        write_fd(fd, |writer| {
+            bincode::serialize_into(writer, &circuit.0).map_err(failure(SvcStatus::Io))
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::capi::*;
+    use std::ffi::CStr;
+
+    fn last_error() -> String {
+        let message = svc_last_error();
+        assert!(!message.is_null());
+        unsafe { CStr::from_ptr(message) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut circuit: *mut SvcCircuit = ptr::null_mut();
+        let status = unsafe { svc_flatten_blif(ptr::null(), 4, &mut circuit) };
+        assert_eq!(status, SvcStatus::NullPointer);
+        assert_eq!(last_error(), "data is NULL");
+        assert!(circuit.is_null());
+
+        let mut witness: *mut SvcWitness = ptr::null_mut();
+        let garbage = b"not a witness";
+        let status = unsafe { svc_witness_parse(garbage.as_ptr(), garbage.len(), &mut witness) };
+        assert_eq!(status, SvcStatus::Parse);
+        assert!(witness.is_null());
+
+        let status = unsafe { svc_circuit_write_bincode(ptr::null(), 1) };
+        assert_eq!(status, SvcStatus::NullPointer);
+        assert_eq!(last_error(), "circuit is NULL");
+
+        assert_eq!(unsafe { svc_circuit_gate_count(ptr::null()) }, 0);
+        unsafe { svc_circuit_free(ptr::null_mut()) };
+    }
+
+    #[test]
+    fn test_export_options() {
+        let defaults = ExportOptions::default();
+        let read = unsafe { SvcExportOptions::read(ptr::null()) };
+        assert_eq!(
+            (read.annotate, read.delete),
+            (defaults.annotate, defaults.delete)
+        );
+
+        let options = SvcExportOptions {
+            annotate: true,
+            delete_dead: true,
+            ..svc_export_options_default()
+        };
+        let read = unsafe { SvcExportOptions::read(&options) };
+        assert!(read.annotate && read.delete);
+
+        // A caller built before `delete_dead` was added doesn't get it by accident
+        let older = SvcExportOptions {
+            size: mem::size_of::<usize>() + mem::size_of::<bool>(),
+            ..options
+        };
+        let read = unsafe { SvcExportOptions::read(&older) };
+        assert!(read.annotate && !read.delete);
+    }
+}
@@ -1,10 +1,210 @@
+use std::cmp::max;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Write};
+use std::path::Path;
+
 use itertools::Itertools;
-use mcircuit::{CombineOperation, Operation};
-use serde::{Serialize, Serializer};
+use mcircuit::parsers::blif::BlifParser;
+use mcircuit::{CombineOperation, HasIO, Operation, Parse, WireValue};
+use serde::de::Error as _;
+use serde::ser::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
 
+use crate::connection::ConnectionSpec;
+use crate::container::{self, ContainerError};
+use crate::generic::circuit::GenericCircuit;
+use crate::generic::eval::evaluate_gate;
 use crate::generic::Wire;
 use crate::ArithCircuit;
 use crate::BoolCircuit;
+use crate::{evaluate_program, Evaluable};
+use crate::{Direction, SVCircuitError};
+
+/// Number of boolean wires converted into each arithmetic wire by a B2A gate
+const B2A_WIDTH: usize = 64;
+
+#[derive(Error, Debug)]
+pub enum CompositorError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("Malformed connection spec: {0}")]
+    Spec(#[from] serde_json::Error),
+
+    #[error("The {domain} circuit has no {kind}port named '{name}'")]
+    UnknownPort {
+        domain: &'static str,
+        kind: &'static str,
+        name: String,
+    },
+
+    #[error(
+        "Boolean port '{boolean}' is {width} bits wide, but arithmetic port '{arithmetic}' needs {expected}"
+    )]
+    WidthMismatch {
+        boolean: String,
+        arithmetic: String,
+        width: usize,
+        expected: usize,
+    },
+
+    #[error("Boolean wire {wire} (bit {bit} of the bundle starting at {lo}) isn't driven")]
+    UndrivenBundle { lo: Wire, bit: usize, wire: Wire },
+
+    #[error("Arithmetic wire {wire} isn't an input, so it can't be driven by a B2A gate")]
+    NotArithmeticInput { wire: Wire },
+
+    #[error("Arithmetic wire {wire} isn't an input, so it can't receive a random challenge")]
+    NotChallengeable { wire: Wire },
+
+    #[error("Arithmetic wire {wire} is connected more than once")]
+    ConnectedTwice { wire: Wire },
+
+    #[error(
+        "Boolean wire {wire} (bit {bit} of the bundle starting at {lo}) isn't an input, so it can't be driven by an A2B decomposition"
+    )]
+    NotBooleanInput { lo: Wire, bit: usize, wire: Wire },
+
+    #[error("Arithmetic wire {wire} isn't driven, so it can't be decomposed")]
+    UndrivenArithmetic { wire: Wire },
+
+    #[error("Boolean wire {wire} receives more than one decomposed bit")]
+    DecomposedTwice { wire: Wire },
+
+    #[error("Expected {expected} {domain} witness values, but got {actual}")]
+    WitnessLength {
+        domain: &'static str,
+        expected: usize,
+        actual: usize,
+    },
+
+    #[error("The witness fails {failed} of the circuit's assertions")]
+    UnsatisfiedWitness { failed: usize },
+
+    #[error("Serialization error: {0}")]
+    Bincode(#[from] bincode::Error),
+
+    #[error(transparent)]
+    Container(#[from] ContainerError),
+
+    #[error("Operation {index} of the composite program {reason}")]
+    MalformedProgram { index: usize, reason: String },
+
+    #[error(transparent)]
+    Circuit(#[from] SVCircuitError),
+}
+
+/// A complete witness for a composite program: a value for every `Input` and `Random` gate, in
+/// the order they appear in the program, one list per domain. These are exactly the inputs
+/// `evaluate_program` takes.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompositeWitness {
+    pub boolean: Vec<bool>,
+    pub arithmetic: Vec<u64>,
+}
+
+impl CompositeWitness {
+    /// Reads a witness written by `write`
+    pub fn read<R: Read>(reader: R) -> Result<Self, CompositorError> {
+        Ok(bincode::deserialize_from(reader)?)
+    }
+
+    /// Writes the witness with bincode
+    pub fn write<W: Write>(&self, writer: W) -> Result<(), CompositorError> {
+        Ok(bincode::serialize_into(writer, self)?)
+    }
+}
+
+/// A step of witness generation: either a gate of the program, or filling in the bits of an A2B
+/// decomposition
+#[derive(Clone, Copy)]
+enum Step<'a> {
+    Gate(&'a CombineOperation),
+    Decompose(Wire, Wire),
+}
+
+/// Looks up a port by name, optionally only among those in one direction
+// `Option::is_none_or` is newer than the pinned toolchain
+#[allow(clippy::unnecessary_map_or)]
+fn port_wires<T: WireValue>(
+    circuit: &GenericCircuit<T>,
+    domain: &'static str,
+    name: &str,
+    direction: Option<Direction>,
+) -> Result<Vec<Wire>, CompositorError> {
+    circuit
+        .ports
+        .iter()
+        .find(|port| port.name == name && direction.map_or(true, |d| port.direction == d))
+        .map(|port| port.wires.clone())
+        .ok_or_else(|| CompositorError::UnknownPort {
+            domain,
+            kind: if direction.is_some() { "input " } else { "" },
+            name: name.to_string(),
+        })
+}
+
+/// Returns the lowest of a run of consecutive boolean wires carrying `bits`, as B2A expects.
+/// Bits that aren't already consecutive are copied onto fresh wires.
+fn contiguous(boolean: &mut BoolCircuit, bits: &[Wire]) -> Result<Wire, SVCircuitError> {
+    if bits.windows(2).all(|pair| pair[1] == pair[0] + 1) {
+        return Ok(bits[0]);
+    }
+    let lo = boolean.nwire() + 1;
+    for (offset, bit) in bits.iter().enumerate() {
+        boolean._add_gate(Operation::AddConst(lo + offset, *bit, false))?;
+    }
+    boolean._build()?;
+    Ok(lo)
+}
+
+/// Like `contiguous`, but for boolean inputs that an A2B decomposition will drive. Inputs that
+/// aren't already consecutive are replaced by copies of fresh, consecutive inputs.
+fn contiguous_inputs(boolean: &mut BoolCircuit, bits: &[Wire]) -> Result<Wire, SVCircuitError> {
+    if bits.windows(2).all(|pair| pair[1] == pair[0] + 1) {
+        return Ok(bits[0]);
+    }
+    let lo = boolean.nwire() + 1;
+    for (offset, bit) in bits.iter().enumerate() {
+        boolean.inputs.remove(bit);
+        boolean.inputs.insert(lo + offset);
+        boolean._add_gate(Operation::AddConst(*bit, lo + offset, false))?;
+    }
+    boolean._build()?;
+    Ok(lo)
+}
+
+/// Whether witness generation has to compute the value of the wire a gate drives, rather than it
+/// being supplied
+fn computed<T: WireValue>(gate: &Operation<T>) -> bool {
+    !matches!(
+        gate,
+        Operation::Input(_) | Operation::Random(_) | Operation::AssertZero(_)
+    )
+}
+
+/// Evaluates a gate if all its inputs are known, recording the first one that isn't otherwise
+fn evaluate<T: Evaluable>(
+    gate: &Operation<T>,
+    values: &mut HashMap<Wire, T>,
+    missing: &mut Option<Wire>,
+) -> Result<bool, SVCircuitError> {
+    match evaluate_gate(gate, values) {
+        Ok(Some((wire, value))) => {
+            values.insert(wire, value);
+            Ok(true)
+        }
+        Ok(None) => Ok(true),
+        Err(SVCircuitError::UnassignedWire { wire }) => {
+            missing.get_or_insert(wire);
+            Ok(false)
+        }
+        Err(e) => Err(e),
+    }
+}
 
 /// A module that takes a top-level circuit representation and several subcircuit representations and
 /// produces a flattened circuit out of only logic gates. Like the BoolCircuit, this is a wrapper around
@@ -14,6 +214,8 @@ pub struct CircuitCompositor {
     boolean: BoolCircuit,
     arithmetic: ArithCircuit,
     connection: Vec<CombineOperation>,
+    /// A2B decompositions, as (arithmetic wire, lowest boolean wire) pairs
+    decomposition: Vec<(Wire, Wire)>,
 }
 
 impl CircuitCompositor {
@@ -22,70 +224,899 @@ impl CircuitCompositor {
             boolean,
             arithmetic,
             connection: vec![],
+            decomposition: vec![],
         }
     }
 
+    /// Flattens a boolean and an arithmetic BLIF netlist, and connects them as described by `spec`
+    pub fn from_blif(
+        boolean: &Path,
+        arithmetic: &Path,
+        spec: &ConnectionSpec,
+    ) -> Result<Self, CompositorError> {
+        let boolean = File::open(boolean)
+            .map(BufReader::new)
+            .map(BlifParser::<bool>::new)?;
+        let arithmetic = File::open(arithmetic)
+            .map(BufReader::new)
+            .map(BlifParser::<u64>::new)?;
+        Self::with_connections(
+            crate::flatten(boolean)?.0,
+            crate::flatten(arithmetic)?.0,
+            spec,
+        )
+    }
+
+    /// Connects two circuits as described by `spec`, resolving the ports it names to wires
+    pub fn with_connections(
+        mut boolean: BoolCircuit,
+        arithmetic: ArithCircuit,
+        spec: &ConnectionSpec,
+    ) -> Result<Self, CompositorError> {
+        let mut connections: Vec<(Wire, Wire)> = Vec::new();
+        for bundle in spec.bundles.iter() {
+            let bits = port_wires(&boolean, "boolean", &bundle.boolean, None)?;
+            let words = port_wires(
+                &arithmetic,
+                "arithmetic",
+                &bundle.arithmetic,
+                Some(Direction::Input),
+            )?;
+            if bits.len() != words.len() * B2A_WIDTH {
+                return Err(CompositorError::WidthMismatch {
+                    boolean: bundle.boolean.clone(),
+                    arithmetic: bundle.arithmetic.clone(),
+                    width: bits.len(),
+                    expected: words.len() * B2A_WIDTH,
+                });
+            }
+            for (word, bits) in words.into_iter().zip(bits.chunks(B2A_WIDTH)) {
+                connections.push((word, contiguous(&mut boolean, bits)?));
+            }
+        }
+
+        let mut decompositions: Vec<(Wire, Wire)> = Vec::new();
+        for bundle in spec.decompositions.iter() {
+            let words = port_wires(&arithmetic, "arithmetic", &bundle.arithmetic, None)?;
+            let bits = port_wires(&boolean, "boolean", &bundle.boolean, Some(Direction::Input))?;
+            if bits.len() != words.len() * B2A_WIDTH {
+                return Err(CompositorError::WidthMismatch {
+                    boolean: bundle.boolean.clone(),
+                    arithmetic: bundle.arithmetic.clone(),
+                    width: bits.len(),
+                    expected: words.len() * B2A_WIDTH,
+                });
+            }
+            for (word, bits) in words.into_iter().zip(bits.chunks(B2A_WIDTH)) {
+                decompositions.push((word, contiguous_inputs(&mut boolean, bits)?));
+            }
+        }
+
+        let mut challenges: Vec<Wire> = Vec::new();
+        for name in spec.challenges.iter() {
+            challenges.extend(port_wires(
+                &arithmetic,
+                "arithmetic",
+                name,
+                Some(Direction::Input),
+            )?);
+        }
+
+        let mut compositor = Self::new(boolean, arithmetic);
+        for (arith_wire, lo) in connections {
+            compositor.connect(arith_wire, lo)?;
+        }
+        for (arith_wire, lo) in decompositions {
+            compositor.decompose(arith_wire, lo)?;
+        }
+        compositor.challenge_all(challenges)?;
+        Ok(compositor)
+    }
+
     /// Add a BtoA gate to the connection circuit. Boolean wire ranges are given as [low, low + 64),
-    /// but we only ask for the low wire here
-    pub fn connect(&mut self, arith_wire: Wire, lo: Wire) {
+    /// but we only ask for the low wire here. The boolean wires must all be driven, and the
+    /// arithmetic wire must be an input that isn't connected yet.
+    pub fn connect(&mut self, arith_wire: Wire, lo: Wire) -> Result<(), CompositorError> {
+        self.check_connection(arith_wire, lo)?;
+        if self
+            .connection
+            .iter()
+            .any(|c| matches!(c, CombineOperation::B2A(wire, _) if *wire == arith_wire))
+        {
+            return Err(CompositorError::ConnectedTwice { wire: arith_wire });
+        }
         self.connection.push(CombineOperation::B2A(arith_wire, lo));
+        Ok(())
+    }
+
+    /// Checks a single B2A gate against the current state of both circuits
+    fn check_connection(&self, arith_wire: Wire, lo: Wire) -> Result<(), CompositorError> {
+        for (bit, wire) in (lo..lo + B2A_WIDTH).enumerate() {
+            if !self.boolean.inputs.contains(&wire)
+                && !self.boolean._gate_outputs.contains_key(&wire)
+            {
+                return Err(CompositorError::UndrivenBundle { lo, bit, wire });
+            }
+        }
+        if !self.arithmetic.inputs.contains(&arith_wire) {
+            return Err(CompositorError::NotArithmeticInput { wire: arith_wire });
+        }
+        Ok(())
+    }
+
+    /// Decomposes an arithmetic wire into the boolean inputs [lo, lo + 64), least significant bit
+    /// first (A2B). The arithmetic wire must be driven, and the boolean wires must be inputs that
+    /// aren't driven by another decomposition.
+    ///
+    /// The composite format has no A2B gate, so this is lowered to a gadget when serialized: the
+    /// prover supplies the bits as boolean inputs, a B2A gate packs them back into a fresh
+    /// arithmetic wire, and the difference between that and the decomposed wire is asserted to
+    /// be zero.
+    pub fn decompose(&mut self, arith_wire: Wire, lo: Wire) -> Result<(), CompositorError> {
+        self.check_decomposition(arith_wire, lo)?;
+        let taken = lo..lo + B2A_WIDTH;
+        for (_, other) in self.decomposition.iter() {
+            if taken.contains(other) || (*other..other + B2A_WIDTH).contains(&lo) {
+                return Err(CompositorError::DecomposedTwice {
+                    wire: max(lo, *other),
+                });
+            }
+        }
+        self.decomposition.push((arith_wire, lo));
+        Ok(())
+    }
+
+    /// Checks a single A2B decomposition against the current state of both circuits
+    fn check_decomposition(&self, arith_wire: Wire, lo: Wire) -> Result<(), CompositorError> {
+        for (bit, wire) in (lo..lo + B2A_WIDTH).enumerate() {
+            if !self.boolean.inputs.contains(&wire) {
+                return Err(CompositorError::NotBooleanInput { lo, bit, wire });
+            }
+        }
+        if !self.arithmetic.inputs.contains(&arith_wire)
+            && !self.arithmetic._gate_outputs.contains_key(&arith_wire)
+        {
+            return Err(CompositorError::UndrivenArithmetic { wire: arith_wire });
+        }
+        Ok(())
+    }
+
+    /// Re-checks every connection, in case either circuit changed after it was made (say, by
+    /// challenging a wire that was already connected). Serialization fails if this does.
+    pub fn validate(&self) -> Result<(), CompositorError> {
+        let mut connected: HashSet<Wire> = HashSet::new();
+        for connection in self.connection.iter() {
+            if let CombineOperation::B2A(arith_wire, lo) = connection {
+                self.check_connection(*arith_wire, *lo)?;
+                if !connected.insert(*arith_wire) {
+                    return Err(CompositorError::ConnectedTwice { wire: *arith_wire });
+                }
+            }
+        }
+
+        let mut decomposed: HashSet<Wire> = HashSet::new();
+        for (arith_wire, lo) in self.decomposition.iter() {
+            self.check_decomposition(*arith_wire, *lo)?;
+            for wire in *lo..lo + B2A_WIDTH {
+                if !decomposed.insert(wire) {
+                    return Err(CompositorError::DecomposedTwice { wire });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Add a random challenge gate to the connection circuit. `dst` must be an input of the
+    /// arithmetic circuit.
+    pub fn challenge(&mut self, dst: Wire) -> Result<(), CompositorError> {
+        self.challenge_all(std::iter::once(dst))
+    }
+
+    /// Adds a random challenge gate for each of `dsts`, rebuilding the arithmetic circuit just
+    /// once. Each wire must be an input of the arithmetic circuit, and appear only once. If any
+    /// doesn't, nothing is added.
+    pub fn challenge_all(
+        &mut self,
+        dsts: impl IntoIterator<Item = Wire>,
+    ) -> Result<(), CompositorError> {
+        let dsts: Vec<Wire> = dsts.into_iter().collect();
+        let mut seen: HashSet<Wire> = HashSet::new();
+        for dst in dsts.iter() {
+            if !self.arithmetic.inputs.contains(dst) || !seen.insert(*dst) {
+                return Err(CompositorError::NotChallengeable { wire: *dst });
+            }
+        }
+
+        for dst in dsts {
+            // The challenge drives the wire from now on, so it can't stay an input
+            self.arithmetic.inputs.remove(&dst);
+            self.arithmetic._add_gate(Operation::Random(dst))?;
+        }
+        self.arithmetic._build()?;
+        Ok(())
     }
 
-    /// Add a random challenge gate to the connection circuit.
-    pub fn challenge(&mut self, dst: Wire) {
-        // self.arithmetic.circuit.inputs.remove(&dst);
-        self.arithmetic._add_gate(Operation::Random(dst)).unwrap();
-        self.arithmetic._build().unwrap();
+    /// Computes the complete witness for the composite program. `boolean` holds the values of
+    /// the boolean inputs, in order of ascending wire ID, leaving out those driven by A2B
+    /// decompositions. `challenges` holds the values of the random challenges, in program order.
+    ///
+    /// The boolean circuit is evaluated on `boolean`, each B2A bundle packed into its arithmetic
+    /// wire, and the arithmetic circuit evaluated from there. Decomposed bits are filled in as soon
+    /// as the wire they come from is known. Fails if the result doesn't satisfy every assertion
+    /// in the program.
+    pub fn witness(
+        &self,
+        boolean: &[bool],
+        challenges: &[u64],
+    ) -> Result<CompositeWitness, CompositorError> {
+        self.validate()?;
+        let program: Vec<CombineOperation> = self.operations().collect();
+
+        let decomposed: HashSet<Wire> = self
+            .decomposition
+            .iter()
+            .flat_map(|(_, lo)| *lo..lo + B2A_WIDTH)
+            .collect();
+        let supplied: Vec<Wire> = self
+            .boolean
+            .inputs
+            .iter()
+            .copied()
+            .filter(|wire| !decomposed.contains(wire))
+            .sorted()
+            .collect();
+        if supplied.len() != boolean.len() {
+            return Err(CompositorError::WitnessLength {
+                domain: "boolean",
+                expected: supplied.len(),
+                actual: boolean.len(),
+            });
+        }
+        let randoms: Vec<Wire> = program
+            .iter()
+            .filter_map(|op| match op {
+                CombineOperation::Z64(Operation::Random(wire)) => Some(*wire),
+                _ => None,
+            })
+            .collect();
+        if randoms.len() != challenges.len() {
+            return Err(CompositorError::WitnessLength {
+                domain: "challenge",
+                expected: randoms.len(),
+                actual: challenges.len(),
+            });
+        }
+
+        let mut bool_values: HashMap<Wire, bool> =
+            supplied.into_iter().zip(boolean.iter().copied()).collect();
+        let mut arith_values: HashMap<Wire, u64> = randoms
+            .into_iter()
+            .zip(challenges.iter().copied())
+            .collect();
+
+        // Values can cross between the domains in both directions, so keep sweeping through the
+        // program until everything's been computed
+        let mut pending: Vec<Step> = program
+            .iter()
+            .filter(|op| match op {
+                CombineOperation::GF2(gate) => computed(gate),
+                CombineOperation::Z64(gate) => computed(gate),
+                CombineOperation::B2A(_, _) => true,
+                CombineOperation::SizeHint(_, _) => false,
+            })
+            .map(Step::Gate)
+            .chain(
+                self.decomposition
+                    .iter()
+                    .map(|(arith_wire, lo)| Step::Decompose(*arith_wire, *lo)),
+            )
+            .collect();
+        while !pending.is_empty() {
+            let mut missing: Option<Wire> = None;
+            let mut remaining: Vec<Step> = Vec::new();
+            for step in pending.iter() {
+                let done = match step {
+                    Step::Gate(CombineOperation::GF2(gate)) => {
+                        evaluate(gate, &mut bool_values, &mut missing)?
+                    }
+                    Step::Gate(CombineOperation::Z64(gate)) => {
+                        evaluate(gate, &mut arith_values, &mut missing)?
+                    }
+                    Step::Gate(CombineOperation::B2A(dst, lo)) => {
+                        let bits: Option<Vec<bool>> = (*lo..lo + B2A_WIDTH)
+                            .map(|wire| bool_values.get(&wire).copied())
+                            .collect();
+                        match bits {
+                            Some(bits) => {
+                                let packed = bits
+                                    .iter()
+                                    .enumerate()
+                                    .fold(0u64, |acc, (i, bit)| acc | ((*bit as u64) << i));
+                                arith_values.insert(*dst, packed);
+                                true
+                            }
+                            None => false,
+                        }
+                    }
+                    Step::Gate(_) => true,
+                    Step::Decompose(arith_wire, lo) => match arith_values.get(arith_wire) {
+                        Some(value) => {
+                            for bit in 0..B2A_WIDTH {
+                                bool_values.insert(lo + bit, (value >> bit) & 1 == 1);
+                            }
+                            true
+                        }
+                        None => {
+                            missing = Some(*arith_wire);
+                            false
+                        }
+                    },
+                };
+                if !done {
+                    remaining.push(*step);
+                }
+            }
+            if remaining.len() == pending.len() {
+                let wire = missing.unwrap_or_default();
+                return Err(SVCircuitError::UnassignedWire { wire }.into());
+            }
+            pending = remaining;
+        }
+
+        let mut witness = CompositeWitness::default();
+        for op in program.iter() {
+            match op {
+                CombineOperation::GF2(Operation::Input(wire)) => {
+                    witness.boolean.push(bool_values[wire])
+                }
+                CombineOperation::Z64(Operation::Input(wire))
+                | CombineOperation::Z64(Operation::Random(wire)) => {
+                    witness.arithmetic.push(arith_values[wire])
+                }
+                _ => {}
+            }
+        }
+
+        let outputs = evaluate_program(&program, &witness.boolean, &witness.arithmetic)?;
+        let failed = outputs.boolean.iter().filter(|v| **v).count()
+            + outputs.arithmetic.iter().filter(|v| **v != 0).count();
+        if failed > 0 {
+            return Err(CompositorError::UnsatisfiedWitness { failed });
+        }
+        Ok(witness)
     }
 
     pub fn gate_stats(&self) -> (usize, usize, usize, usize, usize) {
         (
             self.boolean.ngate(),
             self.boolean.nwire(),
-            self.connection.len(),
+            self.connection.len() + self.decomposition.len(),
             self.arithmetic.ngate(),
             self.arithmetic.nwire(),
         )
     }
+
+    /// The whole composite program, in the order it's serialized: inputs and gates of the boolean
+    /// circuit, then the connections, then the gates and assertions of the arithmetic circuit.
+    /// A2B decompositions are lowered to B2A gates and assertions on wires past the end of the
+    /// arithmetic circuit.
+    pub fn operations(&self) -> impl Iterator<Item = CombineOperation> + '_ {
+        // Each decomposition gets a wire for the repacked value and one for its difference from
+        // the original
+        let first = self.arithmetic.nwire() + 1;
+        let packed = move |k: usize| first + 2 * k;
+        let difference = move |k: usize| first + 2 * k + 1;
+
+        // Size Hint - helps Reverie know how much memory to allocate
+        std::iter::once(CombineOperation::SizeHint(
+            packed(self.decomposition.len()),
+            self.boolean.nwire() + 1,
+        ))
+        .chain(
+            // Boolean Circuit Inputs
+            self.boolean
+                .inputs
+                .iter()
+                .sorted()
+                .map(|i| CombineOperation::GF2(Operation::Input(*i))),
+        )
+        // Boolean Circuit Gates
+        .chain(self.boolean.topo_iter().map(|g| CombineOperation::GF2(*g)))
+        // Connection Circuit
+        .chain(self.connection.iter().cloned())
+        .chain(
+            self.decomposition
+                .iter()
+                .enumerate()
+                .map(move |(k, (_, lo))| CombineOperation::B2A(packed(k), *lo)),
+        )
+        // Arithmetic Circuit Gates
+        .chain(
+            self.arithmetic
+                .topo_iter()
+                .map(|g| CombineOperation::Z64(*g)),
+        )
+        .chain(
+            self.decomposition
+                .iter()
+                .enumerate()
+                .map(move |(k, (arith_wire, _))| {
+                    CombineOperation::Z64(Operation::Sub(difference(k), packed(k), *arith_wire))
+                }),
+        )
+        // Arithmetic Circuit Outputs
+        .chain(
+            self.arithmetic
+                .outputs
+                .iter()
+                .sorted()
+                .map(|o| CombineOperation::Z64(Operation::AssertZero(*o))),
+        )
+        .chain(
+            (0..self.decomposition.len())
+                .map(move |k| CombineOperation::Z64(Operation::AssertZero(difference(k)))),
+        )
+    }
+}
+
+/// The sections of a composite program, in the order they're serialized
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Section {
+    SizeHint,
+    BooleanInputs,
+    BooleanGates,
+    Connections,
+    ArithmeticGates,
+    Assertions,
 }
 
+impl Section {
+    fn of(op: &CombineOperation) -> Option<Section> {
+        match op {
+            CombineOperation::SizeHint(_, _) => Some(Section::SizeHint),
+            CombineOperation::GF2(Operation::Input(_)) => Some(Section::BooleanInputs),
+            CombineOperation::GF2(Operation::Random(_) | Operation::AssertZero(_)) => None,
+            CombineOperation::GF2(_) => Some(Section::BooleanGates),
+            CombineOperation::B2A(_, _) => Some(Section::Connections),
+            CombineOperation::Z64(Operation::Input(_)) => None,
+            CombineOperation::Z64(Operation::AssertZero(_)) => Some(Section::Assertions),
+            CombineOperation::Z64(_) => Some(Section::ArithmeticGates),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Section::SizeHint => "size hint",
+            Section::BooleanInputs => "boolean input",
+            Section::BooleanGates => "boolean gate",
+            Section::Connections => "connection",
+            Section::ArithmeticGates => "arithmetic gate",
+            Section::Assertions => "assertion",
+        }
+    }
+}
+
+impl CircuitCompositor {
+    /// Reads a composite program stored in a container by `write`
+    pub fn from_reader<R: Read>(reader: R) -> Result<Self, CompositorError> {
+        let (_, program) = container::read(reader)?;
+        Self::try_from(program)
+    }
+
+    /// Validates the compositor, then stores its program in a container, optionally with a
+    /// checksum
+    pub fn write<W: Write>(&self, writer: W, checksum: bool) -> Result<(), CompositorError> {
+        self.validate()?;
+        let program: Vec<CombineOperation> = self.operations().collect();
+        container::write(writer, &program, checksum)?;
+        Ok(())
+    }
+}
+
+/// Rebuilds a compositor out of a composite program, checking that its sections come in the
+/// order `operations` produces them. Lowered A2B decompositions come back as the B2A gates and
+/// assertions they were lowered to.
+impl TryFrom<Vec<CombineOperation>> for CircuitCompositor {
+    type Error = CompositorError;
+
+    fn try_from(program: Vec<CombineOperation>) -> Result<Self, Self::Error> {
+        let mut boolean = BoolCircuit {
+            name: "boolean".to_string(),
+            ..Default::default()
+        };
+        let mut arithmetic = ArithCircuit {
+            name: "arithmetic".to_string(),
+            ..Default::default()
+        };
+        let mut connection: Vec<CombineOperation> = Vec::new();
+
+        let mut current = Section::SizeHint;
+        for (index, op) in program.into_iter().enumerate() {
+            let malformed = |reason: String| CompositorError::MalformedProgram { index, reason };
+            let section =
+                Section::of(&op).ok_or_else(|| malformed(format!("({:?}) isn't supported", op)))?;
+            if section < current || (section == Section::SizeHint && index > 0) {
+                return Err(malformed(format!(
+                    "({:?}) comes after the {} section",
+                    op,
+                    current.name()
+                )));
+            }
+            current = section;
+
+            match op {
+                CombineOperation::SizeHint(_, _) => {}
+                CombineOperation::GF2(Operation::Input(wire)) => {
+                    if !boolean.inputs.insert(wire) {
+                        return Err(malformed(format!("declares input {} twice", wire)));
+                    }
+                }
+                CombineOperation::GF2(gate) => {
+                    boolean._add_gate(gate)?;
+                }
+                CombineOperation::B2A(_, _) => connection.push(op),
+                CombineOperation::Z64(Operation::AssertZero(wire)) => {
+                    arithmetic.outputs.insert(wire);
+                }
+                CombineOperation::Z64(gate) => {
+                    arithmetic._add_gate(gate)?;
+                }
+            }
+        }
+
+        // Arithmetic wires that are read without being driven are inputs, which is where B2A
+        // gates write to
+        let undriven: Vec<Wire> = arithmetic
+            .graph
+            .node_weights()
+            .flat_map(|gate| gate.inputs().collect::<Vec<Wire>>())
+            .chain(arithmetic.outputs.iter().copied())
+            .chain(connection.iter().filter_map(|op| match op {
+                CombineOperation::B2A(dst, _) => Some(*dst),
+                _ => None,
+            }))
+            .filter(|wire| !arithmetic._gate_outputs.contains_key(wire))
+            .collect();
+        arithmetic.inputs.extend(undriven);
+
+        boolean._build()?;
+        arithmetic._build()?;
+
+        let compositor = CircuitCompositor {
+            boolean,
+            arithmetic,
+            connection,
+            decomposition: vec![],
+        };
+        compositor.validate()?;
+        Ok(compositor)
+    }
+}
+
+/// Deserializes a container written by `Serialize`, as a byte string
+impl<'de> Deserialize<'de> for CircuitCompositor {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let bytes = Vec::<u8>::deserialize(deserializer)?;
+        Self::from_reader(bytes.as_slice()).map_err(D::Error::custom)
+    }
+}
+
+/// Serializes the container that `write` produces, without a checksum, as a byte string
 impl Serialize for CircuitCompositor {
     fn serialize<S>(&self, serializer: S) -> Result<<S as Serializer>::Ok, <S as Serializer>::Error>
     where
         S: Serializer,
     {
-        serializer.collect_seq(
-            // Size Hint - helps Reverie know how much memory to allocate
-            std::iter::once(CombineOperation::SizeHint(
-                self.arithmetic.nwire() + 1,
-                self.boolean.nwire() + 1,
-            ))
-            .chain(
-                // Boolean Circuit Inputs
-                self.boolean
-                    .inputs
-                    .iter()
-                    .sorted()
-                    .map(|i| CombineOperation::GF2(Operation::Input(*i))),
-            )
-            // Boolean Circuit Gates
-            .chain(self.boolean.topo_iter().map(|g| CombineOperation::GF2(*g)))
-            // Connection Circuit
-            .chain(self.connection.iter().cloned())
-            // Arithmetic Circuit Gates
-            .chain(
-                self.arithmetic
-                    .topo_iter()
-                    .map(|g| CombineOperation::Z64(*g)),
-            )
-            // Arithmetic Circuit Outputs
-            .chain(
-                self.arithmetic
-                    .outputs
-                    .iter()
-                    .sorted()
-                    .map(|o| CombineOperation::Z64(Operation::AssertZero(*o))),
-            ),
-        )
+        let mut bytes: Vec<u8> = Vec::new();
+        self.write(&mut bytes, false).map_err(S::Error::custom)?;
+        serializer.serialize_bytes(&bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compositor::{CompositeWitness, CompositorError, B2A_WIDTH};
+    use crate::connection::{Bundle, ConnectionSpec};
+    use crate::{evaluate_program, ArithCircuit, BoolCircuit, CircuitCompositor, Direction, Port};
+    use mcircuit::{CombineOperation, Operation};
+    use std::collections::HashSet;
+    use std::convert::TryFrom;
+    use std::iter::FromIterator;
+
+    /// A boolean circuit whose output port `bits` is its input port, most significant bit on the
+    /// lowest wire
+    fn reversed() -> BoolCircuit {
+        let wires: Vec<usize> = (2..2 + B2A_WIDTH).rev().collect();
+        BoolCircuit {
+            name: "reversed".to_string(),
+            inputs: HashSet::from_iter(wires.iter().copied()),
+            outputs: HashSet::from_iter(wires.iter().copied()),
+            ports: vec![
+                Port {
+                    name: "in".to_string(),
+                    direction: Direction::Input,
+                    wires: wires.clone(),
+                },
+                Port {
+                    name: "bits".to_string(),
+                    direction: Direction::Output,
+                    wires,
+                },
+            ],
+            ..Default::default()
+        }
+    }
+
+    fn arithmetic(width: usize) -> ArithCircuit {
+        let wires: Vec<usize> = (0..width).collect();
+        ArithCircuit {
+            name: "arithmetic".to_string(),
+            inputs: HashSet::from_iter(wires.iter().copied()),
+            ports: vec![Port {
+                name: "x".to_string(),
+                direction: Direction::Input,
+                wires,
+            }],
+            ..Default::default()
+        }
+    }
+
+    fn spec() -> ConnectionSpec {
+        ConnectionSpec {
+            bundles: vec![Bundle {
+                boolean: "bits".to_string(),
+                arithmetic: "x".to_string(),
+            }],
+            decompositions: vec![],
+            challenges: vec![],
+        }
+    }
+
+    #[test]
+    fn test_connect_noncontiguous() -> Result<(), CompositorError> {
+        let compositor = CircuitCompositor::with_connections(reversed(), arithmetic(1), &spec())?;
+
+        // The bits get copied onto consecutive wires, least significant first
+        let operations: Vec<CombineOperation> = compositor.operations().collect();
+        let lo = operations
+            .iter()
+            .find_map(|op| match op {
+                CombineOperation::B2A(0, lo) => Some(*lo),
+                _ => None,
+            })
+            .expect("Missing B2A gate");
+        for offset in 0..B2A_WIDTH {
+            let copy = CombineOperation::GF2(Operation::AddConst(
+                lo + offset,
+                1 + B2A_WIDTH - offset,
+                false,
+            ));
+            assert!(operations.contains(&copy), "missing {:?}", copy);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_connect_width_mismatch() {
+        let result = CircuitCompositor::with_connections(reversed(), arithmetic(2), &spec());
+        assert!(matches!(
+            result,
+            Err(CompositorError::WidthMismatch {
+                width: 64,
+                expected: 128,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_connect_checks() -> Result<(), CompositorError> {
+        let mut compositor = CircuitCompositor::new(reversed(), arithmetic(2));
+
+        // Wires 2..66 are the boolean inputs
+        assert!(matches!(
+            compositor.connect(0, 3),
+            Err(CompositorError::UndrivenBundle {
+                lo: 3,
+                bit: 63,
+                wire: 66
+            })
+        ));
+        assert!(matches!(
+            compositor.connect(5, 2),
+            Err(CompositorError::NotArithmeticInput { wire: 5 })
+        ));
+        compositor.connect(0, 2).expect("Failed to connect");
+        assert!(matches!(
+            compositor.connect(0, 2),
+            Err(CompositorError::ConnectedTwice { wire: 0 })
+        ));
+        assert!(compositor.validate().is_ok());
+
+        // Challenging a connected wire means it's no longer an input for the B2A gate to drive
+        compositor.challenge(0)?;
+        assert!(matches!(
+            compositor.validate(),
+            Err(CompositorError::NotArithmeticInput { wire: 0 })
+        ));
+        assert!(compositor.write(Vec::new(), false).is_err());
+        assert!(bincode::serialize(&compositor).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_decompose() -> Result<(), CompositorError> {
+        // The arithmetic circuit squares a random challenge
+        let mut squares = arithmetic(1);
+        squares._add_gate(Operation::Mul(1, 0, 0))?;
+        squares._build()?;
+        let mut compositor = CircuitCompositor::new(reversed(), squares);
+        compositor.challenge(0)?;
+
+        assert!(matches!(
+            compositor.decompose(1, 3),
+            Err(CompositorError::NotBooleanInput { wire: 66, .. })
+        ));
+        assert!(matches!(
+            compositor.decompose(7, 2),
+            Err(CompositorError::UndrivenArithmetic { wire: 7 })
+        ));
+        compositor.decompose(1, 2)?;
+        assert!(matches!(
+            compositor.decompose(0, 2),
+            Err(CompositorError::DecomposedTwice { wire: 2 })
+        ));
+
+        // The gadget only holds when the prover supplies the right bits
+        let program: Vec<CombineOperation> = compositor.operations().collect();
+        let (largest, _) = mcircuit::largest_wires(&program);
+        assert!(matches!(program[0], CombineOperation::SizeHint(arith, _) if arith == largest + 1));
+        let bits = |value: u64| -> Vec<bool> { (0..64).map(|i| (value >> i) & 1 == 1).collect() };
+        let outputs = evaluate_program(&program, &bits(9), &[3])?;
+        assert_eq!(outputs.arithmetic, vec![0]);
+        let outputs = evaluate_program(&program, &bits(10), &[3])?;
+        assert_ne!(outputs.arithmetic, vec![0]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_challenge_all() -> Result<(), CompositorError> {
+        let mut compositor = CircuitCompositor::new(reversed(), arithmetic(1000));
+
+        // Nothing is challenged if any of the wires can't be
+        for bad in [vec![0, 1, 1], vec![0, 1000]] {
+            let wire = *bad.last().unwrap();
+            assert!(matches!(
+                compositor.challenge_all(bad),
+                Err(CompositorError::NotChallengeable { wire: w }) if w == wire
+            ));
+        }
+        assert_eq!(compositor.gate_stats().3, 0);
+
+        compositor.challenge_all(0..1000)?;
+        assert_eq!(compositor.gate_stats().3, 1000);
+        assert!(compositor.arithmetic.inputs.is_empty());
+        assert!(matches!(
+            compositor.challenge(0),
+            Err(CompositorError::NotChallengeable { wire: 0 })
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_witness() -> Result<(), CompositorError> {
+        // x = bits, y = x * r for a challenge r, and y is decomposed back into boolean inputs
+        // 100..164, which the boolean circuit reads
+        let mut boolean = reversed();
+        boolean.inputs.extend(100..100 + B2A_WIDTH);
+        boolean._add_gate(Operation::Add(200, 100, 2 + B2A_WIDTH - 1))?;
+        boolean._build()?;
+        let mut products = arithmetic(2);
+        products._add_gate(Operation::Mul(2, 0, 1))?;
+        products._build()?;
+
+        let mut compositor = CircuitCompositor::new(boolean, products);
+        compositor.connect(0, 2)?;
+        compositor.challenge(1)?;
+        compositor.decompose(2, 100)?;
+
+        // Connected directly rather than through port `bits`, so the lowest wire is bit 0
+        let x: u64 = 0x0123_4567_89ab_cdef;
+        let supplied: Vec<bool> = (0..64).map(|i| (x >> i) & 1 == 1).collect();
+        let witness = compositor.witness(&supplied, &[5])?;
+
+        let y = x.wrapping_mul(5);
+        let mut boolean = supplied.clone();
+        boolean.extend((0..64).map(|i| (y >> i) & 1 == 1));
+        assert_eq!(
+            witness,
+            CompositeWitness {
+                boolean,
+                arithmetic: vec![5]
+            }
+        );
+
+        let mut written: Vec<u8> = Vec::new();
+        witness.write(&mut written)?;
+        assert_eq!(CompositeWitness::read(written.as_slice())?, witness);
+
+        assert!(matches!(
+            compositor.witness(&supplied[1..], &[5]),
+            Err(CompositorError::WitnessLength {
+                domain: "boolean",
+                expected: 64,
+                actual: 63
+            })
+        ));
+        assert!(matches!(
+            compositor.witness(&supplied, &[]),
+            Err(CompositorError::WitnessLength {
+                domain: "challenge",
+                ..
+            })
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_roundtrip() -> Result<(), CompositorError> {
+        let mut boolean = reversed();
+        boolean._add_gate(Operation::Mul(100, 2, 3))?;
+        boolean._build()?;
+        let mut products = arithmetic(2);
+        products._add_gate(Operation::Mul(2, 0, 1))?;
+        products.outputs.insert(2);
+        products._build()?;
+        let mut compositor = CircuitCompositor::new(boolean, products);
+        compositor.connect(0, 2)?;
+        compositor.challenge(1)?;
+
+        let mut written = Vec::new();
+        compositor.write(&mut written, true)?;
+        let read_back = CircuitCompositor::from_reader(written.as_slice())?;
+        let program: Vec<CombineOperation> = compositor.operations().collect();
+        assert_eq!(read_back.operations().collect::<Vec<_>>(), program);
+        assert_eq!(read_back.gate_stats(), compositor.gate_stats());
+
+        // Serde goes through the same container
+        let mut unchecked = Vec::new();
+        compositor.write(&mut unchecked, false)?;
+        let serialized = bincode::serialize(&compositor)?;
+        assert_eq!(bincode::deserialize::<Vec<u8>>(&serialized)?, unchecked);
+        let deserialized: CircuitCompositor = bincode::deserialize(&serialized)?;
+        assert_eq!(deserialized.operations().collect::<Vec<_>>(), program);
+
+        // Sections out of order: an assertion before the boolean inputs
+        let mut swapped = program.clone();
+        let last = swapped.len() - 1;
+        swapped.swap(1, last);
+        assert!(matches!(
+            CircuitCompositor::try_from(swapped),
+            Err(CompositorError::MalformedProgram { index: 2, .. })
+        ));
+        let mut hints = program.clone();
+        hints.push(CombineOperation::SizeHint(1, 1));
+        assert!(matches!(
+            CircuitCompositor::try_from(hints),
+            Err(CompositorError::MalformedProgram { .. })
+        ));
+
+        // Connections are validated just like when they're made
+        let mut undriven = program;
+        undriven.retain(|op| !matches!(op, CombineOperation::GF2(Operation::Input(65))));
+        assert!(matches!(
+            CircuitCompositor::try_from(undriven),
+            Err(CompositorError::UndrivenBundle { wire: 65, .. })
+        ));
+
+        Ok(())
     }
 }
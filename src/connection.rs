@@ -0,0 +1,47 @@
+//! Declarative description of how a boolean and an arithmetic circuit are glued together, as read
+//! by `sv-composite`. Ports are referred to by name, so the spec survives re-synthesis of either
+//! circuit. For example:
+//!
+//! ```json
+//! {
+//!     "bundles": [{ "boolean": "sum", "arithmetic": "x" }],
+//!     "decompositions": [{ "arithmetic": "y", "boolean": "y_bits" }],
+//!     "challenges": ["r"]
+//! }
+//! ```
+
+use std::io::Read;
+
+use serde::Deserialize;
+
+use crate::CompositorError;
+
+/// Pairs a boolean port with an arithmetic port. Each wire of the arithmetic port corresponds to
+/// the next 64 bits of the boolean port, least significant bit first.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct Bundle {
+    pub boolean: String,
+    pub arithmetic: String,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct ConnectionSpec {
+    /// Boolean ports converted into arithmetic input ports (B2A)
+    #[serde(default)]
+    pub bundles: Vec<Bundle>,
+    /// Arithmetic ports decomposed into boolean input ports (A2B)
+    #[serde(default)]
+    pub decompositions: Vec<Bundle>,
+    /// Arithmetic input ports that receive random challenges rather than values
+    #[serde(default)]
+    pub challenges: Vec<String>,
+}
+
+impl ConnectionSpec {
+    /// Reads a spec written as JSON
+    pub fn from_reader<R: Read>(reader: R) -> Result<Self, CompositorError> {
+        Ok(serde_json::from_reader(reader)?)
+    }
+}
@@ -0,0 +1,424 @@
+//! Self-describing file format for composite programs. A container starts with `MAGIC` and the
+//! format version, followed by a bincode-encoded `Header`, so a file from an incompatible writer is
+//! rejected up front rather than failing halfway through decoding.
+//!
+//! The operations follow the header in chunks, each written as its length in bytes and a
+//! bincode-encoded `Vec<CombineOperation>`, with a zero length marking the last one. An index of
+//! chunk offsets and the offset of that index close the container, which lets passes that need to
+//! walk the program backwards (like dead code elimination) read the chunks in reverse. See
+//! `stream` for reading and writing a container a chunk at a time.
+
+use std::hash::Hasher;
+use std::io::{self, Read, Write};
+
+use fnv::FnvHasher;
+use mcircuit::{largest_wires, CombineOperation};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::stream::StreamReader;
+
+/// The first bytes of every container
+pub const MAGIC: [u8; 4] = *b"SVCP";
+
+/// Bumped whenever the header or the encoding of the operations changes
+pub const FORMAT_VERSION: u32 = 2;
+
+/// Recorded in the header of every container this build writes
+pub const GENERATOR: &str = concat!("sv_circuit ", env!("CARGO_PKG_VERSION"));
+
+/// Number of operations per chunk, unless the writer asks for something else
+pub const DEFAULT_CHUNK_SIZE: usize = 1 << 16;
+
+/// Bytes taken up by the magic number and the format version
+pub(crate) const PREAMBLE_LEN: u64 = 8;
+
+#[derive(Error, Debug)]
+pub enum ContainerError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("Not a composite program container (starts with {found:02x?})")]
+    BadMagic { found: [u8; 4] },
+
+    #[error(
+        "Container format version {found} isn't supported (this build reads version {supported})"
+    )]
+    UnsupportedVersion { found: u32, supported: u32 },
+
+    #[error("Malformed container header: {0}")]
+    Header(#[source] bincode::Error),
+
+    #[error("Couldn't decode the chunk at byte {offset}, written by {generator}: {source}")]
+    Operations {
+        generator: String,
+        offset: u64,
+        #[source]
+        source: bincode::Error,
+    },
+
+    #[error("Malformed chunk index: {0}")]
+    Index(#[source] bincode::Error),
+
+    #[error("The header records {expected} {what}, but the container holds {actual}")]
+    CountMismatch {
+        what: &'static str,
+        expected: usize,
+        actual: usize,
+    },
+
+    #[error("Checksum mismatch: the header records {expected:#018x}, but the operations hash to {actual:#018x}")]
+    Checksum { expected: u64, actual: u64 },
+
+    #[error("Serialization error: {0}")]
+    Bincode(#[from] bincode::Error),
+}
+
+/// Number of operations of each kind in a program
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GateCounts {
+    /// GF2 operations, including inputs and assertions
+    pub boolean: usize,
+    /// Z64 operations, including inputs and assertions
+    pub arithmetic: usize,
+    /// B2A operations
+    pub connections: usize,
+    /// Every operation, size hints included
+    pub total: usize,
+}
+
+impl GateCounts {
+    pub fn of(operations: &[CombineOperation]) -> GateCounts {
+        let mut counts = GateCounts {
+            total: operations.len(),
+            ..Default::default()
+        };
+        for op in operations {
+            match op {
+                CombineOperation::GF2(_) => counts.boolean += 1,
+                CombineOperation::Z64(_) => counts.arithmetic += 1,
+                CombineOperation::B2A(_, _) => counts.connections += 1,
+                CombineOperation::SizeHint(_, _) => {}
+            }
+        }
+        counts
+    }
+}
+
+/// Describes the program stored in a container
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Header {
+    /// Name and version of the program that wrote the container
+    pub generator: String,
+    /// One above the largest boolean wire
+    pub boolean_wires: usize,
+    /// One above the largest arithmetic wire
+    pub arithmetic_wires: usize,
+    pub gates: GateCounts,
+    /// FNV-1a hash of the encoded chunks, if the writer computed one
+    pub checksum: Option<u64>,
+}
+
+impl Header {
+    /// The header this build writes for `operations`, without a checksum
+    pub fn describe(operations: &[CombineOperation]) -> Header {
+        let mut summary = Summary::default();
+        summary.add(operations);
+        summary.header(false)
+    }
+
+    /// Checks that the operations summarized in `actual` are the ones this header describes
+    pub(crate) fn verify(&self, actual: &Header) -> Result<(), ContainerError> {
+        if let (Some(expected), Some(actual)) = (self.checksum, actual.checksum) {
+            if actual != expected {
+                return Err(ContainerError::Checksum { expected, actual });
+            }
+        }
+
+        let counts = [
+            ("operations", self.gates.total, actual.gates.total),
+            (
+                "boolean operations",
+                self.gates.boolean,
+                actual.gates.boolean,
+            ),
+            (
+                "arithmetic operations",
+                self.gates.arithmetic,
+                actual.gates.arithmetic,
+            ),
+            (
+                "connections",
+                self.gates.connections,
+                actual.gates.connections,
+            ),
+            ("boolean wires", self.boolean_wires, actual.boolean_wires),
+            (
+                "arithmetic wires",
+                self.arithmetic_wires,
+                actual.arithmetic_wires,
+            ),
+        ];
+        match counts
+            .iter()
+            .find(|(_, expected, actual)| expected != actual)
+        {
+            Some(&(what, expected, actual)) => Err(ContainerError::CountMismatch {
+                what,
+                expected,
+                actual,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Size of the encoded header. Only the generator and whether there's a checksum affect it.
+    pub(crate) fn encoded_len(&self) -> Result<u64, ContainerError> {
+        Ok(bincode::serialized_size(self)?)
+    }
+}
+
+/// Builds up the header for a program one chunk at a time, as it's written or read
+#[derive(Default)]
+pub(crate) struct Summary {
+    gates: GateCounts,
+    largest_arith: usize,
+    largest_bool: usize,
+    hasher: FnvHasher,
+}
+
+impl Summary {
+    pub fn add(&mut self, chunk: &[CombineOperation]) {
+        let counts = GateCounts::of(chunk);
+        self.gates.boolean += counts.boolean;
+        self.gates.arithmetic += counts.arithmetic;
+        self.gates.connections += counts.connections;
+        self.gates.total += counts.total;
+        let (arith, bool) = largest_wires(chunk);
+        self.largest_arith = self.largest_arith.max(arith);
+        self.largest_bool = self.largest_bool.max(bool);
+    }
+
+    /// Adds the encoding of a chunk to the checksum
+    pub fn hash(&mut self, encoded: &[u8]) {
+        self.hasher.write(encoded);
+    }
+
+    /// The header for everything added so far, with a checksum if `with_checksum` is set
+    pub fn header(&self, with_checksum: bool) -> Header {
+        Header {
+            generator: GENERATOR.to_string(),
+            boolean_wires: self.largest_bool + 1,
+            arithmetic_wires: self.largest_arith + 1,
+            gates: self.gates,
+            checksum: if with_checksum {
+                Some(self.hasher.finish())
+            } else {
+                None
+            },
+        }
+    }
+}
+
+/// Writes the magic number, the format version and `header`
+pub(crate) fn write_header<W: Write>(
+    writer: &mut W,
+    header: &Header,
+) -> Result<(), ContainerError> {
+    writer.write_all(&MAGIC)?;
+    writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+    bincode::serialize_into(writer, header)?;
+    Ok(())
+}
+
+/// Keeps track of where each chunk starts, for the index at the end of the container
+pub(crate) struct Chunks {
+    offsets: Vec<u64>,
+    position: u64,
+}
+
+impl Chunks {
+    /// Starts the chunks at `position`, just past the header
+    pub fn new(position: u64) -> Self {
+        Chunks {
+            offsets: Vec::new(),
+            position,
+        }
+    }
+
+    pub fn write<W: Write>(&mut self, writer: &mut W, encoded: &[u8]) -> io::Result<()> {
+        writer.write_all(&(encoded.len() as u64).to_le_bytes())?;
+        writer.write_all(encoded)?;
+        self.offsets.push(self.position);
+        self.position += 8 + encoded.len() as u64;
+        Ok(())
+    }
+
+    /// Writes the terminator, the index and the index's offset
+    pub fn finish<W: Write>(self, writer: &mut W) -> Result<(), ContainerError> {
+        writer.write_all(&0u64.to_le_bytes())?;
+        let index_offset = self.position + 8;
+        bincode::serialize_into(&mut *writer, &self.offsets)?;
+        writer.write_all(&index_offset.to_le_bytes())?;
+        Ok(())
+    }
+}
+
+/// Writes `operations` as a container, returning the header it was written with. Use
+/// `stream::StreamWriter` for programs too large to hold in memory.
+pub fn write<W: Write>(
+    mut writer: W,
+    operations: &[CombineOperation],
+    with_checksum: bool,
+) -> Result<Header, ContainerError> {
+    let mut summary = Summary::default();
+    let mut encoded = Vec::new();
+    for chunk in operations.chunks(DEFAULT_CHUNK_SIZE) {
+        let bytes = bincode::serialize(chunk)?;
+        summary.add(chunk);
+        summary.hash(&bytes);
+        encoded.push(bytes);
+    }
+    let header = summary.header(with_checksum);
+
+    write_header(&mut writer, &header)?;
+    let mut chunks = Chunks::new(PREAMBLE_LEN + header.encoded_len()?);
+    for bytes in encoded.iter() {
+        chunks.write(&mut writer, bytes)?;
+    }
+    chunks.finish(&mut writer)?;
+    writer.flush()?;
+    Ok(header)
+}
+
+/// Reads just the header, leaving `reader` at the start of the first chunk
+pub fn read_header<R: Read>(reader: &mut R) -> Result<Header, ContainerError> {
+    let mut found = [0; 4];
+    reader.read_exact(&mut found)?;
+    if found != MAGIC {
+        return Err(ContainerError::BadMagic { found });
+    }
+    let mut version = [0; 4];
+    reader.read_exact(&mut version)?;
+    let version = u32::from_le_bytes(version);
+    if version != FORMAT_VERSION {
+        return Err(ContainerError::UnsupportedVersion {
+            found: version,
+            supported: FORMAT_VERSION,
+        });
+    }
+    bincode::deserialize_from(reader).map_err(ContainerError::Header)
+}
+
+/// Reads a container, checking the operations against the checksum and counts in its header
+pub fn read<R: Read>(reader: R) -> Result<(Header, Vec<CombineOperation>), ContainerError> {
+    let mut stream = StreamReader::new(reader)?;
+    let operations = stream.by_ref().collect::<Result<_, _>>()?;
+    Ok((stream.header().clone(), operations))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::container::*;
+    use mcircuit::Operation;
+    use std::convert::TryInto;
+
+    fn program() -> Vec<CombineOperation> {
+        vec![
+            CombineOperation::SizeHint(3, 66),
+            CombineOperation::GF2(Operation::Input(0)),
+            CombineOperation::GF2(Operation::Input(1)),
+            CombineOperation::GF2(Operation::Add(2, 0, 1)),
+            CombineOperation::B2A(1, 2),
+            CombineOperation::Z64(Operation::Random(0)),
+            CombineOperation::Z64(Operation::Mul(2, 0, 1)),
+            CombineOperation::Z64(Operation::AssertZero(2)),
+        ]
+    }
+
+    #[test]
+    fn test_roundtrip() -> Result<(), ContainerError> {
+        for with_checksum in [false, true] {
+            let mut written = Vec::new();
+            let header = write(&mut written, &program(), with_checksum)?;
+            assert_eq!(header.checksum.is_some(), with_checksum);
+            assert_eq!(
+                header.gates,
+                GateCounts {
+                    boolean: 3,
+                    arithmetic: 3,
+                    connections: 1,
+                    total: 8
+                }
+            );
+            assert_eq!((header.boolean_wires, header.arithmetic_wires), (66, 3));
+            assert_eq!(
+                header,
+                Header {
+                    checksum: header.checksum,
+                    ..Header::describe(&program())
+                }
+            );
+
+            let (read_back, operations) = read(written.as_slice())?;
+            assert_eq!(read_back, header);
+            assert_eq!(operations, program());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_rejects() -> Result<(), ContainerError> {
+        let mut written = Vec::new();
+        let header = write(&mut written, &program(), true)?;
+        let chunks = (PREAMBLE_LEN + header.encoded_len()?) as usize;
+
+        // A bare sequence of operations, as written before there was a container
+        let bare = bincode::serialize(&program())?;
+        assert!(matches!(
+            read(bare.as_slice()),
+            Err(ContainerError::BadMagic { .. })
+        ));
+
+        let mut future = written.clone();
+        future[MAGIC.len()..MAGIC.len() + 4].copy_from_slice(&3u32.to_le_bytes());
+        assert!(matches!(
+            read(future.as_slice()),
+            Err(ContainerError::UnsupportedVersion {
+                found: 3,
+                supported: FORMAT_VERSION
+            })
+        ));
+
+        // The last byte of the only chunk is the top of a wire ID, so it still decodes
+        let len = u64::from_le_bytes(written[chunks..chunks + 8].try_into().unwrap()) as usize;
+        let mut corrupted = written.clone();
+        corrupted[chunks + 8 + len - 1] ^= 1;
+        assert!(matches!(
+            read(corrupted.as_slice()),
+            Err(ContainerError::Checksum { .. })
+        ));
+
+        // Without a checksum, a header that disagrees with the operations is still caught. The
+        // header is the same size, so the chunks and their index can stay where they are.
+        let mut unchecked = Vec::new();
+        write(&mut unchecked, &program(), false)?;
+        let mut header = Header::describe(&program());
+        header.gates.connections = 2;
+        let mut mismatched = Vec::new();
+        write_header(&mut mismatched, &header)?;
+        mismatched.extend_from_slice(&unchecked[mismatched.len()..]);
+        assert!(matches!(
+            read(mismatched.as_slice()),
+            Err(ContainerError::CountMismatch {
+                what: "connections",
+                expected: 2,
+                actual: 1
+            })
+        ));
+
+        let truncated = &written[..written.len() - 3];
+        assert!(matches!(read(truncated), Err(ContainerError::Io(_))));
+        Ok(())
+    }
+}
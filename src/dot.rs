@@ -0,0 +1,310 @@
+//! Renders circuits as [Graphviz](https://graphviz.org/) graphs, for eyeballing the logic behind a
+//! particular output.
+//!
+//! Gates are labelled with their operation and the (local) name of the wire they drive. Inputs
+//! and outputs are drawn as coloured boxes, and wires that cross the edge of a partial view as
+//! dashed ones.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+use mcircuit::{Gate, HasIO, Identity, Operation};
+use petgraph::graph::NodeIndex;
+
+use crate::generic::circuit::GenericCircuit;
+use crate::generic::Wire;
+use crate::Evaluable;
+
+#[derive(Clone, Debug, Default)]
+pub struct DotOptions {
+    /// Group gates into (nested) clusters by the subcircuit instance they came from
+    pub cluster: bool,
+    /// Only draw the logic that drives these wires. Draws the whole circuit if empty.
+    pub cone: Vec<Wire>,
+    /// Only draw gates at most this many levels back from the outputs (or from `cone`)
+    pub depth: Option<usize>,
+}
+
+/// Escapes a string for use inside a quoted DOT ID
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Operation performed by a gate. Boolean circuits get the names of the logic gates they amount to.
+fn operation<T: Evaluable>(gate: &Operation<T>) -> String {
+    let boolean = T::BITS == 1;
+    match gate {
+        Operation::Input(_) => "INPUT".to_string(),
+        Operation::Random(_) => "RANDOM".to_string(),
+        Operation::Add(_, _, _) if boolean => "XOR".to_string(),
+        Operation::Add(_, _, _) => "ADD".to_string(),
+        Operation::Sub(_, _, _) if boolean => "XOR".to_string(),
+        Operation::Sub(_, _, _) => "SUB".to_string(),
+        Operation::Mul(_, _, _) if boolean => "AND".to_string(),
+        Operation::Mul(_, _, _) => "MUL".to_string(),
+        Operation::AddConst(_, _, c) | Operation::SubConst(_, _, c) if boolean => {
+            if *c == T::zero() { "BUF" } else { "NOT" }.to_string()
+        }
+        Operation::AddConst(_, _, c) => format!("ADD {:?}", c),
+        Operation::SubConst(_, _, c) => format!("SUB {:?}", c),
+        Operation::MulConst(_, _, c) if boolean => {
+            if *c == T::zero() { "ZERO" } else { "BUF" }.to_string()
+        }
+        Operation::MulConst(_, _, c) => format!("MUL {:?}", c),
+        Operation::Const(_, c) => format!("CONST {:?}", c),
+        Operation::AssertZero(_) => "ASSERT ZERO".to_string(),
+    }
+}
+
+/// Gates grouped by the instance path of the wire they drive
+#[derive(Default)]
+struct Cluster {
+    nodes: Vec<String>,
+    children: BTreeMap<String, Cluster>,
+}
+
+impl Cluster {
+    fn insert(&mut self, scope: Option<&str>, node: String) {
+        let mut cluster = self;
+        for segment in scope.into_iter().flat_map(|scope| scope.split('.')) {
+            cluster = cluster.children.entry(segment.to_string()).or_default();
+        }
+        cluster.nodes.push(node);
+    }
+
+    fn write<W: Write>(&self, writer: &mut W, path: &str, indent: usize) -> io::Result<()> {
+        let pad = "    ".repeat(indent);
+        for node in self.nodes.iter() {
+            writeln!(writer, "{}{}", pad, node)?;
+        }
+        for (segment, child) in self.children.iter() {
+            let path = if path.is_empty() {
+                segment.clone()
+            } else {
+                format!("{}.{}", path, segment)
+            };
+            writeln!(writer, "{}subgraph \"cluster_{}\" {{", pad, escape(&path))?;
+            writeln!(writer, "{}    label=\"{}\";", pad, escape(segment))?;
+            child.write(writer, &path, indent + 1)?;
+            writeln!(writer, "{}}}", pad)?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes a circuit (or the part of it selected by `options`) as a DOT graph
+pub fn write<T: Evaluable, W: Write>(
+    writer: &mut W,
+    circuit: &GenericCircuit<T>,
+    options: &DotOptions,
+) -> io::Result<()>
+where
+    Operation<T>: Identity<T>,
+{
+    let mut outputs: Vec<Wire> = if options.cone.is_empty() {
+        circuit.outputs.iter().copied().collect()
+    } else {
+        options.cone.clone()
+    };
+    outputs.sort_unstable();
+    outputs.dedup();
+
+    let whole = options.cone.is_empty() && options.depth.is_none();
+    let selected: HashSet<NodeIndex> = if whole {
+        circuit.graph.node_indices().collect()
+    } else {
+        circuit
+            .fan_in(&outputs, options.depth)
+            .into_keys()
+            .collect()
+    };
+
+    let local_name = |wire: Wire| match circuit.symbols.get(wire) {
+        Some(name) => name.rsplit('.').next().unwrap_or(name).to_string(),
+        None => format!("${}", wire),
+    };
+
+    writeln!(writer, "digraph \"{}\" {{", escape(&circuit.name))?;
+    writeln!(writer, "    rankdir=LR;")?;
+    writeln!(writer, "    node [fontname=\"monospace\"];")?;
+
+    // Wires that feed the drawn gates without being driven by one of them
+    let mut sources: Vec<Wire> = Vec::new();
+    let mut edges: Vec<(String, String)> = Vec::new();
+    let drivers = circuit.drivers();
+    let source = |wire: Wire, sources: &mut Vec<Wire>| match drivers.get(&wire) {
+        Some(idx) if selected.contains(idx) => format!("g{}", idx.index()),
+        _ => {
+            sources.push(wire);
+            format!("w{}", wire)
+        }
+    };
+
+    let mut clusters = Cluster::default();
+    let mut gates: Vec<NodeIndex> = selected.iter().copied().collect();
+    gates.sort_unstable();
+    for idx in gates {
+        let gate = &circuit.graph[idx];
+        let node = format!("g{}", idx.index());
+        let (label, scope) = match gate.dst() {
+            Some(dst) => (
+                format!("{}\\n{}", operation(gate), escape(&local_name(dst))),
+                circuit.symbols.scope(dst).filter(|_| options.cluster),
+            ),
+            None => (operation(gate), None),
+        };
+        clusters.insert(scope, format!("\"{}\" [label=\"{}\"];", node, label));
+
+        for wire in gate.inputs() {
+            edges.push((source(wire, &mut sources), node.clone()));
+        }
+    }
+
+    for wire in outputs.iter() {
+        let node = format!("o{}", wire);
+        writeln!(
+            writer,
+            "    \"{}\" [label=\"{}\", shape=box, style=filled, fillcolor=lightpink];",
+            node,
+            escape(&local_name(*wire))
+        )?;
+        edges.push((source(*wire, &mut sources), node));
+    }
+
+    // Every input of the circuit is drawn when drawing all of it, so that unused ones show up
+    if whole {
+        sources.extend(circuit.inputs.iter().copied());
+    }
+    sources.sort_unstable();
+    sources.dedup();
+    for wire in sources {
+        let style = if circuit.inputs.contains(&wire) {
+            "style=filled, fillcolor=palegreen"
+        } else {
+            "style=dashed"
+        };
+        writeln!(
+            writer,
+            "    \"w{}\" [label=\"{}\", shape=box, {}];",
+            wire,
+            escape(&local_name(wire)),
+            style
+        )?;
+    }
+
+    clusters.write(writer, "", 1)?;
+
+    for (from, to) in edges {
+        writeln!(writer, "    \"{}\" -> \"{}\";", from, to)?;
+    }
+    writeln!(writer, "}}")?;
+
+    Ok(())
+}
+
+impl<T: Evaluable> GenericCircuit<T>
+where
+    Operation<T>: Identity<T>,
+{
+    /// Produce a dotfile of the graph underlying this circuit, for debugging purposes.
+    pub fn dotfile(&self, filename: &str) -> io::Result<()> {
+        let mut f = BufWriter::new(File::create(filename)?);
+        write(&mut f, self, &DotOptions::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dot::{write, DotOptions};
+    use crate::{BoolCircuit, SVCircuitError};
+    use mcircuit::Operation;
+    use std::collections::HashSet;
+    use std::iter::FromIterator;
+
+    /// (a ^ b) & c, with an unrelated output that inverts a
+    fn example() -> Result<BoolCircuit, SVCircuitError> {
+        let mut circuit = BoolCircuit {
+            name: "top".to_string(),
+            inputs: HashSet::from_iter([2, 3, 4]),
+            outputs: HashSet::from_iter([6, 7]),
+            ..Default::default()
+        };
+        circuit._add_gate(Operation::Add(5, 2, 3))?;
+        circuit._add_gate(Operation::Mul(6, 5, 4))?;
+        circuit._add_gate(Operation::AddConst(7, 2, true))?;
+        circuit.symbols.insert(2, "top.a".to_string());
+        circuit.symbols.insert(5, "top.u_xor.y".to_string());
+        circuit.symbols.insert(6, "top.out".to_string());
+        circuit._build()?;
+        Ok(circuit)
+    }
+
+    fn render(circuit: &BoolCircuit, options: &DotOptions) -> String {
+        let mut written: Vec<u8> = Vec::new();
+        write(&mut written, circuit, options).expect("Failed to write DOT");
+        String::from_utf8(written).unwrap()
+    }
+
+    #[test]
+    fn test_write_all() -> Result<(), SVCircuitError> {
+        let circuit = example()?;
+        let dot = render(&circuit, &DotOptions::default());
+
+        assert!(dot.contains("[label=\"XOR\\ny\"]"), "{}", dot);
+        assert!(dot.contains("[label=\"NOT\\n$7\"]"), "{}", dot);
+        assert!(dot.contains("\"w2\" [label=\"a\", shape=box, style=filled, fillcolor=palegreen];"));
+        assert!(dot.contains("\"o6\" [label=\"out\", shape=box, style=filled"));
+        assert_eq!(dot.matches(" -> ").count(), 7);
+        assert!(!dot.contains("subgraph"));
+
+        let clustered = render(
+            &circuit,
+            &DotOptions {
+                cluster: true,
+                ..Default::default()
+            },
+        );
+        assert!(
+            clustered.contains("subgraph \"cluster_top.u_xor\" {"),
+            "{}",
+            clustered
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_cone() -> Result<(), SVCircuitError> {
+        let circuit = example()?;
+
+        let cone = render(
+            &circuit,
+            &DotOptions {
+                cone: vec![6],
+                ..Default::default()
+            },
+        );
+        assert!(cone.contains("XOR") && cone.contains("AND"), "{}", cone);
+        assert!(!cone.contains("NOT"), "{}", cone);
+        assert!(!cone.contains("\"o7\""), "{}", cone);
+
+        // One level back, the XOR is cut off and its output drawn as a dashed box
+        let shallow = render(
+            &circuit,
+            &DotOptions {
+                cone: vec![6],
+                depth: Some(1),
+                ..Default::default()
+            },
+        );
+        assert!(!shallow.contains("XOR"), "{}", shallow);
+        assert!(
+            shallow.contains("\"w5\" [label=\"y\", shape=box, style=dashed];"),
+            "{}",
+            shallow
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,502 @@
+//! Functional equivalence checking between two flat circuits.
+//!
+//! Small circuits are checked by simulating every possible input vector; larger ones are checked
+//! against a batch of random input vectors, which can find a bug but can never prove its absence.
+//! For a proof, build a [`miter`] of two boolean circuits and hand the [`write_dimacs`] encoding of
+//! it to a SAT solver: the circuits are equivalent iff the formula is unsatisfiable.
+//!
+//! Circuits are matched up positionally, with inputs and outputs ordered by ascending wire ID. The
+//! flattener freezes I/O wire IDs, so this lines up for two circuits produced from the same
+//! interface. Circuits from different front ends can be lined up by port with [`align_ports`]
+//! first.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use itertools::Itertools;
+use mcircuit::{HasIO, Identity, Operation};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use crate::generic::{translate_gate, SVCircuitError, Wire};
+use crate::{BoolCircuit, Evaluable, GenericCircuit, Port};
+
+/// Controls how hard [`check`] tries
+#[derive(Clone, Debug)]
+pub struct EquivalenceConfig {
+    /// Enumerate every input vector if there are at most this many bits of input
+    pub exhaustive_bits: u32,
+    /// How many random vectors to try when there are too many inputs to enumerate
+    pub random_vectors: usize,
+    /// Seed for the random vectors, so that failures are reproducible
+    pub seed: u64,
+}
+
+impl Default for EquivalenceConfig {
+    fn default() -> Self {
+        EquivalenceConfig {
+            exhaustive_bits: 16,
+            random_vectors: 4096,
+            seed: 0,
+        }
+    }
+}
+
+/// Outcome of an equivalence check
+#[derive(Clone, Debug, PartialEq)]
+pub enum Equivalence<T> {
+    /// Both circuits agreed on every vector we tried. If `exhaustive` is set, that was all of them.
+    Equivalent { vectors: usize, exhaustive: bool },
+    /// An input vector on which the circuits disagree, along with what each of them produced
+    Counterexample {
+        inputs: Vec<T>,
+        lhs: Vec<T>,
+        rhs: Vec<T>,
+    },
+}
+
+impl<T> Equivalence<T> {
+    pub fn is_equivalent(&self) -> bool {
+        matches!(self, Equivalence::Equivalent { .. })
+    }
+}
+
+/// Checks whether `lhs` and `rhs` compute the same function by simulating both of them.
+pub fn check<T: Evaluable>(
+    lhs: &GenericCircuit<T>,
+    rhs: &GenericCircuit<T>,
+    config: &EquivalenceConfig,
+) -> Result<Equivalence<T>, SVCircuitError>
+where
+    Operation<T>: Identity<T>,
+{
+    check_interfaces(lhs, rhs)?;
+
+    let width = lhs.inputs.len() as u64 * T::BITS as u64;
+    let exhaustive = width <= config.exhaustive_bits.min(usize::BITS - 1) as u64;
+
+    let compare = |inputs: Vec<T>| -> Result<Option<Equivalence<T>>, SVCircuitError> {
+        let l = lhs.simulate(&inputs)?;
+        let r = rhs.simulate(&inputs)?;
+        Ok(if l != r {
+            Some(Equivalence::Counterexample {
+                inputs,
+                lhs: l,
+                rhs: r,
+            })
+        } else {
+            None
+        })
+    };
+
+    let vectors = if exhaustive {
+        let vectors = 1usize << width;
+        for assignment in 0..vectors as u64 {
+            let inputs = (0..lhs.inputs.len())
+                .map(|i| T::from_bits(assignment >> (i as u32 * T::BITS)))
+                .collect();
+            if let Some(counterexample) = compare(inputs)? {
+                return Ok(counterexample);
+            }
+        }
+        vectors
+    } else {
+        let mut rng = StdRng::seed_from_u64(config.seed);
+        for _ in 0..config.random_vectors {
+            let inputs = (0..lhs.inputs.len()).map(|_| T::random(&mut rng)).collect();
+            if let Some(counterexample) = compare(inputs)? {
+                return Ok(counterexample);
+            }
+        }
+        config.random_vectors
+    };
+
+    Ok(Equivalence::Equivalent {
+        vectors,
+        exhaustive,
+    })
+}
+
+fn check_interfaces<T: Evaluable>(
+    lhs: &GenericCircuit<T>,
+    rhs: &GenericCircuit<T>,
+) -> Result<(), SVCircuitError> {
+    for circuit in [lhs, rhs] {
+        if !circuit.flat {
+            return Err(SVCircuitError::NotFlat {
+                name: circuit.name.clone(),
+            });
+        }
+    }
+
+    if lhs.inputs.len() != rhs.inputs.len() || lhs.outputs.len() != rhs.outputs.len() {
+        return Err(SVCircuitError::InterfaceMismatch {
+            lhs_inputs: lhs.inputs.len(),
+            lhs_outputs: lhs.outputs.len(),
+            rhs_inputs: rhs.inputs.len(),
+            rhs_outputs: rhs.outputs.len(),
+        });
+    }
+
+    Ok(())
+}
+
+/// Renumbers the wires of `circuit` so that each of its ports uses the wires of the port of the
+/// same name in `reference`, moving every other wire above those of `reference`. Both circuits
+/// must have the same ports, with the same directions and widths.
+pub fn align_ports<T: Evaluable>(
+    circuit: &GenericCircuit<T>,
+    reference: &GenericCircuit<T>,
+) -> Result<GenericCircuit<T>, SVCircuitError>
+where
+    Operation<T>: Identity<T>,
+{
+    let mut translations: HashMap<Wire, Wire> = HashMap::new();
+    for port in circuit.ports.iter() {
+        let target = reference
+            .ports
+            .iter()
+            .find(|p| {
+                p.name == port.name && p.direction == port.direction && p.width() == port.width()
+            })
+            .ok_or_else(|| SVCircuitError::PortMismatch {
+                name: port.name.clone(),
+            })?;
+        translations.extend(port.wires.iter().copied().zip(target.wires.iter().copied()));
+    }
+    if let Some(missing) = reference
+        .ports
+        .iter()
+        .find(|p| !circuit.ports.iter().any(|port| port.name == p.name))
+    {
+        return Err(SVCircuitError::PortMismatch {
+            name: missing.name.clone(),
+        });
+    }
+
+    let offset = max_wire(reference) + 1;
+    let wires = circuit
+        .topo_iter()
+        .flat_map(|g| g.inputs().chain(g.outputs()).collect::<Vec<_>>())
+        .chain(circuit.inputs.iter().copied())
+        .chain(circuit.outputs.iter().copied())
+        .chain(circuit.symbols.iter().map(|(w, _)| w))
+        .collect::<Vec<_>>();
+    for w in wires {
+        translations.entry(w).or_insert(w + offset);
+    }
+
+    let mut aligned = GenericCircuit {
+        name: circuit.name.clone(),
+        inputs: circuit.inputs.iter().map(|w| translations[w]).collect(),
+        outputs: circuit.outputs.iter().map(|w| translations[w]).collect(),
+        ports: circuit
+            .ports
+            .iter()
+            .map(|port| Port {
+                wires: port.wires.iter().map(|w| translations[w]).collect(),
+                ..port.clone()
+            })
+            .collect(),
+        symbols: circuit.symbols.clone(),
+        ..Default::default()
+    };
+    aligned.symbols.translate(&translations);
+    for gate in circuit.topo_iter() {
+        aligned._add_gate(translate_gate(gate, &translations, None))?;
+    }
+    aligned._build()?;
+
+    Ok(aligned)
+}
+
+/// Builds a miter of two boolean circuits: a circuit that feeds the same inputs to both of them and
+/// has a single output, which is set iff any pair of corresponding outputs differ. `lhs` keeps its
+/// wire IDs; `rhs` is moved above them.
+pub fn miter(lhs: &BoolCircuit, rhs: &BoolCircuit) -> Result<BoolCircuit, SVCircuitError> {
+    check_interfaces(lhs, rhs)?;
+
+    let mut miter = BoolCircuit {
+        name: format!("miter_{}_{}", lhs.name, rhs.name),
+        inputs: lhs.inputs.clone(),
+        ..Default::default()
+    };
+
+    let offset = max_wire(lhs) + 1;
+
+    // Wire the inputs of the right circuit to the inputs of the left one, and move everything else
+    // out of the way.
+    let mut translations: HashMap<Wire, Wire> = rhs
+        .inputs
+        .iter()
+        .sorted()
+        .zip(lhs.inputs.iter().sorted())
+        .map(|(r, l)| (*r, *l))
+        .collect();
+    for gate in rhs.topo_iter() {
+        for w in gate.inputs().chain(gate.outputs()) {
+            translations.entry(w).or_insert(w + offset);
+        }
+    }
+
+    for gate in lhs.topo_iter() {
+        miter._add_gate(*gate)?;
+    }
+    for gate in rhs.topo_iter() {
+        miter._add_gate(translate_gate(gate, &translations, None))?;
+    }
+
+    let mut next = offset + max_wire(rhs) + 1;
+    let mut fresh = || {
+        next += 1;
+        next - 1
+    };
+
+    // XOR together each pair of outputs, then OR all the differences together
+    let mut diff: Option<Wire> = None;
+    for (l, r) in lhs.outputs.iter().sorted().zip(rhs.outputs.iter().sorted()) {
+        let r = *translations.get(r).unwrap_or(r);
+        let xor = fresh();
+        miter._add_gate(Operation::Add(xor, *l, r))?;
+
+        diff = Some(match diff {
+            None => xor,
+            Some(acc) => {
+                // a | b == a ^ b ^ (a & b)
+                let (and, partial, or) = (fresh(), fresh(), fresh());
+                miter._add_gate(Operation::Mul(and, acc, xor))?;
+                miter._add_gate(Operation::Add(partial, acc, xor))?;
+                miter._add_gate(Operation::Add(or, partial, and))?;
+                or
+            }
+        });
+    }
+
+    let out = match diff {
+        Some(out) => out,
+        None => {
+            let out = fresh();
+            miter._add_gate(Operation::Const(out, false))?;
+            out
+        }
+    };
+    miter.outputs.insert(out);
+    miter._build()?;
+
+    Ok(miter)
+}
+
+/// Writes a Tseitin encoding of `circuit` in DIMACS CNF format, asserting that every output is set.
+/// Applied to a [`miter`], a satisfying assignment is a counterexample to equivalence. Comment lines
+/// record which variable corresponds to each input wire.
+pub fn write_dimacs<W: Write>(writer: &mut W, circuit: &BoolCircuit) -> io::Result<()> {
+    let mut vars: HashMap<Wire, i64> = HashMap::new();
+    let mut var = |w: Wire| -> i64 {
+        let next = vars.len() as i64 + 1;
+        *vars.entry(w).or_insert(next)
+    };
+
+    let inputs: Vec<(Wire, i64)> = circuit
+        .inputs
+        .iter()
+        .sorted()
+        .map(|i| (*i, var(*i)))
+        .collect();
+
+    let mut clauses: Vec<Vec<i64>> = Vec::new();
+    for gate in circuit.topo_iter() {
+        match *gate {
+            Operation::Input(_) | Operation::Random(_) => {}
+            Operation::Add(o, l, r) | Operation::Sub(o, l, r) => {
+                let (o, l, r) = (var(o), var(l), var(r));
+                clauses.push(vec![-o, l, r]);
+                clauses.push(vec![-o, -l, -r]);
+                clauses.push(vec![o, -l, r]);
+                clauses.push(vec![o, l, -r]);
+            }
+            Operation::Mul(o, l, r) => {
+                let (o, l, r) = (var(o), var(l), var(r));
+                clauses.push(vec![-o, l]);
+                clauses.push(vec![-o, r]);
+                clauses.push(vec![o, -l, -r]);
+            }
+            Operation::AddConst(o, i, c) | Operation::SubConst(o, i, c) => {
+                let (o, i) = (var(o), var(i));
+                let i = if c { -i } else { i };
+                clauses.push(vec![-o, i]);
+                clauses.push(vec![o, -i]);
+            }
+            Operation::MulConst(o, i, c) => {
+                let (o, i) = (var(o), var(i));
+                if c {
+                    clauses.push(vec![-o, i]);
+                    clauses.push(vec![o, -i]);
+                } else {
+                    clauses.push(vec![-o]);
+                }
+            }
+            Operation::Const(o, c) => {
+                let o = var(o);
+                clauses.push(vec![if c { o } else { -o }]);
+            }
+            Operation::AssertZero(w) => {
+                clauses.push(vec![-var(w)]);
+            }
+        }
+    }
+    for output in circuit.outputs.iter().sorted() {
+        clauses.push(vec![var(*output)]);
+    }
+
+    for (wire, v) in inputs {
+        writeln!(writer, "c input {} {}", v, wire)?;
+    }
+    writeln!(writer, "p cnf {} {}", vars.len(), clauses.len())?;
+    for clause in clauses {
+        writeln!(writer, "{} 0", clause.iter().join(" "))?;
+    }
+
+    Ok(())
+}
+
+/// Largest wire ID used anywhere in `circuit`
+fn max_wire<T: Evaluable>(circuit: &GenericCircuit<T>) -> Wire
+where
+    Operation<T>: Identity<T>,
+{
+    circuit
+        .topo_iter()
+        .flat_map(|g| g.inputs().chain(g.outputs()).collect::<Vec<_>>())
+        .chain(circuit.inputs.iter().copied())
+        .chain(circuit.outputs.iter().copied())
+        .max()
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::equiv::{align_ports, check, miter, Equivalence, EquivalenceConfig};
+    use crate::{BoolCircuit, Direction, Port, SVCircuitError};
+    use mcircuit::Operation;
+    use std::collections::HashSet;
+    use std::iter::FromIterator;
+
+    /// out = a & b, written directly
+    fn and_direct() -> Result<BoolCircuit, SVCircuitError> {
+        let mut circuit = BoolCircuit {
+            inputs: HashSet::from_iter([2, 3]),
+            outputs: HashSet::from_iter([4]),
+            ..Default::default()
+        };
+        circuit._add_gate(Operation::Mul(4, 2, 3))?;
+        circuit._build()?;
+        Ok(circuit)
+    }
+
+    /// out = !(!a | !b), via the GF(2) identity for OR
+    fn and_de_morgan(broken: bool) -> Result<BoolCircuit, SVCircuitError> {
+        let mut circuit = BoolCircuit {
+            inputs: HashSet::from_iter([2, 3]),
+            outputs: HashSet::from_iter([10]),
+            ..Default::default()
+        };
+        circuit._add_gate(Operation::AddConst(5, 2, true))?;
+        circuit._add_gate(Operation::AddConst(6, 3, true))?;
+        circuit._add_gate(Operation::Mul(7, 5, 6))?;
+        circuit._add_gate(Operation::Add(8, 5, 6))?;
+        circuit._add_gate(Operation::Add(9, 8, 7))?;
+        circuit._add_gate(Operation::AddConst(10, 9, !broken))?;
+        circuit._build()?;
+        Ok(circuit)
+    }
+
+    #[test]
+    fn test_equivalent_exhaustive() -> Result<(), SVCircuitError> {
+        let result = check(
+            &and_direct()?,
+            &and_de_morgan(false)?,
+            &EquivalenceConfig::default(),
+        )?;
+        assert_eq!(
+            result,
+            Equivalence::Equivalent {
+                vectors: 4,
+                exhaustive: true
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_counterexample() -> Result<(), SVCircuitError> {
+        let config = EquivalenceConfig {
+            exhaustive_bits: 0,
+            ..Default::default()
+        };
+        let result = check(&and_direct()?, &and_de_morgan(true)?, &config)?;
+        match result {
+            Equivalence::Counterexample { inputs, lhs, rhs } => {
+                assert_eq!(lhs, vec![inputs[0] & inputs[1]]);
+                assert_ne!(lhs, rhs);
+            }
+            _ => panic!("Expected a counterexample"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_miter() -> Result<(), SVCircuitError> {
+        let same = miter(&and_direct()?, &and_de_morgan(false)?)?;
+        let different = miter(&and_direct()?, &and_de_morgan(true)?)?;
+
+        for (a, b) in [(false, false), (false, true), (true, false), (true, true)] {
+            assert_eq!(same.simulate(&[a, b])?, vec![false]);
+            assert_eq!(different.simulate(&[a, b])?, vec![true]);
+        }
+        Ok(())
+    }
+
+    /// out = a & !b, with each input on the given wire
+    fn and_not(a: usize, b: usize) -> Result<BoolCircuit, SVCircuitError> {
+        let port = |name: &str, direction: Direction, wire: usize| Port {
+            name: name.to_string(),
+            direction,
+            wires: vec![wire],
+        };
+        let mut circuit = BoolCircuit {
+            inputs: HashSet::from_iter([a, b]),
+            outputs: HashSet::from_iter([5]),
+            ports: vec![
+                port("a", Direction::Input, a),
+                port("b", Direction::Input, b),
+                port("out", Direction::Output, 5),
+            ],
+            ..Default::default()
+        };
+        circuit._add_gate(Operation::AddConst(4, b, true))?;
+        circuit._add_gate(Operation::Mul(5, a, 4))?;
+        circuit._build()?;
+        Ok(circuit)
+    }
+
+    #[test]
+    fn test_align_ports() -> Result<(), SVCircuitError> {
+        let reference = and_not(2, 3)?;
+        let swapped = and_not(3, 2)?;
+        let config = EquivalenceConfig::default();
+
+        // Matched up by wire ID, a and b trade places
+        assert!(!check(&swapped, &reference, &config)?.is_equivalent());
+        let aligned = align_ports(&swapped, &reference)?;
+        assert_eq!(aligned.ports, reference.ports);
+        assert!(check(&aligned, &reference, &config)?.is_equivalent());
+
+        let mut renamed = and_not(2, 3)?;
+        renamed.ports[2].name = "y".to_string();
+        assert!(matches!(
+            align_ports(&renamed, &reference),
+            Err(SVCircuitError::PortMismatch { name }) if name == "y"
+        ));
+        Ok(())
+    }
+}
@@ -1,13 +1,36 @@
 use anyhow::{Context, Result};
 
 pub use crate::BoolCircuit;
-use crate::Witness;
+use crate::{Direction, Wire, Witness, WITNESS_LEN};
 use itertools::Itertools;
-use mcircuit::Operation;
-use std::collections::VecDeque;
+use mcircuit::{Gate, HasIO, Operation};
+use std::collections::{HashMap, VecDeque};
 
-use std::io::Write;
+use std::fs::File;
+use std::io::{BufWriter, Write};
 use std::ops::Range;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ExportError {
+    #[error(
+        "Can't call {name} on pairs of witness steps: it takes inputs {inputs:?} bits wide and has \
+         outputs {outputs:?} bits wide, rather than two inputs of the same width and a 1-bit output"
+    )]
+    CallLayout {
+        name: String,
+        inputs: Vec<usize>,
+        outputs: Vec<usize>,
+    },
+    #[error("{name} takes steps {width} bits wide, but each witness step is {expected} bits")]
+    StepWidth {
+        name: String,
+        width: usize,
+        expected: usize,
+    },
+    #[error("The witness has {steps} step(s), but at least 2 are needed to compare")]
+    TooFewSteps { steps: usize },
+}
 
 pub fn public<F: Write>(writer: &mut F) -> Result<()> {
     writeln!(writer, "version 2.0.0-beta;")?;
@@ -27,7 +50,7 @@ pub fn private<F: Write>(writer: &mut F, witness: &Witness) -> Result<()> {
     writeln!(writer, "private_input;")?;
     writeln!(writer, "@type field 2;")?;
     writeln!(writer, "@begin")?;
-    
+
     for (i, step) in witness.iter().enumerate() {
         writeln!(writer, "// step {}", i)?;
         for wit_value in step {
@@ -39,25 +62,284 @@ pub fn private<F: Write>(writer: &mut F, witness: &Witness) -> Result<()> {
     Ok(())
 }
 
+/// Knobs for the circuit exporter. The defaults produce plain IR.
+#[derive(Clone, Debug, Default)]
+pub struct ExportOptions {
+    /// Emit `//` comments naming the ports of the circuit function and marking where the gates
+    /// of each flattened module instance start
+    pub annotate: bool,
+    /// Emit `@delete` directives for wires as soon as they're dead, both in the function body
+    /// and between steps, so that IR backends can free them
+    pub delete: bool,
+}
+
+/// Writes `@delete` directives covering `wires`, which must be sorted: one per run of
+/// consecutive wires
+fn delete<F: Write>(writer: &mut F, indent: &str, wires: &[Wire]) -> Result<()> {
+    let mut runs: Vec<Range<Wire>> = Vec::new();
+    for wire in wires.iter() {
+        match runs.last_mut() {
+            Some(run) if run.end == *wire => run.end += 1,
+            _ => runs.push(*wire..*wire + 1),
+        }
+    }
+    for run in runs {
+        if run.len() == 1 {
+            writeln!(writer, "{}@delete(${});", indent, run.start)?;
+        } else {
+            writeln!(
+                writer,
+                "{}@delete(${} ... ${});",
+                indent,
+                run.start,
+                run.end - 1
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// For each of `gates`, the wires it's the last to use. Inputs belong to the caller and outputs
+/// are still needed once the body ends, so neither is ever deleted.
+fn last_uses(circuit: &BoolCircuit, gates: &[&Operation<bool>]) -> Vec<Vec<Wire>> {
+    let mut last: HashMap<Wire, usize> = HashMap::new();
+    for (i, gate) in gates.iter().enumerate() {
+        for wire in gate.outputs().chain(gate.inputs()) {
+            last.insert(wire, i);
+        }
+    }
+
+    let mut deletions: Vec<Vec<Wire>> = vec![Vec::new(); gates.len()];
+    for (wire, i) in last {
+        if !circuit.inputs.contains(&wire) && !circuit.outputs.contains(&wire) {
+            deletions[i].push(wire);
+        }
+    }
+    for wires in deletions.iter_mut() {
+        wires.sort_unstable();
+    }
+    deletions
+}
+
+/// Writes everything needed to prove a witness against `circuit`, to `{output}.circuit`,
+/// `{output}.public_input` and `{output}.private_input`, along with the names of its wires in
+/// `{output}.symbols`
+pub fn files(
+    output: &str,
+    circuit: &BoolCircuit,
+    witness: &Witness,
+    options: &ExportOptions,
+) -> Result<()> {
+    File::create(format!("{}.circuit", output))
+        .map(BufWriter::new)
+        .map(|mut f| circuit_with(&mut f, circuit, witness, options))??;
+
+    File::create(format!("{}.symbols", output))
+        .map(BufWriter::new)
+        .map(|mut f| circuit.symbols.write(&mut f))??;
+
+    File::create(format!("{}.public_input", output))
+        .map(BufWriter::new)
+        .map(|mut f| public(&mut f))??;
+
+    File::create(format!("{}.private_input", output))
+        .map(BufWriter::new)
+        .map(|mut f| private(&mut f, witness))??;
+
+    Ok(())
+}
+
 pub fn circuit<F: Write>(
     circuit_writer: &mut F,
     circuit: &BoolCircuit,
     witness: &Witness,
 ) -> Result<()> {
+    circuit_with(circuit_writer, circuit, witness, &ExportOptions::default())
+}
+
+/// Widths of the ports of `circuit` in `direction`, in the order they appear in its `@function`.
+/// Circuits that don't record their ports are assumed to take two steps of equal width.
+fn widths(circuit: &BoolCircuit, direction: Direction) -> Vec<usize> {
+    if !circuit.ports.is_empty() {
+        return circuit
+            .ports
+            .iter()
+            .filter(|port| port.direction == direction)
+            .map(|port| port.width())
+            .collect();
+    }
+    match direction {
+        // NOTE(jl): guaranteed two inputs of same size in `check.v`.
+        Direction::Input => vec![circuit.inputs.len() / 2, circuit.inputs.len() / 2],
+        Direction::Output => vec![circuit.outputs.len()],
+    }
+}
+
+/// Width of the witness steps that `circuit_with` passes to each `@call`, which only knows how
+/// to compare two steps down to a single bit
+fn step_width(circuit: &BoolCircuit) -> Result<usize, ExportError> {
+    let inputs = widths(circuit, Direction::Input);
+    let outputs = widths(circuit, Direction::Output);
+    match (inputs.as_slice(), outputs.as_slice()) {
+        ([previous, current], [1]) if previous == current => Ok(*current),
+        _ => Err(ExportError::CallLayout {
+            name: circuit.name.clone(),
+            inputs,
+            outputs,
+        }),
+    }
+}
+
+pub fn circuit_with<F: Write>(
+    circuit_writer: &mut F,
+    circuit: &BoolCircuit,
+    witness: &Witness,
+    options: &ExportOptions,
+) -> Result<()> {
+    let width = step_width(circuit)?;
+    if width != WITNESS_LEN {
+        return Err(ExportError::StepWidth {
+            name: circuit.name.clone(),
+            width,
+            expected: WITNESS_LEN,
+        }
+        .into());
+    }
+    // NOTE(jl): need at least 2 traces to compare.
+    if witness.len() < 2 {
+        return Err(ExportError::TooFewSteps {
+            steps: witness.len(),
+        }
+        .into());
+    }
+
     writeln!(circuit_writer, "version 2.0.0-beta;")?;
     writeln!(circuit_writer, "circuit;")?;
     writeln!(circuit_writer, "@type field 2;")?;
     writeln!(circuit_writer, "@begin")?;
 
+    function(circuit_writer, circuit, options)?;
+
+    // FIXME(jl): note about inputs, outputs, @functions, and flattening.
+    // because our wires are identified just by a unique integer,
+    // flattening reserves space for:
+    // - 2 wires, true and false (used for Bristol -- don't hurt us but also don't need).
+    // - all inputs -- so, here the circuit doesn't start until 2 + 656 I think.
+    // - all outputs -- so, we can't use those wires for other assignments!
+    //
+    // I think this is missing:
+    // - we have to generate $stepsize number of wires for each private input bit.
+    // - plus a wire for the `ok` bit.
+    // - plus a wire to invert it -- maybe, if we do really want an `@assert_one`.
+    // there's going to be some funky managing to begin counting anew outside the function body.
+    //
+    // So we can either hack around it,
+    // or maybe enable a flattening "mode" where it doesn't
+    // do any reserving of bits -- then if we have a way of knowing where the flattener left off counting,
+    // so just the number of wires the circuit body uses (currently ~46k),
+    // then we can pick up counting here as we please.
+
+    // HACK(jl): number sufficiently large enough I know the circuit won't conflict.
+    // FIXME(jl): this should start from the last assigned wire of the circuit body.
+    let mut wire_counter: usize = 0;
+
+    // emit circuit data.
+    let mut steps: VecDeque<Range<usize>> = VecDeque::with_capacity(2);
+
+    for (step_count, step) in witness.iter().enumerate() {
+        // fetch the private input;
+        // 1. allocate a contiguous wire range with `@new`, using the circuit step size,
+        // 2. emit an `@private` for each bit of the the inputs step, maintaining pairs
+        //    of steps in 2-depth deque,
+        // 3. emit an `@call` to the circuit function with step pair,
+        writeln!(circuit_writer, "// step {}", step_count)?;
+        // 1.
+        writeln!(
+            circuit_writer,
+            "@new(${} ... ${});", // steps were checked to be `width` bits up front.
+            wire_counter,
+            wire_counter + width - 1
+        )?;
+
+        // 2.
+        let start = wire_counter;
+        for _ in step {
+            writeln!(circuit_writer, "${} <- @private();", wire_counter)?;
+            wire_counter += 1;
+        }
+        // push current step onto deque
+        let step_range = Range {
+            start,
+            end: wire_counter,
+        };
+        steps.push_front(step_range);
+
+        if step_count > 0 {
+            // 3.
+            let back = steps.back().context("missing back step")?;
+            let front = steps.front().context("missing front step")?;
+            writeln!(
+                circuit_writer,
+                "${} <- @call({}, ${} ... ${}, ${} ... ${});",
+                wire_counter,
+                circuit.name,
+                // previous step wire range.
+                back.clone().min().context("no back step minimum")?,
+                back.clone().max().context("no back step maximum")?,
+                // current step wire range.
+                front.clone().min().context("no back step minimum")?,
+                front.clone().max().context("no back step maximum")?,
+            )?;
+            // 4.
+            writeln!(circuit_writer, "@assert_zero(${});", wire_counter)?;
+            if options.delete {
+                // The previous step has now been compared against both of its neighbours
+                writeln!(circuit_writer, "@delete(${});", wire_counter)?;
+                writeln!(
+                    circuit_writer,
+                    "@delete(${} ... ${});",
+                    back.start,
+                    back.end - 1
+                )?;
+            }
+            wire_counter += 1;
+            // pop verified step off of deque
+            steps.pop_back();
+        }
+    }
+    if options.delete {
+        if let Some(last) = steps.pop_front() {
+            writeln!(
+                circuit_writer,
+                "@delete(${} ... ${});",
+                last.start,
+                last.end - 1
+            )?;
+        }
+    }
+    writeln!(circuit_writer, "@end")?;
+    Ok(())
+}
+
+/// Emits the `@function` for `circuit`
+pub fn function<F: Write>(
+    circuit_writer: &mut F,
+    circuit: &BoolCircuit,
+    options: &ExportOptions,
+) -> Result<()> {
     // emit circuit @function.
+    let signature = |direction: Direction| {
+        widths(circuit, direction)
+            .iter()
+            .map(|width| format!("0:{}", width))
+            .join(", ")
+    };
     writeln!(
         circuit_writer,
-        "@function({}, @out: 0:{}, @in: 0:{}, 0:{})",
+        "@function({}, @out: {}, @in: {})",
         circuit.name,
-        circuit.outputs.len(),
-        // NOTE(jl): guaranteed two inputs of same size in `check.v`.
-        circuit.inputs.len() / 2,
-        circuit.inputs.len() / 2
+        signature(Direction::Output),
+        signature(Direction::Input)
     )?;
     // NOTE(lo): wire numbering in function bodies starts with the output and proceeds sequentially through the inputs
     // e.g. for the function signature above, which corresponds to circuit(step1, step2)
@@ -70,7 +352,37 @@ pub fn circuit<F: Write>(
     // but circuit.topo_iter() already comes with a numbering that may conflict with these indices...
     // We also don't appear to hit the Operation::Input case.
 
-    for gate in circuit.topo_iter() {
+    if options.annotate {
+        let symbols = &circuit.symbols;
+        for input in circuit.inputs.iter().sorted() {
+            writeln!(circuit_writer, "  // input {}", symbols.describe(*input))?;
+        }
+        for output in circuit.outputs.iter().sorted() {
+            writeln!(circuit_writer, "  // output {}", symbols.describe(*output))?;
+        }
+    }
+
+    let gates: Vec<&Operation<bool>> = circuit.topo_iter().collect();
+    let deletions = if options.delete {
+        last_uses(circuit, &gates)
+    } else {
+        vec![Vec::new(); gates.len()]
+    };
+
+    // Module instance that the last gate we emitted came from
+    let mut scope: Option<&str> = None;
+
+    for (gate, dead) in gates.into_iter().zip(deletions) {
+        if options.annotate {
+            let gate_scope = gate
+                .dst()
+                .or_else(|| gate.inputs().next())
+                .and_then(|w| circuit.symbols.scope(w));
+            if let Some(name) = gate_scope.filter(|_| gate_scope != scope) {
+                writeln!(circuit_writer, "  // {}", name)?;
+                scope = gate_scope;
+            }
+        }
         write!(circuit_writer, "  ")?; // indent body
         match gate {
             Operation::Input(_) => panic!("Input in circuit body!"),
@@ -110,6 +422,7 @@ pub fn circuit<F: Write>(
                 writeln!(circuit_writer, "${} <- < {} >;", w, *c as u32)
             }
         }?;
+        delete(circuit_writer, "  ", &dead)?;
     }
     // HACK(jl): this exporting function should be independent of our circuit geometry;
     // here we're just lucky the number of outputs slots nicely into the area reserved for Bristol
@@ -125,86 +438,179 @@ pub fn circuit<F: Write>(
     writeln!(circuit_writer, "@end")?;
     writeln!(circuit_writer, "\n")?;
 
-    // FIXME(jl): note about inputs, outputs, @functions, and flattening.
-    // because our wires are identified just by a unique integer,
-    // flattening reserves space for:
-    // - 2 wires, true and false (used for Bristol -- don't hurt us but also don't need).
-    // - all inputs -- so, here the circuit doesn't start until 2 + 656 I think.
-    // - all outputs -- so, we can't use those wires for other assignments!
-    //
-    // I think this is missing:
-    // - we have to generate $stepsize number of wires for each private input bit.
-    // - plus a wire for the `ok` bit.
-    // - plus a wire to invert it -- maybe, if we do really want an `@assert_one`.
-    // there's going to be some funky managing to begin counting anew outside the function body.
-    //
-    // So we can either hack around it,
-    // or maybe enable a flattening "mode" where it doesn't
-    // do any reserving of bits -- then if we have a way of knowing where the flattener left off counting,
-    // so just the number of wires the circuit body uses (currently ~46k),
-    // then we can pick up counting here as we please.
+    Ok(())
+}
 
-    // HACK(jl): number sufficiently large enough I know the circuit won't conflict.
-    // FIXME(jl): this should start from the last assigned wire of the circuit body.
-    let mut wire_counter: usize = 0;
+#[cfg(test)]
+mod tests {
+    use crate::export::{circuit_with, ExportError, ExportOptions};
+    use crate::{BoolCircuit, Direction, Port, WITNESS_LEN};
+    use mcircuit::Operation;
+    use std::collections::HashSet;
+    use std::iter::FromIterator;
 
-    // emit circuit data.
-    // FIXME(jl): ideally this can be caught much earlier.
-    // NOTE(jl): need at least 2 traces to compare.
-    assert!(witness.len() >= 2);
+    /// Compares the first bit of two steps, through an intermediate wire
+    fn compare() -> BoolCircuit {
+        let mut circuit = BoolCircuit {
+            name: "compare".to_string(),
+            inputs: HashSet::from_iter(1..=2 * WITNESS_LEN),
+            outputs: HashSet::from_iter([1314]),
+            ..Default::default()
+        };
+        circuit
+            ._add_gate(Operation::Add(1313, 1, 1 + WITNESS_LEN))
+            .unwrap();
+        circuit
+            ._add_gate(Operation::AddConst(1314, 1313, true))
+            .unwrap();
+        circuit._build().unwrap();
+        circuit
+    }
 
-    let mut steps: VecDeque<Range<usize>> = VecDeque::with_capacity(2);
+    /// Wires referenced by `line`, other than by `@delete`
+    fn wires(line: &str) -> Vec<usize> {
+        line.split('$')
+            .skip(1)
+            .map(|w| w.split(|c: char| !c.is_ascii_digit()).next().unwrap())
+            .map(|w| w.parse().unwrap())
+            .collect()
+    }
 
-    for (step_count, step) in witness.iter().enumerate() {
-        // fetch the private input;
-        // 1. allocate a contiguous wire range with `@new`, using the circuit step size,
-        // 2. emit an `@private` for each bit of the the inputs step, maintaining pairs
-        //    of steps in 2-depth deque,
-        // 3. emit an `@call` to the circuit function with step pair,
-        writeln!(circuit_writer, "// step {}", step_count)?;
-        // 1.
-        writeln!(
-            circuit_writer,
-            "@new(${} ... ${});", // NOTE(jl): this end range is asserted at exit of this loop.
-            wire_counter,
-            wire_counter + circuit.inputs.len() / 2 - 1
-        )?;
+    #[test]
+    fn test_delete() {
+        let circuit = compare();
+        let witness = vec![[false; WITNESS_LEN]; 3];
+        let export = |delete| {
+            let mut written: Vec<u8> = Vec::new();
+            let options = ExportOptions {
+                delete,
+                ..Default::default()
+            };
+            circuit_with(&mut written, &circuit, &witness, &options).unwrap();
+            String::from_utf8(written).unwrap()
+        };
+        let plain = export(false);
+        let deleted = export(true);
 
-        // 2.
-        let start = wire_counter;
-        for _ in step {
-            writeln!(circuit_writer, "${} <- @private();", wire_counter)?;
-            wire_counter += 1;
+        // Deletes are the only difference
+        assert!(!plain.contains("@delete"));
+        let stripped: Vec<&str> = deleted
+            .lines()
+            .filter(|l| !l.trim_start().starts_with("@delete"))
+            .collect();
+        assert_eq!(stripped, plain.lines().collect::<Vec<_>>());
+
+        // Only the intermediate wire is freed in the body, right after its last use
+        assert!(deleted.contains("  $1314 <- @addc($1313, < 1 >);\n  @delete($1313);\n"));
+        // Each step is freed once both calls that read it are done
+        let (_, main) = deleted.split_once("@end\n").unwrap();
+        for range in ["$0 ... $655", "$656 ... $1311", "$1313 ... $1968"] {
+            assert!(main.contains(&format!("@delete({});", range)));
         }
-        let end = wire_counter;
-        assert!(end == start + circuit.inputs.len() / 2);
-        // push current step onto deque
-        let step_range = Range { start, end };
-        steps.push_front(step_range);
 
-        if step_count > 0 {
-            // 3.
-            let back = steps.back().context("missing back step")?;
-            let front = steps.front().context("missing front step")?;
-            writeln!(
-                circuit_writer,
-                "${} <- @call({}, ${} ... ${}, ${} ... ${});",
-                wire_counter,
-                circuit.name,
-                // previous step wire range.
-                back.clone().min().context("no back step minimum")?,
-                back.clone().max().context("no back step maximum")?,
-                // current step wire range.
-                front.clone().min().context("no back step minimum")?,
-                front.clone().max().context("no back step maximum")?,
-            )?;
-            // 4.
-            writeln!(circuit_writer, "@assert_zero(${});", wire_counter)?;
-            wire_counter += 1;
-            // pop verified step off of deque
-            steps.pop_back();
+        // Nothing is used after it's deleted, in either scope
+        for scope in deleted.split("@end\n") {
+            let mut dead: HashSet<usize> = HashSet::new();
+            for line in scope.lines().map(str::trim_start) {
+                let referenced = wires(line);
+                if line.starts_with("@delete") {
+                    let (first, last) = (referenced[0], *referenced.last().unwrap());
+                    dead.extend(first..=last);
+                } else {
+                    assert!(referenced.iter().all(|w| !dead.contains(w)), "{}", line);
+                }
+            }
         }
     }
-    writeln!(circuit_writer, "@end")?;
-    Ok(())
+
+    #[test]
+    fn test_call_layout() {
+        let witness = vec![[false; WITNESS_LEN]; 2];
+        let export = |circuit: &BoolCircuit| {
+            circuit_with(
+                &mut Vec::new(),
+                circuit,
+                &witness,
+                &ExportOptions::default(),
+            )
+        };
+        assert!(export(&compare()).is_ok());
+
+        // Every other layout would give a @call that doesn't match the @function
+        let port = |name: &str, direction, wires: std::ops::Range<usize>| Port {
+            name: name.to_string(),
+            direction,
+            wires: wires.collect(),
+        };
+        let layouts = [
+            vec![port("steps", Direction::Input, 1..1313)],
+            vec![
+                port("a", Direction::Input, 1..657),
+                port("b", Direction::Input, 657..1313),
+                port("c", Direction::Input, 1313..1969),
+            ],
+            vec![
+                port("a", Direction::Input, 1..657),
+                port("b", Direction::Input, 657..1313),
+                port("ok", Direction::Output, 1313..1315),
+            ],
+        ];
+        for ports in layouts {
+            let circuit = BoolCircuit { ports, ..compare() };
+            let error = export(&circuit).unwrap_err();
+            assert!(matches!(
+                error.downcast_ref::<ExportError>(),
+                Some(ExportError::CallLayout { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_witness_checks() {
+        let export = |circuit: &BoolCircuit, steps: usize| {
+            let witness = vec![[false; WITNESS_LEN]; steps];
+            circuit_with(
+                &mut Vec::new(),
+                circuit,
+                &witness,
+                &ExportOptions::default(),
+            )
+            .unwrap_err()
+            .downcast::<ExportError>()
+            .unwrap()
+        };
+        assert!(matches!(
+            export(&compare(), 1),
+            ExportError::TooFewSteps { steps: 1 }
+        ));
+
+        let narrow = BoolCircuit {
+            ports: vec![
+                Port {
+                    name: "a".to_string(),
+                    direction: Direction::Input,
+                    wires: (1..9).collect(),
+                },
+                Port {
+                    name: "b".to_string(),
+                    direction: Direction::Input,
+                    wires: (657..665).collect(),
+                },
+                Port {
+                    name: "ok".to_string(),
+                    direction: Direction::Output,
+                    wires: vec![1314],
+                },
+            ],
+            ..compare()
+        };
+        assert!(matches!(
+            export(&narrow, 2),
+            ExportError::StepWidth {
+                width: 8,
+                expected: WITNESS_LEN,
+                ..
+            }
+        ));
+    }
 }
@@ -1,15 +1,13 @@
 use std::collections::hash_map::RandomState;
 use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
 use std::fmt::Debug;
-use std::fs::File;
-use std::io::Write;
 
 use counter::Counter;
 use itertools::Itertools;
 use mcircuit::parsers::blif::BlifCircuitDesc;
 use mcircuit::{Gate, HasIO, Identity, Operation, WireValue};
-use petgraph::algo::toposort;
-use petgraph::dot::Dot;
+use petgraph::algo::{is_cyclic_directed, toposort};
 use petgraph::graph::NodeIndex;
 use petgraph::prelude::StableDiGraph;
 use petgraph::visit::Topo;
@@ -17,7 +15,10 @@ use petgraph::Direction;
 use serde::{Serialize, Serializer};
 
 use crate::generic;
+use crate::generic::ports::Port;
+use crate::generic::symbols::SymbolTable;
 use crate::generic::{ModId, NodeIdx, SVCircuitError, SubCircuitDesc, Wire};
+use crate::optimize::schedule::{self, Node};
 
 /// A circuit model that can support gates of arbitrary type
 pub struct GenericCircuit<T: WireValue> {
@@ -31,18 +32,26 @@ pub struct GenericCircuit<T: WireValue> {
     pub inputs: HashSet<Wire>,
     /// IDs of the output wires
     pub outputs: HashSet<Wire>,
+    /// Named groupings of the input and output wires, in declaration order. May be empty if the
+    /// source format doesn't record them.
+    pub ports: Vec<Port>,
     /// Maps wires to the gates that write to them
     pub _gate_outputs: HashMap<Wire, NodeIdx>,
     /// IDs of wires that will be driven by subcircuits
     pub _subcircuit_outputs: HashSet<Wire>,
     /// Maps wires in the local domain to wires in the global domain
     pub remappings: HashMap<Wire, Wire>,
+    /// Maps wires back to their (hierarchical) names in the source design
+    pub symbols: SymbolTable,
     /// Whether or not all the wires from the pending_wires list have been processed
     pub built: bool,
     /// Whether or not this circuit contains subcircuits
     pub flat: bool,
     /// Unique ID for this circuit. Captures parent information
     pub id: ModId,
+    /// Gate order chosen by `schedule`. Followed by `topo_indices` and `topo_iter` in place of an
+    /// arbitrary topological order, until a method that changes the graph clears it.
+    pub order: Vec<NodeIndex>,
 }
 
 impl<T: WireValue> Default for GenericCircuit<T> {
@@ -53,12 +62,15 @@ impl<T: WireValue> Default for GenericCircuit<T> {
             subcircuits: Vec::new(),
             inputs: HashSet::new(),
             outputs: HashSet::new(),
+            ports: Vec::new(),
             _gate_outputs: HashMap::new(),
             _subcircuit_outputs: HashSet::new(),
             remappings: HashMap::new(),
+            symbols: SymbolTable::default(),
             built: false,
             flat: true,
             id: ModId::new(0),
+            order: Vec::new(),
         }
     }
 }
@@ -79,6 +91,7 @@ where
     /// A HashMap would use up substantially more RAM than the existing flat memory layout.
     pub fn minimize_wires(&mut self) {
         assert!(self.built);
+        self.order.clear();
 
         let mut translations: HashMap<Wire, Wire> = HashMap::new();
         let mut frozen: HashSet<Wire> = HashSet::new();
@@ -108,12 +121,19 @@ where
             self.graph[*idx] =
                 generic::translate_gate::<T>(&self.graph[*idx], &translations, Some(&frozen));
         }
+
+        // Names of wires that no gate touches anymore could collide with the new IDs
+        self.symbols
+            .retain(|w| frozen.contains(&w) || translations.contains_key(&w));
+        self.symbols.translate(&translations);
+        self._rebuild_gate_outputs();
     }
 
     /// Increment every wire index in the circuit by the provided amount. Useful for moving two minimized
     /// circuits into the same namespace without producing index collisions.
     pub fn increment_wires(&mut self, increment: Wire) {
         assert!(self.built);
+        self.order.clear();
 
         let mut translations: HashMap<Wire, Wire> = HashMap::new();
 
@@ -134,6 +154,8 @@ where
             // don't use those directly.
             self.graph[*idx] = generic::translate_gate::<T>(&self.graph[*idx], &translations, None);
         }
+        self.symbols.translate(&translations);
+        self._rebuild_gate_outputs();
         //
         // for idx in 0..self.inputs.len(){
         //     self.inputs[idx] += increment;
@@ -146,6 +168,7 @@ where
     /// Removes unnecessary buffer gates.
     pub fn prune(&mut self) -> usize {
         assert!(self.built);
+        self.order.clear();
 
         let mut removed: usize = 0;
         let mut topo = Topo::new(&self.graph);
@@ -257,12 +280,14 @@ where
                 }
             }
         }
+        self._rebuild_gate_outputs();
         removed
     }
 
     /// Replaces arithmetic nodes with const inputs with the Const variant of the node
     pub fn curry(&mut self) -> usize {
         assert!(self.built);
+        self.order.clear();
 
         let mut removed: usize = 0;
         let mut topo = Topo::new(&self.graph);
@@ -329,6 +354,7 @@ where
                 }
             }
         }
+        self._rebuild_gate_outputs();
         removed
     }
 
@@ -344,6 +370,7 @@ where
             name: self.name.clone(),
             inputs: self.inputs.clone(),
             outputs: self.outputs.clone(),
+            ports: self.ports.clone(),
             id: self.id.clone(),
             ..Default::default()
         };
@@ -361,6 +388,10 @@ where
             merged._add_gate(localized)?;
         }
 
+        // Our own wires keep their names wherever they ended up
+        merged.symbols = self.symbols.clone();
+        merged.symbols.translate(&merged.remappings);
+
         // Print a debug message with the subcircuits we're merging into this one
         let submod_counts = self
             .subcircuits
@@ -401,6 +432,15 @@ where
                 merged._add_gate(localized)?;
             }
 
+            // The subcircuit's wires are named relative to this instance of it
+            for (wire, name) in other.symbols.iter() {
+                if let Some(localized) = other_localizations.get(&wire) {
+                    merged
+                        .symbols
+                        .insert(*localized, format!("{}.{}", desc.instance, name));
+                }
+            }
+
             let mut splice_pairs: Vec<(Wire, Wire)> = Vec::new();
 
             // Connect the wires from the parent circuit to the input wires of the subcircuit. The
@@ -514,6 +554,12 @@ where
         // graph (assuming the gates weren't already topologically sorted)
         let num_wires = merged._build()?;
         log::debug!("Created {} wires", num_wires);
+        // Splicing in the subcircuits can close a loop even if none of the pieces had one
+        if is_cyclic_directed(&merged.graph) {
+            return Err(SVCircuitError::CombinationalLoop {
+                name: self.name.clone(),
+            });
+        }
         // We undo the remappings on any IO wires for this subcircuit
         // We attempt to squash the buffer gates that we used to connect the parent circuits to
         // the subcircuits
@@ -525,6 +571,13 @@ where
         let num_constant_gates_removed = merged.curry();
         log::debug!("Removed {} constant gates", num_constant_gates_removed);
 
+        // Forget the names of any wires that were squashed out of the circuit
+        let mut used: HashSet<Wire> = merged.inputs.union(&merged.outputs).copied().collect();
+        for gate in merged.graph.node_weights() {
+            used.extend(gate.inputs().chain(gate.outputs()));
+        }
+        merged.symbols.retain(|w| used.contains(&w));
+
         Ok(merged)
     }
 
@@ -532,6 +585,9 @@ where
     /// iterator, as it's not directly consumed by PyO3
     pub fn topo_indices(&self) -> Vec<NodeIndex> {
         assert!(self.built);
+        if self.is_scheduled() {
+            return self.order.clone();
+        }
         match toposort(&self.graph, None) {
             Ok(indices) => indices,
             Err(cycle) => panic!(
@@ -541,6 +597,54 @@ where
         }
     }
 
+    /// Whether `order` holds a schedule. Every method that changes the graph clears it, and it
+    /// must cover every gate to be followed.
+    fn is_scheduled(&self) -> bool {
+        !self.order.is_empty() && self.order.len() == self.graph.node_count()
+    }
+
+    /// Reorders the gates, within the dependencies between them, to reduce the number of wires
+    /// that are live at once (see `optimize::schedule`). Returns the peak number of live wires
+    /// before and after.
+    pub fn schedule(&mut self) -> (usize, usize) {
+        // Gates are numbered in the order they were added, which usually follows the source
+        // netlist, so ties are broken the way the netlist was written
+        let indices: Vec<NodeIndex> = self.graph.node_indices().collect();
+        let position: HashMap<NodeIndex, usize> = indices
+            .iter()
+            .enumerate()
+            .map(|(i, idx)| (*idx, i))
+            .collect();
+        let current: Vec<usize> = self
+            .topo_indices()
+            .iter()
+            .map(|idx| position[idx])
+            .collect();
+
+        let mut nodes: Vec<Node<Wire>> = indices
+            .iter()
+            .map(|idx| Node::new(self.graph[*idx].inputs(), self.graph[*idx].outputs()))
+            .collect();
+        // These consume the witness in order, so they keep their current order
+        let mut last_input = None;
+        for i in current.iter() {
+            if let Operation::Input(_) | Operation::Random(_) = self.graph[indices[*i]] {
+                nodes[*i].after = last_input.replace(*i);
+            }
+        }
+        // Outputs stay live to the end
+        let outputs: Vec<Wire> = self.outputs.iter().copied().collect();
+
+        let before = schedule::peak_live(&nodes, &current, &outputs);
+        let order = schedule::order(&nodes, &outputs)
+            .expect("Gates of a circuit can't drive the same wire");
+        let after = schedule::peak_live(&nodes, &order, &outputs);
+        log::info!("{}: peak live wires {} -> {}", self.name, before, after);
+
+        self.order = order.into_iter().map(|i| indices[i]).collect();
+        (before, after)
+    }
+
     /// Add the _description_ of a subcircuit to this circuit. Does not take the subcircuit model,
     /// only its name and a set of mappings detailing how to connect the inputs and outputs of the
     /// subcircuit. The mappings are Vecs of tuples (Wire, Wire), where the first wire is the ID
@@ -551,12 +655,30 @@ where
         name: String,
         inputs: Vec<(Wire, Wire)>,
         outputs: Vec<(Wire, Wire)>,
+    ) {
+        // BLIF doesn't name instances, so we number them per model
+        let instance = format!(
+            "{}_{}",
+            name,
+            self.subcircuits.iter().filter(|s| s.name == name).count()
+        );
+        self.add_named_subcircuit(name, instance, inputs, outputs);
+    }
+
+    /// Like `add_subcircuit`, but for source formats that give each instance its own name
+    pub fn add_named_subcircuit(
+        &mut self,
+        name: String,
+        instance: String,
+        inputs: Vec<(Wire, Wire)>,
+        outputs: Vec<(Wire, Wire)>,
     ) {
         self.flat = false;
         for (parent, _child) in outputs.iter() {
             self._subcircuit_outputs.insert(*parent);
         }
         self.subcircuits.push(SubCircuitDesc {
+            instance,
             name,
             inputs,
             outputs,
@@ -600,14 +722,6 @@ where
         max_wire
     }
 
-    /// Produce a dotfile of the graph underlying this circuit, for debugging purposes.
-    pub fn dotfile(&self, filename: &str) -> std::io::Result<()> {
-        let mut f = File::create(filename)?;
-        let output = format!("{:?}", Dot::with_config(&self.graph, &[]));
-        f.write_all(output.as_bytes())?;
-        Ok(())
-    }
-
     pub fn prettyprint(&self) -> String {
         format!("{:#?}", self.graph)
     }
@@ -619,6 +733,7 @@ where
     /// Add a gate to the circuit
     pub(crate) fn _add_gate(&mut self, gate: Operation<T>) -> Result<NodeIdx, SVCircuitError> {
         self.built = false;
+        self.order.clear();
 
         // Add the gate to the graph, and mark that it doesn't have its inputs satisfied yet
         let idx = self.graph.add_node(gate).index();
@@ -647,6 +762,7 @@ where
         if self.built {
             return Ok(0);
         }
+        self.order.clear();
         let mut edges_added = 0;
 
         let indices: Vec<NodeIndex> = self.graph.node_indices().collect();
@@ -684,6 +800,16 @@ where
         Ok(edges_added)
     }
 
+    /// Re-derive which gate drives each wire from the graph. Passes that rewrite or remove gates
+    /// call this so that `_build` and the cone searches don't look up wires that no longer exist.
+    fn _rebuild_gate_outputs(&mut self) {
+        self._gate_outputs = self
+            .graph
+            .node_indices()
+            .filter_map(|idx| self.graph[idx].dst().map(|dst| (dst, idx.index())))
+            .collect();
+    }
+
     /// Provide the set of input wires to this circuit
     fn _set_inputs(&mut self, inputs: &HashSet<usize, RandomState>) {
         self.inputs.clear();
@@ -760,7 +886,11 @@ pub struct TopoGateIter<'a, T: WireValue> {
 
 impl<'a, T: WireValue> TopoGateIter<'a, T> {
     fn new(circuit: &'a GenericCircuit<T>) -> Self {
-        let ordering: Vec<NodeIndex> = toposort(&circuit.graph, None).unwrap();
+        let ordering: Vec<NodeIndex> = if circuit.is_scheduled() {
+            circuit.order.clone()
+        } else {
+            toposort(&circuit.graph, None).unwrap()
+        };
         TopoGateIter {
             ordering,
             circuit,
@@ -814,11 +944,13 @@ where
     }
 }
 
-impl<T: WireValue> From<BlifCircuitDesc<T>> for GenericCircuit<T>
+impl<T: WireValue> TryFrom<BlifCircuitDesc<T>> for GenericCircuit<T>
 where
     Operation<T>: Identity<T>,
 {
-    fn from(mut desc: BlifCircuitDesc<T>) -> Self {
+    type Error = SVCircuitError;
+
+    fn try_from(mut desc: BlifCircuitDesc<T>) -> Result<Self, Self::Error> {
         let mut circuit = GenericCircuit::<T> {
             name: desc.name,
             ..Default::default()
@@ -826,9 +958,9 @@ where
         circuit.inputs.extend(desc.inputs.drain(..));
         circuit.outputs.extend(desc.outputs.drain(..));
         for gate in desc.gates.drain(..) {
-            circuit._add_gate(gate).unwrap();
+            circuit._add_gate(gate)?;
         }
-        circuit
+        Ok(circuit)
     }
 }
 
@@ -863,6 +995,58 @@ mod tests {
                 Operation::Mul(6, 5, 2)
             ]
         );
+        let mut drivers: Vec<usize> = circuit._gate_outputs.keys().copied().collect();
+        drivers.sort_unstable();
+        assert_eq!(drivers, vec![4, 5, 6]);
+        // So gates can still be added on top of the renumbered wires
+        circuit._add_gate(Operation::Add(7, 6, 5))?;
+        circuit._build()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_schedule() -> Result<(), SVCircuitError> {
+        // Eight independent XORs, then ANDs of adjacent results
+        let mut circuit: GenericCircuit<bool> = GenericCircuit::default();
+        circuit._set_inputs(&HashSet::from_iter(0..16));
+        circuit._set_outputs(&HashSet::from_iter(24..28));
+        for k in 0..8 {
+            circuit._add_gate(Operation::Add(16 + k, 2 * k, 2 * k + 1))?;
+        }
+        for k in 0..4 {
+            circuit._add_gate(Operation::Mul(24 + k, 16 + 2 * k, 17 + 2 * k))?;
+        }
+        circuit._build()?;
+
+        let inputs: Vec<bool> = (0..16).map(|w| w % 3 == 0).collect();
+        let expected = circuit.simulate(&inputs)?;
+
+        let (before, after) = circuit.schedule();
+        assert!(after <= before);
+        let scheduled: Vec<Operation<bool>> = circuit.topo_iter().cloned().collect();
+        assert_eq!(scheduled.len(), 12);
+        // Each AND runs as soon as the two XORs it reads are done
+        for k in 0..4 {
+            let position = |dst: usize| {
+                scheduled
+                    .iter()
+                    .position(|gate| gate == &Operation::Mul(dst, 16 + 2 * k, 17 + 2 * k))
+            };
+            assert_eq!(position(24 + k), Some(3 * k + 2));
+        }
+        assert_eq!(circuit.simulate(&inputs)?, expected);
+
+        // Adding a gate forgets the schedule
+        circuit._add_gate(Operation::AssertZero(24))?;
+        circuit._build()?;
+        assert_eq!(circuit.topo_iter().count(), 13);
+
+        // So does any pass that changes the graph, even if it keeps every gate
+        circuit.schedule();
+        assert!(circuit.is_scheduled());
+        assert_eq!(circuit.prune(), 0);
+        assert!(!circuit.is_scheduled());
 
         Ok(())
     }
@@ -892,6 +1076,7 @@ mod tests {
                 Operation::Mul(9, 5, 6)
             ]
         );
+        assert!(!circuit._gate_outputs.contains_key(&7));
 
         Ok(())
     }
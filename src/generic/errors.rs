@@ -29,6 +29,41 @@ pub enum SVCircuitError {
     #[error("This circuit is not topologically-sorted")]
     NonTopo,
 
+    #[error("Circuit '{name}' contains a combinational loop")]
+    CombinationalLoop { name: String },
+
+    #[error("Circuit '{name}' (indirectly) instantiates itself")]
+    RecursiveDependency { name: String },
+
+    #[error("No circuits found in this design")]
+    NoCircuits,
+
     #[error("Circuit {name} is missing output bits: {wires:?}")]
     UndrivenOutput { name: String, wires: Vec<Wire> },
+
+    #[error("Circuit '{name}' still contains subcircuits and must be flattened first")]
+    NotFlat { name: String },
+
+    #[error("Wire {wire} was read before any value was assigned to it")]
+    UnassignedWire { wire: Wire },
+
+    #[error("Circuit '{name}' takes {expected} inputs, but {actual} values were given")]
+    InputCount {
+        name: String,
+        expected: usize,
+        actual: usize,
+    },
+
+    #[error(
+        "Circuits have different interfaces ({lhs_inputs} -> {lhs_outputs} vs. {rhs_inputs} -> {rhs_outputs})"
+    )]
+    InterfaceMismatch {
+        lhs_inputs: usize,
+        lhs_outputs: usize,
+        rhs_inputs: usize,
+        rhs_outputs: usize,
+    },
+
+    #[error("Port '{name}' isn't on both circuits with the same direction and width")]
+    PortMismatch { name: String },
 }
@@ -0,0 +1,305 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+
+use itertools::Itertools;
+use mcircuit::{CombineOperation, Identity, Operation, WireValue};
+use rand::Rng;
+
+use crate::generic::circuit::GenericCircuit;
+use crate::generic::{SVCircuitError, Wire};
+
+/// The arithmetic we need in order to simulate a circuit whose wires carry values of this type.
+/// Boolean circuits compute over GF(2), arithmetic circuits over Z_2^64.
+pub trait Evaluable: WireValue + Debug {
+    /// Number of bits needed to enumerate every possible value of a single wire
+    const BITS: u32;
+
+    fn zero() -> Self;
+    fn add(self, rhs: Self) -> Self;
+    fn sub(self, rhs: Self) -> Self;
+    fn mul(self, rhs: Self) -> Self;
+
+    /// Builds a value out of the low `Self::BITS` bits of `bits`
+    fn from_bits(bits: u64) -> Self;
+
+    /// Samples a uniformly random value
+    fn random<R: Rng + ?Sized>(rng: &mut R) -> Self;
+}
+
+impl Evaluable for bool {
+    const BITS: u32 = 1;
+
+    fn zero() -> Self {
+        false
+    }
+
+    fn add(self, rhs: Self) -> Self {
+        self ^ rhs
+    }
+
+    fn sub(self, rhs: Self) -> Self {
+        self ^ rhs
+    }
+
+    fn mul(self, rhs: Self) -> Self {
+        self & rhs
+    }
+
+    fn from_bits(bits: u64) -> Self {
+        bits & 1 == 1
+    }
+
+    fn random<R: Rng + ?Sized>(rng: &mut R) -> Self {
+        rng.gen()
+    }
+}
+
+impl Evaluable for u64 {
+    const BITS: u32 = 64;
+
+    fn zero() -> Self {
+        0
+    }
+
+    fn add(self, rhs: Self) -> Self {
+        self.wrapping_add(rhs)
+    }
+
+    fn sub(self, rhs: Self) -> Self {
+        self.wrapping_sub(rhs)
+    }
+
+    fn mul(self, rhs: Self) -> Self {
+        self.wrapping_mul(rhs)
+    }
+
+    fn from_bits(bits: u64) -> Self {
+        bits
+    }
+
+    fn random<R: Rng + ?Sized>(rng: &mut R) -> Self {
+        rng.gen()
+    }
+}
+
+/// Computes the value a single gate writes to its output wire, if it has one. Reads its operands
+/// out of `values`.
+pub(crate) fn evaluate_gate<T: Evaluable>(
+    gate: &Operation<T>,
+    values: &HashMap<Wire, T>,
+) -> Result<Option<(Wire, T)>, SVCircuitError> {
+    let get = |wire: &Wire| {
+        values
+            .get(wire)
+            .copied()
+            .ok_or(SVCircuitError::UnassignedWire { wire: *wire })
+    };
+
+    Ok(match gate {
+        // Inputs and challenges are supplied by the caller, so all we do is check they're present
+        Operation::Input(w) | Operation::Random(w) => Some((*w, get(w)?)),
+        Operation::Add(o, l, r) => Some((*o, get(l)?.add(get(r)?))),
+        Operation::AddConst(o, i, c) => Some((*o, get(i)?.add(*c))),
+        Operation::Sub(o, l, r) => Some((*o, get(l)?.sub(get(r)?))),
+        Operation::SubConst(o, i, c) => Some((*o, get(i)?.sub(*c))),
+        Operation::Mul(o, l, r) => Some((*o, get(l)?.mul(get(r)?))),
+        Operation::MulConst(o, i, c) => Some((*o, get(i)?.mul(*c))),
+        Operation::Const(o, c) => Some((*o, *c)),
+        Operation::AssertZero(w) => {
+            get(w)?;
+            None
+        }
+    })
+}
+
+impl<T: Evaluable> GenericCircuit<T>
+where
+    Operation<T>: Identity<T>,
+{
+    /// Simulates this circuit. `values` must hold a value for every input wire, as well as for the
+    /// destination of every `Random` gate. On return, it also holds the value of every wire driven
+    /// by a gate.
+    pub fn evaluate(&self, values: &mut HashMap<Wire, T>) -> Result<(), SVCircuitError> {
+        if !self.flat {
+            return Err(SVCircuitError::NotFlat {
+                name: self.name.clone(),
+            });
+        }
+
+        for idx in self.topo_indices() {
+            if let Some((wire, value)) = evaluate_gate(&self.graph[idx], values)? {
+                values.insert(wire, value);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Simulates this circuit on a single input vector. Inputs and outputs are both given in order
+    /// of ascending wire ID.
+    pub fn simulate(&self, inputs: &[T]) -> Result<Vec<T>, SVCircuitError> {
+        if inputs.len() != self.inputs.len() {
+            return Err(SVCircuitError::InputCount {
+                name: self.name.clone(),
+                expected: self.inputs.len(),
+                actual: inputs.len(),
+            });
+        }
+        let mut values: HashMap<Wire, T> = self
+            .inputs
+            .iter()
+            .copied()
+            .sorted()
+            .zip(inputs.iter().copied())
+            .collect();
+
+        self.evaluate(&mut values)?;
+
+        self.outputs
+            .iter()
+            .sorted()
+            .map(|o| {
+                values
+                    .get(o)
+                    .copied()
+                    .ok_or(SVCircuitError::UnassignedWire { wire: *o })
+            })
+            .collect()
+    }
+}
+
+/// Values of every wire passed to an `AssertZero` gate while evaluating a composite program, in
+/// program order
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ProgramOutputs {
+    pub boolean: Vec<bool>,
+    pub arithmetic: Vec<u64>,
+}
+
+/// Evaluates a composite program. `Input` and `Random` gates consume values from the input slice for
+/// their domain, in program order.
+pub fn evaluate_program(
+    program: &[CombineOperation],
+    boolean_inputs: &[bool],
+    arithmetic_inputs: &[u64],
+) -> Result<ProgramOutputs, SVCircuitError> {
+    fn step<T: Evaluable>(
+        gate: &Operation<T>,
+        values: &mut HashMap<Wire, T>,
+        inputs: &mut impl Iterator<Item = T>,
+        asserted: &mut Vec<T>,
+    ) -> Result<(), SVCircuitError> {
+        match *gate {
+            Operation::Input(w) | Operation::Random(w) => {
+                let value = inputs
+                    .next()
+                    .ok_or(SVCircuitError::UnassignedWire { wire: w })?;
+                values.insert(w, value);
+            }
+            Operation::AssertZero(w) => {
+                let value = values
+                    .get(&w)
+                    .copied()
+                    .ok_or(SVCircuitError::UnassignedWire { wire: w })?;
+                asserted.push(value);
+            }
+            _ => {
+                if let Some((wire, value)) = evaluate_gate(gate, values)? {
+                    values.insert(wire, value);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    let mut boolean: HashMap<Wire, bool> = HashMap::new();
+    let mut arithmetic: HashMap<Wire, u64> = HashMap::new();
+    let mut boolean_inputs = boolean_inputs.iter().copied();
+    let mut arithmetic_inputs = arithmetic_inputs.iter().copied();
+    let mut outputs = ProgramOutputs::default();
+
+    for op in program {
+        match op {
+            CombineOperation::GF2(gate) => step(
+                gate,
+                &mut boolean,
+                &mut boolean_inputs,
+                &mut outputs.boolean,
+            )?,
+            CombineOperation::Z64(gate) => step(
+                gate,
+                &mut arithmetic,
+                &mut arithmetic_inputs,
+                &mut outputs.arithmetic,
+            )?,
+            CombineOperation::B2A(dst, lo) => {
+                let mut packed: u64 = 0;
+                for bit in 0..64 {
+                    let wire = lo + bit;
+                    let value = boolean
+                        .get(&wire)
+                        .copied()
+                        .ok_or(SVCircuitError::UnassignedWire { wire })?;
+                    packed |= (value as u64) << bit;
+                }
+                arithmetic.insert(*dst, packed);
+            }
+            CombineOperation::SizeHint(_, _) => {}
+        }
+    }
+
+    Ok(outputs)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{GenericCircuit, SVCircuitError};
+    use mcircuit::Operation;
+    use std::collections::HashSet;
+    use std::iter::FromIterator;
+
+    #[test]
+    fn test_simulate_bool() -> Result<(), SVCircuitError> {
+        let mut circuit: GenericCircuit<bool> = GenericCircuit {
+            inputs: HashSet::from_iter([2, 3]),
+            outputs: HashSet::from_iter([6]),
+            ..Default::default()
+        };
+        circuit._add_gate(Operation::Add(4, 2, 3))?;
+        circuit._add_gate(Operation::Mul(5, 2, 3))?;
+        circuit._add_gate(Operation::AddConst(6, 4, true))?;
+        circuit._build()?;
+
+        assert_eq!(circuit.simulate(&[false, false])?, vec![true]);
+        assert_eq!(circuit.simulate(&[true, false])?, vec![false]);
+        assert_eq!(circuit.simulate(&[true, true])?, vec![true]);
+        assert!(matches!(
+            circuit.simulate(&[true, true, true]),
+            Err(SVCircuitError::InputCount {
+                expected: 2,
+                actual: 3,
+                ..
+            })
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_simulate_arith() -> Result<(), SVCircuitError> {
+        let mut circuit: GenericCircuit<u64> = GenericCircuit {
+            inputs: HashSet::from_iter([0, 1]),
+            outputs: HashSet::from_iter([4]),
+            ..Default::default()
+        };
+        circuit._add_gate(Operation::Mul(2, 0, 1))?;
+        circuit._add_gate(Operation::SubConst(3, 2, 7))?;
+        circuit._add_gate(Operation::Add(4, 3, 0))?;
+        circuit._build()?;
+
+        assert_eq!(circuit.simulate(&[3, 5])?, vec![11]);
+        assert_eq!(circuit.simulate(&[0, 5])?, vec![u64::MAX - 6]);
+
+        Ok(())
+    }
+}
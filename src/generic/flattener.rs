@@ -1,9 +1,10 @@
 use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
 use std::fmt::Debug;
 use std::mem::size_of;
 
 use mcircuit::{Gate, HasIO, Operation, WireValue};
-use petgraph::algo::toposort;
+use petgraph::algo::{is_cyclic_directed, toposort};
 use petgraph::graph::NodeIndex;
 use petgraph::prelude::StableDiGraph;
 
@@ -59,18 +60,18 @@ where
     /// Add a subcircuit design to the flattener
     /// * `name` - The name of the subcircuit
     /// * `circuit` - The full design of the subcircuit
-    pub fn add_subcircuit(&mut self, name: String, mut circuit: GenericCircuit<T>) -> NodeIdx {
+    pub fn add_subcircuit(
+        &mut self,
+        name: String,
+        mut circuit: GenericCircuit<T>,
+    ) -> Result<NodeIdx, SVCircuitError> {
         self.built = false;
 
         // Build the `required_by` dict appropriately
         for sub in &circuit.subcircuits {
-            if !self.required_by.contains_key(&sub.name) {
-                self.required_by.insert(sub.name.clone(), HashSet::new());
-            }
-
             self.required_by
-                .get_mut(&sub.name)
-                .expect("But... Where did it go?")
+                .entry(sub.name.clone())
+                .or_default()
                 .insert(name.clone());
         }
 
@@ -78,49 +79,50 @@ where
         while !circuit.built {
             match circuit._build() {
                 Ok(_) => {}
-                Err(e) => match e {
-                    SVCircuitError::UndrivenGate {
-                        parent,
-                        gate_index,
-                        wire: _,
-                    } => {
-                        log::warn!("{parent} contains a gate with an undriven input. Dropping this gate and trusting that its output won't be needed.");
-                        let gate = circuit
-                            .graph
-                            .remove_node(NodeIndex::new(gate_index))
-                            .unwrap();
+                Err(SVCircuitError::UndrivenGate {
+                    parent,
+                    gate_index,
+                    wire: _,
+                }) => {
+                    log::warn!("{parent} contains a gate with an undriven input. Dropping this gate and trusting that its output won't be needed.");
+                    circuit.order.clear();
+                    if let Some(gate) = circuit.graph.remove_node(NodeIndex::new(gate_index)) {
                         if let Some(dst) = gate.dst() {
                             circuit._gate_outputs.remove(&dst);
                         }
                     }
-                    _ => {
-                        panic!("Failed to build {}", circuit.name);
-                    }
-                },
+                }
+                Err(e) => return Err(e),
             }
         }
+
+        // Flattening a circuit relies on being able to put its gates in topological order
+        if is_cyclic_directed(&circuit.graph) {
+            return Err(SVCircuitError::CombinationalLoop { name });
+        }
+
         // Store the subcircuit info in the appropriate fields
         self.subcircuits.insert(name.clone(), circuit);
         let idx = self.graph.add_node(name.clone()).index();
         self.name_map.insert(name, idx);
 
-        idx
+        Ok(idx)
     }
 
     /// Iterate through the requirements and add appropriate edges in the underlying graph.
     /// Necessary before flattening so we can get a topological ordering.
-    fn build(&mut self) {
+    fn build(&mut self) -> Result<(), SVCircuitError> {
         // Iterate over the pair of parents and children
         for (child_name, parent_names) in self.required_by.iter() {
-            // Get the node ID for the child
-            let child_id = self.name_map.get(child_name).unwrap_or_else(|| {
-                panic!(
-                    "A circuit called {} was referenced, but couldn't be found",
-                    child_name
-                )
-            });
             // Iterate over parents, since subcircuits can be used in multiple other modules
             for parent_name in parent_names.iter() {
+                // Get the node ID for the child
+                let child_id = self.name_map.get(child_name).ok_or_else(|| {
+                    SVCircuitError::MissingDependency {
+                        dependency: child_name.clone(),
+                        parent: parent_name.clone(),
+                    }
+                })?;
                 // Get the node ID for the parent
                 let parent_id = self.name_map.get(parent_name).unwrap_or_else(|| {
                     panic!("Somehow, a node was never created for {}", parent_name)
@@ -131,12 +133,13 @@ where
             }
         }
         self.built = true;
+        Ok(())
     }
 
     /// Produces a flat representation of `self.top`
     pub fn flatten(&mut self) -> Result<GenericCircuit<T>, SVCircuitError> {
         if !self.built {
-            self.build();
+            self.build()?;
         }
         // Get a topological ordering of the subcircuits
         let graph = &self.graph;
@@ -145,10 +148,14 @@ where
                 .into_iter()
                 .map(|i| graph.node_weight(i).expect("The node vanished!"))
                 .collect(),
-            Err(cycle) => panic!(
-                "Can't get a topological ordering - there's a cycle in this circuit: {:?}",
-                cycle
-            ),
+            Err(cycle) => {
+                return Err(SVCircuitError::RecursiveDependency {
+                    name: graph
+                        .node_weight(cycle.node_id())
+                        .expect("The node vanished!")
+                        .clone(),
+                })
+            }
         };
         // Iterate over the subcircuits in topological order and flatten them as we go
         for sub_name in ordering {
@@ -189,6 +196,7 @@ where
         // Merge all of the subcircuits into the top module
         log::debug!("Top module:");
         let mut out = self.top.merge(&self.subcircuits)?;
+        out.symbols.prefix(&out.name);
 
         // Shrink the wires down into the smallest contiguous chunk of the 64-bit space as possible
         // so that they'll fit in less memory when we have to load them into Reverie.
@@ -198,21 +206,29 @@ where
     }
 }
 
-impl<T: WireValue> From<(String, HashMap<String, GenericCircuit<T>>)> for CircuitFlattener<T>
+impl<T: Debug + WireValue> TryFrom<(String, HashMap<String, GenericCircuit<T>>)>
+    for CircuitFlattener<T>
 where
     Operation<T>: Gate<T>,
 {
-    fn from(repr: (String, HashMap<String, GenericCircuit<T>>)) -> Self {
+    type Error = SVCircuitError;
+
+    fn try_from(repr: (String, HashMap<String, GenericCircuit<T>>)) -> Result<Self, Self::Error> {
         let (top_name, mut mappings) = repr;
         let mut flattener = CircuitFlattener {
-            top: mappings.remove(&*top_name).expect("Missing top circuit"),
+            top: mappings
+                .remove(&*top_name)
+                .ok_or(SVCircuitError::MissingDependency {
+                    dependency: top_name.clone(),
+                    parent: top_name,
+                })?,
             ..Default::default()
         };
 
         for (name, circuit) in mappings.drain() {
-            flattener.add_subcircuit(name, circuit);
+            flattener.add_subcircuit(name, circuit)?;
         }
 
-        flattener
+        Ok(flattener)
     }
 }
@@ -9,14 +9,18 @@ mod errors;
 
 pub use errors::SVCircuitError;
 pub mod circuit;
+pub mod eval;
 pub mod flattener;
+pub mod ports;
+pub mod slice;
+pub mod symbols;
 
 pub type Wire = usize;
 pub type NodeIdx = usize;
 
 /// Returns a version of `gate` with wires specified as keys in `remap` replaced with the
 /// corresponding values.
-fn translate_gate<T: WireValue>(
+pub(crate) fn translate_gate<T: WireValue>(
     gate: &Operation<T>,
     remap: &HashMap<Wire, Wire>,
     frozen: Option<&HashSet<Wire>>,
@@ -106,6 +110,8 @@ impl ModId {
 pub struct SubCircuitDesc {
     /// Name of the subcircuit to look for
     pub name: String,
+    /// Name of this instance of the subcircuit, unique within the parent circuit
+    pub instance: String,
     /// Pairings between wire IDs in the parent namespace and wire IDs in the subcircuit's namespace
     pub inputs: Vec<(Wire, Wire)>,
     /// Pairings between wire IDs in the parent namespace and wire IDs in the subcircuit's namespace
@@ -0,0 +1,158 @@
+use itertools::Itertools;
+use mcircuit::WireValue;
+
+use crate::generic::circuit::GenericCircuit;
+use crate::generic::Wire;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Input,
+    Output,
+}
+
+/// A named, possibly multi-bit, port on the interface of a circuit
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Port {
+    pub name: String,
+    pub direction: Direction,
+    /// Wires carrying each bit of the port, least significant bit first
+    pub wires: Vec<Wire>,
+}
+
+impl Port {
+    pub fn width(&self) -> usize {
+        self.wires.len()
+    }
+
+    /// Reassembles ports out of individually-named bits, as found in a BLIF file. Bits named like
+    /// `step[3]` are grouped into a port called `step`, while any other name becomes a port of its
+    /// own. Ports are returned in the order their first bit appears in `bits`.
+    pub fn from_bit_names<'a>(
+        bits: impl Iterator<Item = (Wire, &'a str)>,
+        direction: Direction,
+    ) -> Vec<Port> {
+        let mut ports: Vec<(Port, Vec<usize>)> = Vec::new();
+
+        for (wire, name) in bits {
+            let (base, index) = split_index(name).unwrap_or((name, 0));
+            match ports.iter_mut().find(|(port, _)| port.name == base) {
+                Some((port, indices)) => {
+                    port.wires.push(wire);
+                    indices.push(index);
+                }
+                None => ports.push((
+                    Port {
+                        name: base.to_string(),
+                        direction,
+                        wires: vec![wire],
+                    },
+                    vec![index],
+                )),
+            }
+        }
+
+        ports
+            .into_iter()
+            .map(|(mut port, indices)| {
+                let mut bits: Vec<(usize, Wire)> = indices.into_iter().zip(port.wires).collect();
+                bits.sort_unstable();
+                port.wires = bits.into_iter().map(|(_, wire)| wire).collect();
+                port
+            })
+            .collect()
+    }
+}
+
+impl<T: WireValue> GenericCircuit<T> {
+    /// Input or output wires in interface order: port by port, least significant bit first. If
+    /// the circuit doesn't have any ports in that direction, falls back to ascending wire ID.
+    pub fn interface(&self, direction: Direction) -> Vec<Wire> {
+        let wires: Vec<Wire> = self
+            .ports
+            .iter()
+            .filter(|port| port.direction == direction)
+            .flat_map(|port| port.wires.iter().copied())
+            .collect();
+
+        if !wires.is_empty() {
+            return wires;
+        }
+
+        match direction {
+            Direction::Input => self.inputs.iter().copied().sorted().collect(),
+            Direction::Output => self.outputs.iter().copied().sorted().collect(),
+        }
+    }
+
+    /// Local names for each bit of the interface, in the same order as `interface`. Bits of
+    /// multi-bit ports are named like `step[3]`. Without ports, we fall back to the symbol table.
+    pub fn interface_names(&self, direction: Direction) -> Vec<(Wire, Option<String>)> {
+        let names: Vec<(Wire, Option<String>)> = self
+            .ports
+            .iter()
+            .filter(|port| port.direction == direction)
+            .flat_map(|port| {
+                port.wires.iter().enumerate().map(move |(idx, wire)| {
+                    let name = if port.width() == 1 {
+                        port.name.clone()
+                    } else {
+                        format!("{}[{}]", port.name, idx)
+                    };
+                    (*wire, Some(name))
+                })
+            })
+            .collect();
+
+        if !names.is_empty() {
+            return names;
+        }
+
+        self.interface(direction)
+            .into_iter()
+            .map(|wire| (wire, self.symbols.get(wire).map(str::to_string)))
+            .collect()
+    }
+}
+
+/// Splits a bit name like `step[3]` into `("step", 3)`
+fn split_index(name: &str) -> Option<(&str, usize)> {
+    let (base, index) = name.strip_suffix(']')?.rsplit_once('[')?;
+    Some((base, index.parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::generic::ports::{Direction, Port};
+
+    #[test]
+    fn test_from_bit_names() {
+        let bits = [
+            (10, "b[1]"),
+            (11, "a"),
+            (12, "b[0]"),
+            (13, "b[2]"),
+            (14, "c[x]"),
+        ];
+
+        assert_eq!(
+            Port::from_bit_names(bits.iter().copied(), Direction::Input),
+            vec![
+                Port {
+                    name: "b".to_string(),
+                    direction: Direction::Input,
+                    wires: vec![12, 10, 13],
+                },
+                Port {
+                    name: "a".to_string(),
+                    direction: Direction::Input,
+                    wires: vec![11],
+                },
+                Port {
+                    name: "c[x]".to_string(),
+                    direction: Direction::Input,
+                    wires: vec![14],
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,264 @@
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::Debug;
+
+use mcircuit::{Gate, HasIO, Identity, Operation, WireValue};
+use petgraph::graph::NodeIndex;
+use petgraph::Direction as EdgeDirection;
+
+use crate::generic::circuit::GenericCircuit;
+use crate::generic::ports::{Direction, Port};
+use crate::generic::{SVCircuitError, Wire};
+
+impl<T: Debug + WireValue> GenericCircuit<T>
+where
+    Operation<T>: Identity<T>,
+{
+    /// Gates reachable by walking back (`Incoming`) or forward (`Outgoing`) from `start`, along
+    /// with how many gates away from `start` they are. `start` itself is at level 1. Stops
+    /// expanding after `depth` levels, if given.
+    fn reachable(
+        &self,
+        start: impl Iterator<Item = NodeIndex>,
+        direction: EdgeDirection,
+        depth: Option<usize>,
+    ) -> HashMap<NodeIndex, usize> {
+        let mut levels: HashMap<NodeIndex, usize> = HashMap::new();
+        let mut queue: VecDeque<NodeIndex> = VecDeque::new();
+
+        for idx in start {
+            if levels.insert(idx, 1).is_none() {
+                queue.push_back(idx);
+            }
+        }
+
+        while let Some(idx) = queue.pop_front() {
+            let level = levels[&idx];
+            if depth.is_some_and(|depth| level >= depth) {
+                continue;
+            }
+            for next in self.graph.neighbors_directed(idx, direction) {
+                if let Entry::Vacant(entry) = levels.entry(next) {
+                    entry.insert(level + 1);
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        levels
+    }
+
+    /// The gate that drives each wire, read off the graph rather than `_gate_outputs` so that it
+    /// holds whatever passes have been run over the circuit
+    pub(crate) fn drivers(&self) -> HashMap<Wire, NodeIndex> {
+        self.graph
+            .node_indices()
+            .filter_map(|idx| self.graph[idx].dst().map(|dst| (dst, idx)))
+            .collect()
+    }
+
+    /// The gates in the transitive fan-in of `wires`, along with how many levels back from
+    /// `wires` they are
+    pub(crate) fn fan_in(&self, wires: &[Wire], depth: Option<usize>) -> HashMap<NodeIndex, usize> {
+        let drivers = self.drivers();
+        let start = wires.iter().filter_map(|wire| drivers.get(wire).copied());
+        self.reachable(start, EdgeDirection::Incoming, depth)
+    }
+
+    /// Extracts the logic that drives `outputs`: the transitive fan-in of those wires, with the
+    /// inputs it reads as its inputs and `outputs` as its outputs. Wires keep their IDs and names.
+    pub fn slice(&self, outputs: &[Wire]) -> Result<GenericCircuit<T>, SVCircuitError> {
+        let drivers = self.drivers();
+        for wire in outputs {
+            if !self.inputs.contains(wire) && !drivers.contains_key(wire) {
+                return Err(SVCircuitError::UnassignedWire { wire: *wire });
+            }
+        }
+
+        let gates: HashSet<NodeIndex> = self.fan_in(outputs, None).into_keys().collect();
+        self.subcircuit(&gates, outputs.iter().copied().collect())
+    }
+
+    /// Extracts the logic that `inputs` affect: the transitive fan-out of those wires. Any other
+    /// wires read by that logic become inputs too, and the outputs of the original circuit that
+    /// it drives become its outputs. Wires keep their IDs and names.
+    pub fn fanout(&self, inputs: &[Wire]) -> Result<GenericCircuit<T>, SVCircuitError> {
+        let wires: HashSet<Wire> = inputs.iter().copied().collect();
+        let readers = self
+            .graph
+            .node_indices()
+            .filter(|idx| self.graph[*idx].inputs().any(|w| wires.contains(&w)));
+        let gates: HashSet<NodeIndex> = self
+            .reachable(readers, EdgeDirection::Outgoing, None)
+            .into_keys()
+            .collect();
+
+        let drivers = self.drivers();
+        let outputs: HashSet<Wire> = self
+            .outputs
+            .iter()
+            .copied()
+            .filter(|wire| {
+                wires.contains(wire) || drivers.get(wire).is_some_and(|idx| gates.contains(idx))
+            })
+            .collect();
+        self.subcircuit(&gates, outputs)
+    }
+
+    /// Builds a flat circuit out of `gates`, reading any wire they don't drive themselves as an
+    /// input. Ports are rebuilt out of the names of the individual bits.
+    fn subcircuit(
+        &self,
+        gates: &HashSet<NodeIndex>,
+        outputs: HashSet<Wire>,
+    ) -> Result<GenericCircuit<T>, SVCircuitError> {
+        if !self.flat {
+            return Err(SVCircuitError::NotFlat {
+                name: self.name.clone(),
+            });
+        }
+
+        let mut circuit = GenericCircuit {
+            name: self.name.clone(),
+            outputs,
+            ..Default::default()
+        };
+
+        let driven: HashSet<Wire> = gates
+            .iter()
+            .filter_map(|idx| self.graph[*idx].dst())
+            .collect();
+        for idx in self.topo_indices() {
+            if gates.contains(&idx) {
+                let gate = self.graph[idx];
+                circuit
+                    .inputs
+                    .extend(gate.inputs().filter(|w| !driven.contains(w)));
+                circuit._add_gate(gate)?;
+            }
+        }
+        // Outputs that aren't driven by anything in the slice pass an input straight through
+        let passthrough: Vec<Wire> = circuit
+            .outputs
+            .iter()
+            .copied()
+            .filter(|w| !driven.contains(w))
+            .collect();
+        circuit.inputs.extend(passthrough);
+        circuit._build()?;
+
+        // Name each bit after the port it belonged to, falling back on the wire's own name
+        let mut bit_names: HashMap<Wire, String> = HashMap::new();
+        for direction in [Direction::Input, Direction::Output] {
+            for (wire, name) in self.interface_names(direction) {
+                if let Some(name) = name {
+                    bit_names.entry(wire).or_insert(name);
+                }
+            }
+        }
+        let scope = format!("{}.", self.name);
+        let bit_name = |wire: Wire| match (bit_names.get(&wire), self.symbols.get(wire)) {
+            (Some(name), _) => name.clone(),
+            (None, Some(name)) => name.strip_prefix(&scope).unwrap_or(name).to_string(),
+            (None, None) => format!("${}", wire),
+        };
+
+        for (direction, wires) in [
+            (Direction::Input, &circuit.inputs),
+            (Direction::Output, &circuit.outputs),
+        ] {
+            let mut wires: Vec<Wire> = wires.iter().copied().collect();
+            wires.sort_unstable();
+            let names: Vec<(Wire, String)> = wires.into_iter().map(|w| (w, bit_name(w))).collect();
+            circuit.ports.extend(Port::from_bit_names(
+                names.iter().map(|(w, n)| (*w, n.as_str())),
+                direction,
+            ));
+        }
+
+        let mut wires: HashSet<Wire> = circuit.inputs.union(&circuit.outputs).copied().collect();
+        wires.extend(driven);
+        for (wire, name) in self.symbols.iter() {
+            if wires.contains(&wire) {
+                circuit.symbols.insert(wire, name.to_string());
+            }
+        }
+
+        Ok(circuit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{BoolCircuit, Direction, SVCircuitError};
+    use mcircuit::Operation;
+    use std::collections::HashSet;
+    use std::iter::FromIterator;
+
+    /// out[0] = (a ^ b) & c, out[1] = !a, out[2] = d
+    fn example() -> Result<BoolCircuit, SVCircuitError> {
+        let mut circuit = BoolCircuit {
+            name: "top".to_string(),
+            inputs: HashSet::from_iter([2, 3, 4, 5]),
+            outputs: HashSet::from_iter([5, 7, 8]),
+            ..Default::default()
+        };
+        circuit._add_gate(Operation::Add(6, 2, 3))?;
+        circuit._add_gate(Operation::Mul(7, 6, 4))?;
+        circuit._add_gate(Operation::AddConst(8, 2, true))?;
+        circuit.symbols.insert(6, "top.u_xor.y".to_string());
+        circuit._build()?;
+        Ok(circuit)
+    }
+
+    #[test]
+    fn test_slice() -> Result<(), SVCircuitError> {
+        let circuit = example()?;
+
+        let slice = circuit.slice(&[7])?;
+        assert_eq!(slice.inputs, HashSet::from_iter([2, 3, 4]));
+        assert_eq!(slice.outputs, HashSet::from_iter([7]));
+        assert_eq!(slice.ngate(), 2);
+        for x in 0..8u8 {
+            let bits: Vec<bool> = (0..3).map(|i| (x >> i) & 1 == 1).collect();
+            assert_eq!(slice.simulate(&bits)?, vec![(bits[0] ^ bits[1]) & bits[2]]);
+        }
+
+        // Slicing on an internal wire makes it an output, under its own name
+        let inner = circuit.slice(&[6])?;
+        assert_eq!(inner.ngate(), 1);
+        assert_eq!(
+            inner.interface_names(Direction::Output)[0].1.as_deref(),
+            Some("u_xor.y")
+        );
+        assert_eq!(inner.symbols.get(6), Some("top.u_xor.y"));
+
+        // Outputs that are inputs pass straight through
+        let passthrough = circuit.slice(&[5])?;
+        assert_eq!(passthrough.ngate(), 0);
+        assert_eq!(passthrough.simulate(&[true])?, vec![true]);
+
+        assert!(matches!(
+            circuit.slice(&[100]),
+            Err(SVCircuitError::UnassignedWire { wire: 100 })
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fanout() -> Result<(), SVCircuitError> {
+        let circuit = example()?;
+
+        // `b` only affects the first output, but the XOR and AND also read `a` and `c`
+        let fanout = circuit.fanout(&[3])?;
+        assert_eq!(fanout.inputs, HashSet::from_iter([2, 3, 4]));
+        assert_eq!(fanout.outputs, HashSet::from_iter([7]));
+
+        let fanout = circuit.fanout(&[2])?;
+        assert_eq!(fanout.outputs, HashSet::from_iter([7, 8]));
+        assert_eq!(fanout.ngate(), 3);
+
+        Ok(())
+    }
+}
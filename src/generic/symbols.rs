@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+use std::io::{BufRead, Error, ErrorKind, Write};
+
+use itertools::Itertools;
+use mcircuit::parsers::WireHasher;
+
+use crate::generic::Wire;
+
+/// Maps wires back to the names they had in the source design. Names of wires that came from a
+/// subcircuit are qualified with the instance path, e.g. `top.alu_0.add_1.carry[3]`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SymbolTable {
+    names: HashMap<Wire, String>,
+}
+
+impl SymbolTable {
+    /// Looks up the (local) name of each of `wires` in the hasher that produced them
+    pub fn from_hasher(hasher: &WireHasher, wires: impl Iterator<Item = Wire>) -> Self {
+        SymbolTable {
+            names: wires
+                .filter_map(|w| hasher.backref(w).map(|name| (w, name.clone())))
+                .collect(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    pub fn get(&self, wire: Wire) -> Option<&str> {
+        self.names.get(&wire).map(String::as_str)
+    }
+
+    /// Names `wire`, unless it already has a name
+    pub fn insert(&mut self, wire: Wire, name: String) {
+        self.names.entry(wire).or_insert(name);
+    }
+
+    /// Path of the module instance that `wire` belongs to, e.g. `top.alu_0` for
+    /// `top.alu_0.carry[3]`. Local names are assumed not to contain any dots.
+    pub fn scope(&self, wire: Wire) -> Option<&str> {
+        self.get(wire)
+            .and_then(|name| name.rsplit_once('.'))
+            .map(|(scope, _)| scope)
+    }
+
+    /// Describes a wire for use in error messages, e.g. `top.out[0] ($12)`
+    pub fn describe(&self, wire: Wire) -> String {
+        match self.get(wire) {
+            Some(name) => format!("{} (${})", name, wire),
+            None => format!("${}", wire),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Wire, &str)> {
+        self.names.iter().map(|(w, name)| (*w, name.as_str()))
+    }
+
+    /// Prepends `prefix` to the path of every name in the table
+    pub fn prefix(&mut self, prefix: &str) {
+        for name in self.names.values_mut() {
+            *name = format!("{}.{}", prefix, name);
+        }
+    }
+
+    /// Moves every name whose wire appears as a key in `remap` over to the corresponding value.
+    /// Names of wires not in `remap` are left where they are.
+    pub fn translate(&mut self, remap: &HashMap<Wire, Wire>) {
+        let (moved, kept): (Vec<_>, Vec<_>) =
+            self.names.drain().partition(|(w, _)| remap.contains_key(w));
+        self.names.extend(kept);
+        for (w, name) in moved {
+            self.names.insert(remap[&w], name);
+        }
+    }
+
+    /// Drops the names of any wires for which `keep` returns false
+    pub fn retain(&mut self, mut keep: impl FnMut(Wire) -> bool) {
+        self.names.retain(|w, _| keep(*w));
+    }
+
+    /// Writes the table out as one `<wire> <name>` pair per line, in order of ascending wire ID
+    pub fn write<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        for (wire, name) in self.names.iter().sorted() {
+            writeln!(writer, "{} {}", wire, name)?;
+        }
+        Ok(())
+    }
+
+    /// Reads back a table produced by `write`
+    pub fn read<R: BufRead>(reader: R) -> std::io::Result<Self> {
+        let mut table = SymbolTable::default();
+        for line in reader.lines() {
+            let line = line?;
+            let (wire, name) = line
+                .split_once(' ')
+                .and_then(|(wire, name)| Some((wire.parse().ok()?, name)))
+                .ok_or_else(|| {
+                    Error::new(ErrorKind::InvalidData, format!("bad symbol {:?}", line))
+                })?;
+            table.names.insert(wire, name.to_string());
+        }
+        Ok(table)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::generic::symbols::SymbolTable;
+    use std::collections::HashMap;
+    use std::iter::FromIterator;
+
+    #[test]
+    fn test_translate() {
+        let mut table = SymbolTable::default();
+        table.insert(1, "a".to_string());
+        table.insert(2, "b".to_string());
+        table.insert(3, "c".to_string());
+
+        // Wires 1 and 2 swap places, 3 stays put
+        table.translate(&HashMap::from_iter([(1, 2), (2, 1)]));
+
+        assert_eq!(table.get(1), Some("b"));
+        assert_eq!(table.get(2), Some("a"));
+        assert_eq!(table.get(3), Some("c"));
+    }
+
+    #[test]
+    fn test_roundtrip() -> std::io::Result<()> {
+        let mut table = SymbolTable::default();
+        table.insert(7, "carry[3]".to_string());
+        table.insert(2, "in[0]".to_string());
+        table.prefix("add_0");
+        table.prefix("top");
+
+        let mut written: Vec<u8> = Vec::new();
+        table.write(&mut written)?;
+        assert_eq!(
+            String::from_utf8(written.clone()).unwrap(),
+            "2 top.add_0.in[0]\n7 top.add_0.carry[3]\n"
+        );
+        assert_eq!(SymbolTable::read(written.as_slice())?, table);
+
+        Ok(())
+    }
+}
@@ -1,22 +1,41 @@
 #![feature(type_alias_impl_trait)]
 #![feature(impl_trait_in_assoc_type)]
 
+pub mod aiger;
+pub mod blif;
+pub mod bristol;
+#[cfg(unix)]
+pub mod capi;
 mod compositor;
+pub mod connection;
+pub mod container;
+pub mod dot;
+pub mod equiv;
 pub mod export;
 mod generic;
+pub mod optimize;
 pub mod parse;
+#[cfg(feature = "python")]
+mod python;
+pub mod stream;
+pub mod verilog;
+pub mod yosys;
 
 #[macro_use]
 extern crate maplit;
 
-pub use crate::compositor::CircuitCompositor;
+pub use crate::compositor::{CircuitCompositor, CompositeWitness, CompositorError};
 pub use crate::generic::circuit::GenericCircuit;
+pub use crate::generic::eval::{evaluate_program, Evaluable, ProgramOutputs};
 pub use crate::generic::flattener::CircuitFlattener;
-use crate::generic::SVCircuitError;
+pub use crate::generic::ports::{Direction, Port};
+pub use crate::generic::symbols::SymbolTable;
+pub use crate::generic::{SVCircuitError, Wire};
 use mcircuit::parsers::blif::{BlifParser, BlifSubcircuitDesc, CanConstructVariant};
 use mcircuit::parsers::WireHasher;
-use mcircuit::{Gate, Operation, Parse, WireValue};
+use mcircuit::{Gate, HasIO, Operation, Parse, WireValue};
 use std::collections::HashMap;
+use std::convert::{TryFrom, TryInto};
 
 pub const WITNESS_LEN: usize = 656;
 pub type WitnessStep = [bool; WITNESS_LEN];
@@ -25,7 +44,9 @@ pub type Witness = Vec<WitnessStep>;
 pub type ArithCircuit = GenericCircuit<u64>;
 pub type BoolCircuit = GenericCircuit<bool>;
 
-pub fn flatten<T: WireValue>(mut parser: BlifParser<T>) -> (GenericCircuit<T>, String, WireHasher)
+pub fn flatten<T: WireValue>(
+    mut parser: BlifParser<T>,
+) -> Result<(GenericCircuit<T>, String, WireHasher), SVCircuitError>
 where
     BlifParser<T>: CanConstructVariant<T>,
     Operation<T>: Gate<T>,
@@ -39,7 +60,45 @@ where
             top = Some(circuit.name.clone());
         }
         subcircuit_mappings.insert(circuit.name.clone(), circuit.subcircuits.clone());
-        circuits.insert(circuit.name.clone(), circuit.into());
+
+        // BLIF only has individual bits, so we regroup them into ports by name
+        let hasher = &parser.hasher;
+        let bit_names = |wires: &[Wire]| -> Vec<(Wire, String)> {
+            wires
+                .iter()
+                .map(|w| {
+                    (
+                        *w,
+                        hasher.backref(*w).cloned().unwrap_or_else(|| w.to_string()),
+                    )
+                })
+                .collect()
+        };
+        let (inputs, outputs) = (bit_names(&circuit.inputs), bit_names(&circuit.outputs));
+        let name = circuit.name.clone();
+        let mut circuit: GenericCircuit<T> = circuit.try_into()?;
+        circuit.ports = Port::from_bit_names(
+            inputs.iter().map(|(w, n)| (*w, n.as_str())),
+            Direction::Input,
+        );
+        circuit.ports.extend(Port::from_bit_names(
+            outputs.iter().map(|(w, n)| (*w, n.as_str())),
+            Direction::Output,
+        ));
+        circuits.insert(name, circuit);
+    }
+
+    // Record the local name of every wire each circuit touches, so that we can report
+    // hierarchical names once everything has been flattened
+    for (name, circuit) in circuits.iter_mut() {
+        let mut wires: Vec<Wire> = circuit.inputs.union(&circuit.outputs).copied().collect();
+        for gate in circuit.graph.node_weights() {
+            wires.extend(gate.inputs().chain(gate.outputs()));
+        }
+        for sub_desc in subcircuit_mappings.get(name).into_iter().flatten() {
+            wires.extend(sub_desc.connections.iter().map(|(own_wire, _)| *own_wire));
+        }
+        circuit.symbols = SymbolTable::from_hasher(&parser.hasher, wires.into_iter());
     }
 
     let mut keys: Vec<String> = circuits.keys().cloned().collect();
@@ -47,9 +106,12 @@ where
     for name in keys.drain(..) {
         if let Some(sub_mappings) = subcircuit_mappings.get(&name) {
             for sub_desc in sub_mappings {
-                let sub_model = circuits.get(&sub_desc.name).unwrap_or_else(|| {
-                    panic!("No model for required subcircuit {}", sub_desc.name)
-                });
+                let sub_model = circuits.get(&sub_desc.name).ok_or_else(|| {
+                    SVCircuitError::MissingDependency {
+                        dependency: sub_desc.name.clone(),
+                        parent: name.clone(),
+                    }
+                })?;
 
                 let mut in_map: Vec<(usize, usize)> = Vec::new();
                 let mut out_map: Vec<(usize, usize)> = Vec::new();
@@ -72,25 +134,26 @@ where
         }
     }
 
-    let top = top.expect("No circuits found in this BLIF file");
+    let top = top.ok_or(SVCircuitError::NoCircuits)?;
 
-    let mut flattener: CircuitFlattener<T> = (top.clone(), circuits).into();
-
-    let flat = match flattener.flatten() {
-        Ok(flat) => flat,
-        Err(e) => match e {
+    let flat = CircuitFlattener::try_from((top.clone(), circuits))
+        .and_then(|mut flattener| flattener.flatten());
+    if let Err(e) = &flat {
+        // Wire IDs don't mean much to anyone reading the BLIF, so log the names we hashed
+        // them from before handing the error back
+        match e {
             SVCircuitError::UndrivenOutput { name, wires } => {
                 let missing: Vec<String> = wires
                     .iter()
                     .map(|x| parser.hasher.backref(*x).unwrap_or(&x.to_string()).clone())
                     .collect();
 
-                panic!(
+                log::error!(
                     "Circuit '{}' doesn't drive the following wires: {:?} ({} in total)",
                     name,
                     missing,
                     missing.len()
-                )
+                );
             }
             SVCircuitError::UndrivenGate {
                 parent,
@@ -99,19 +162,19 @@ where
             } => {
                 let name: String = parser
                     .hasher
-                    .backref(wire)
+                    .backref(*wire)
                     .unwrap_or(&wire.to_string())
                     .clone();
-                panic!(
+                log::error!(
                     "{}.{} is not driven by a gate, input, or subcircuit",
-                    parent, name
+                    parent,
+                    name
                 );
             }
-            _ => {
-                panic!("An error occurred during flattening: {:?}", e)
-            }
-        },
-    };
+            _ => {}
+        }
+    }
+    let flat = flat?;
 
-    (flat, top, parser.hasher)
+    Ok((flat, top, parser.hasher))
 }
@@ -1,10 +1,11 @@
 use std::fs::File;
-use std::io::{BufReader, BufWriter};
-use std::path::{PathBuf};
+use std::io::BufReader;
+use std::path::PathBuf;
 
 use anyhow::Result;
-use clap::{command, Parser};
+use clap::{command, ArgGroup, Parser};
 
+use sv_circuit::export::ExportOptions;
 use sv_circuit::Witness;
 
 use mcircuit::parsers::blif::BlifParser;
@@ -12,43 +13,63 @@ use mcircuit::Parse;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
+#[clap(group(ArgGroup::new("netlist").required(true).args(["blif", "json"])))]
 struct Cli {
     #[clap(short, long, value_name = "BLIF")]
-    blif: PathBuf,
+    blif: Option<PathBuf>,
+
+    /// Netlist produced by Yosys' `write_json`
+    #[clap(short, long, value_name = "JSON")]
+    json: Option<PathBuf>,
+
+    /// Top module of the JSON netlist, if Yosys didn't mark one
+    #[clap(long, value_name = "MODULE", requires = "json")]
+    top: Option<String>,
 
     #[clap(short, long, value_name = "WITNESS")]
     witness: PathBuf,
 
     #[clap(short, long, value_name = "OUTPUT")]
     output: String,
+
+    /// Annotate the exported circuit with the names of ports and module instances
+    #[clap(long)]
+    annotate: bool,
+
+    /// Free wires in the exported circuit with `@delete` as soon as they're dead
+    #[clap(long)]
+    delete: bool,
 }
 
 /// Rust version of circuit compositor
 fn main() -> Result<()> {
     let cli = Cli::parse();
+    let options = ExportOptions {
+        annotate: cli.annotate,
+        delete: cli.delete,
+    };
 
-    // Parse and process input BLIF.
-    let blif = File::open(cli.blif)
-        .map(BufReader::new)
-        .map(BlifParser::<bool>::new)?;
-    let (circuit, _, _) = sv_circuit::flatten(blif);
+    // Parse and process input netlist.
+    let circuit = match (cli.blif, cli.json) {
+        (Some(blif), _) => {
+            let blif = File::open(blif)
+                .map(BufReader::new)
+                .map(BlifParser::<bool>::new)?;
+            sv_circuit::flatten(blif)?.0
+        }
+        (None, Some(json)) => {
+            let json = File::open(json).map(BufReader::new)?;
+            sv_circuit::yosys::flatten(json, cli.top.as_deref())?.0
+        }
+        (None, None) => unreachable!("clap requires one of --blif or --json"),
+    };
 
     // Parse and process input witness.
     let witness: Witness = File::open(cli.witness)
         .map(BufReader::new)
         .map(sv_circuit::parse::witness)??;
 
-    File::create(format!("{}.circuit", &cli.output))
-        .map(BufWriter::new)
-        .map(|mut f| sv_circuit::export::circuit(&mut f, &circuit, &witness))??;
-
-    File::create(format!("{}.public_input", &cli.output))
-        .map(BufWriter::new)
-        .map(|mut f| sv_circuit::export::public(&mut f))??;
-
-    File::create(format!("{}.private_input", &cli.output))
-        .map(BufWriter::new)
-        .map(|mut f| sv_circuit::export::private(&mut f, &witness))??;
+    sv_circuit::export::files(&cli.output, &circuit, &witness, &options)?;
 
     Ok(())
 }
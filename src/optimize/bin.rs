@@ -1,13 +1,20 @@
-use std::io::{self, BufRead, Write};
+use std::io::{BufRead, Write};
 
 use mcircuit::CombineOperation as Op;
 
-pub fn load_serialized<R: BufRead>(reader: &mut R) -> Result<Vec<Op>, io::Error> {
-    let program: Vec<Op> = bincode::deserialize_from(reader).unwrap();
+use crate::container::{self, ContainerError};
+
+/// Reads a composite program stored in a container by `store_serialized`
+pub fn load_serialized<R: BufRead>(reader: &mut R) -> Result<Vec<Op>, ContainerError> {
+    let (_, program) = container::read(reader)?;
     Ok(program)
 }
 
-pub fn store_serialized<W: Write>(w: &mut W, circuit: &[Op]) -> Result<(), io::Error> {
-    bincode::serialize_into(w, circuit).expect("Failed to write composite circuit");
+pub fn store_serialized<W: Write>(
+    w: &mut W,
+    circuit: &[Op],
+    checksum: bool,
+) -> Result<(), ContainerError> {
+    container::write(w, circuit, checksum)?;
     Ok(())
 }
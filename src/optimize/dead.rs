@@ -1,8 +1,12 @@
-use mcircuit::{Operation, Translatable};
+use std::io::{Read, Seek, Write};
+
 use mcircuit::CombineOperation as Op;
+use mcircuit::{HasIO, Operation};
 
-/// This code assumes that wire indexes are no greater than |circuit|
+use crate::container::ContainerError;
+use crate::stream::{self, ReverseChunks, StreamWriter};
 
+/// This code assumes that wire indexes are no greater than |circuit|
 pub fn eliminate_dead_code(circuit: &[Op], max_wire: usize) -> Vec<Op> {
     // pass 1: do the reference count
     let mut wire_refs: Vec<usize> = vec![0; max_wire];
@@ -49,7 +53,7 @@ pub fn eliminate_dead_code(circuit: &[Op], max_wire: usize) -> Vec<Op> {
         // if the dead gate is an input gate we would need
         // to eliminate part of the witness, might decide to do so in the future...
         match op {
-            CombineOperation::GF2(domain_op) => match *domain_op {
+            Op::GF2(domain_op) => match *domain_op {
                 Operation::Input(_) => {
                     is_dead = false;
                 }
@@ -58,7 +62,7 @@ pub fn eliminate_dead_code(circuit: &[Op], max_wire: usize) -> Vec<Op> {
                 }
                 _ => (),
             },
-            CombineOperation::Z64(domain_op) => match *domain_op {
+            Op::Z64(domain_op) => match *domain_op {
                 Operation::Input(_) => {
                     is_dead = false;
                 }
@@ -78,3 +82,62 @@ pub fn eliminate_dead_code(circuit: &[Op], max_wire: usize) -> Vec<Op> {
 
     new_circuit
 }
+
+/// Whether `op` is kept no matter what reads its outputs. Input and random gates are part of the
+/// witness, and gates without outputs are assertions.
+fn is_always_live(op: &Op) -> bool {
+    match op {
+        Op::GF2(Operation::Input(_) | Operation::Random(_))
+        | Op::Z64(Operation::Input(_) | Operation::Random(_)) => true,
+        _ => op.outputs().next().is_none(),
+    }
+}
+
+/// Streaming equivalent of `eliminate_dead_code`, for programs too large to load. The first pass
+/// walks the chunks backwards to find the live wires, and the second copies over the gates that
+/// drive them. Only the liveness of each wire is held in memory.
+pub fn eliminate_dead_code_stream<R, W>(
+    input: &mut R,
+    output: W,
+    max_wire: usize,
+) -> Result<W, ContainerError>
+where
+    R: Read + Seek,
+    W: Write + Seek,
+{
+    // pass 1 (backwards): a wire is live if a live gate reads it
+    let mut live: Vec<bool> = vec![false; max_wire];
+    let chunks = ReverseChunks::new(&mut *input)?;
+    let checksum = chunks.header().checksum.is_some();
+    for chunk in chunks {
+        for op in chunk?.iter().rev() {
+            if is_always_live(op) || op.outputs().all(|wout| live[wout]) {
+                for win in op.inputs() {
+                    live[win] = true;
+                }
+            }
+        }
+    }
+
+    // pass 2: copy over every gate whose outputs are all live
+    let mut writer = StreamWriter::new(output, checksum)?;
+    let (mut total, mut num_dead) = (0, 0);
+    for op in stream::rewind(input)? {
+        let op = op?;
+        total += 1;
+        if is_always_live(&op) || op.outputs().all(|wout| live[wout]) {
+            writer.push(op)?;
+        } else {
+            num_dead += 1;
+        }
+    }
+
+    log::info!(
+        "dead: {}, total: {} ({:.2}% circuit size reduction)",
+        num_dead,
+        total,
+        ((num_dead as f64) / (total as f64)) * 100.,
+    );
+
+    writer.finish()
+}
@@ -1,8 +1,12 @@
+use std::cmp::{max, min};
+use std::io::{Read, Seek, Write};
+
 use mcircuit::{largest_wires, smallest_wires, CombineOperation, HasIO, Translatable};
 
-pub(crate) fn combine_arithmetic_namespace(
-    mut circuit: Vec<CombineOperation>,
-) -> Vec<CombineOperation> {
+use crate::container::ContainerError;
+use crate::stream::{self, StreamWriter};
+
+pub fn combine_arithmetic_namespace(mut circuit: Vec<CombineOperation>) -> Vec<CombineOperation> {
     let mut bool_portion: Vec<CombineOperation> = Vec::new();
     let mut connection: Vec<CombineOperation> = Vec::new();
     let mut arith_portion: Vec<CombineOperation> = Vec::new();
@@ -34,9 +38,7 @@ pub(crate) fn combine_arithmetic_namespace(
         .collect()
 }
 
-pub(crate) fn isolate_arithmetic_wires(
-    mut circuit: Vec<CombineOperation>,
-) -> Vec<CombineOperation> {
+pub fn isolate_arithmetic_wires(mut circuit: Vec<CombineOperation>) -> Vec<CombineOperation> {
     let mut bool_portion: Vec<CombineOperation> = Vec::new();
     let mut connection: Vec<CombineOperation> = Vec::new();
     let mut arith_portion: Vec<CombineOperation> = Vec::new();
@@ -68,7 +70,78 @@ pub(crate) fn isolate_arithmetic_wires(
         .collect()
 }
 
-pub(crate) fn insert_size_hint(circuit: &mut Vec<CombineOperation>) {
+pub fn insert_size_hint(circuit: &mut Vec<CombineOperation>) {
     let (largest_arith, largest_bool) = largest_wires(circuit);
     circuit.insert(0, CombineOperation::SizeHint(largest_arith, largest_bool));
 }
+
+/// Streaming equivalent of `isolate_arithmetic_wires`. One pass finds the smallest arithmetic
+/// wire, then the boolean gates, connections and arithmetic gates are each copied over in a pass
+/// of their own.
+pub fn isolate_arithmetic_wires_stream<R, W>(input: &mut R, output: W) -> Result<W, ContainerError>
+where
+    R: Read + Seek,
+    W: Write + Seek,
+{
+    let mut smallest_arith = usize::MAX;
+    let mut reader = stream::rewind(input)?;
+    let checksum = reader.header().checksum.is_some();
+    for g in reader.by_ref() {
+        let g = g?;
+        if let CombineOperation::Z64(_) = g {
+            let (smallest, _) = smallest_wires(std::slice::from_ref(&g));
+            smallest_arith = min(smallest_arith, smallest);
+        }
+    }
+
+    let mut writer = StreamWriter::new(output, checksum)?;
+    for g in stream::rewind(input)? {
+        if let g @ CombineOperation::GF2(_) = g? {
+            writer.push(g)?;
+        }
+    }
+    for g in stream::rewind(input)? {
+        if let g @ CombineOperation::B2A(_, _) = g? {
+            writer.push(
+                g.translate(g.inputs(), g.outputs().map(|i| i - (smallest_arith - 2)))
+                    .unwrap(),
+            )?;
+        }
+    }
+    for g in stream::rewind(input)? {
+        if let g @ CombineOperation::Z64(_) = g? {
+            writer.push(
+                g.translate(
+                    g.inputs().map(|i| i - (smallest_arith - 2)),
+                    g.outputs().map(|i| i - (smallest_arith - 2)),
+                )
+                .unwrap(),
+            )?;
+        }
+    }
+    writer.finish()
+}
+
+/// Streaming equivalent of `insert_size_hint`: one pass finds the largest wires, and a second
+/// copies the program over behind the hint
+pub fn insert_size_hint_stream<R, W>(input: &mut R, output: W) -> Result<W, ContainerError>
+where
+    R: Read + Seek,
+    W: Write + Seek,
+{
+    let (mut largest_arith, mut largest_bool) = (0, 0);
+    let mut reader = stream::rewind(input)?;
+    let checksum = reader.header().checksum.is_some();
+    while let Some(chunk) = reader.next_chunk()? {
+        let (arith, bool) = largest_wires(&chunk);
+        largest_arith = max(largest_arith, arith);
+        largest_bool = max(largest_bool, bool);
+    }
+
+    let mut writer = StreamWriter::new(output, checksum)?;
+    writer.push(CombineOperation::SizeHint(largest_arith, largest_bool))?;
+    for g in stream::rewind(input)? {
+        writer.push(g?)?;
+    }
+    writer.finish()
+}
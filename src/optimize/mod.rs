@@ -1,4 +1,5 @@
-pub(crate) mod bin;
-// pub(crate) mod dead;
-pub(crate) mod isolate;
-pub(crate) mod ram;
+pub mod bin;
+pub mod dead;
+pub mod isolate;
+pub mod ram;
+pub mod schedule;
@@ -3,102 +3,142 @@ use std::collections::{BinaryHeap, HashMap};
 
 // since the keys are machine words
 use fnv::FnvBuildHasher;
-use indicatif::{ProgressBar, ProgressIterator};
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressIterator};
 
 use mcircuit::Translatable;
 use mcircuit::{largest_wires, HasIO};
 use mcircuit::{CombineOperation as Op, CombineOperation};
 
+/// Registers of one domain. GF2 and Z64 wires are numbered independently, so each domain gets its
+/// own aliases, free registers and counter.
+struct Allocator {
+    last_use: HashMap<usize, usize, FnvBuildHasher>,
+    alias: HashMap<usize, usize, FnvBuildHasher>, // map[old_wire] -> new_wire
+    free: BinaryHeap<Reverse<usize>>,             // heap of free registers
+    next: usize,                                  // next unused wire label
+    /// Maps each bit of a B2A block to the first wire of the block. Only used for GF2 wires.
+    blocks: HashMap<usize, usize, FnvBuildHasher>,
+}
+
+impl Allocator {
+    fn with_capacity(capacity: usize) -> Self {
+        Allocator {
+            last_use: HashMap::with_capacity_and_hasher(capacity, FnvBuildHasher::default()),
+            alias: HashMap::with_hasher(FnvBuildHasher::default()),
+            free: BinaryHeap::new(),
+            next: 0,
+            blocks: HashMap::with_hasher(FnvBuildHasher::default()),
+        }
+    }
+
+    fn translate(&self, wire: usize) -> usize {
+        *self
+            .alias
+            .get(&wire)
+            .expect("No alias assigned to this wire yet!")
+    }
+
+    /// Frees the register behind `wire` if gate `i` is the last to read it. A gate may read the
+    /// same wire twice, in which case it's already been freed the second time around.
+    fn release(&mut self, wire: usize, i: usize) {
+        if i >= self.last_use[&wire] {
+            if let Some(register) = self.alias.remove(&wire) {
+                self.free.push(Reverse(register));
+            }
+        }
+    }
+
+    /// Assigns a register to `wire`, unless it already has one. The bits of a B2A block must be
+    /// contiguous, so whichever bit is written first claims fresh registers for the whole block.
+    fn assign(&mut self, wire: usize) -> usize {
+        if let Some(base) = self.blocks.remove(&wire) {
+            for w in base..base + 64 {
+                self.alias.insert(w, self.next);
+                self.next += 1;
+                self.blocks.remove(&w);
+            }
+        }
+        let Allocator {
+            alias, free, next, ..
+        } = self;
+        *alias.entry(wire).or_insert_with(|| match free.pop() {
+            Some(Reverse(register)) => register,
+            None => {
+                *next += 1;
+                *next - 1
+            }
+        })
+    }
+}
+
 /// We attempt to allocate registers that are consecutive ("small index") as best we can:
 /// To improve cache locality in the prover.
 ///
 /// This is done by using a binary (min) heap over the free registers
-/// and always allocating the smallest available free register.
-
+/// and always allocating the smallest available free register. Each domain is allocated
+/// separately, so the size hint only accounts for the registers each domain actually uses.
 pub fn register_aliasing(circuit: &[Op], max_wire: usize) -> Vec<Op> {
     log::debug!("Calculating time of last use...");
 
-    let progress = ProgressBar::new(circuit.len() as u64);
-    progress.set_draw_rate(4);
+    let progress = ProgressBar::with_draw_target(
+        Some(circuit.len() as u64),
+        ProgressDrawTarget::stderr_with_hz(4),
+    );
+
+    let mut boolean = Allocator::with_capacity(circuit.len());
+    let mut arithmetic = Allocator::with_capacity(circuit.len());
 
     // pass 1: time of last use
-    let mut last_use = HashMap::with_capacity_and_hasher(circuit.len(), FnvBuildHasher::default());
-    let mut contig_blocks =
-        HashMap::with_capacity_and_hasher(circuit.len(), FnvBuildHasher::default());
     for (i, op) in circuit.iter().enumerate().progress_with(progress) {
+        let readers = match op {
+            CombineOperation::Z64(_) => &mut arithmetic,
+            _ => &mut boolean,
+        };
         for win in op.inputs() {
-            last_use.insert(win, i);
+            readers.last_use.insert(win, i);
         }
         if let CombineOperation::B2A(_z64, gf2_base) = op {
             for win in op.inputs() {
-                contig_blocks.insert(win, *gf2_base);
+                boolean.blocks.insert(win, *gf2_base);
             }
         }
     }
 
     // pass 2: alias registers
-    let mut free: BinaryHeap<Reverse<usize>> = BinaryHeap::new(); // heap of free registers
-    let mut next: usize = 0; // next unused wire label
-    let mut alias = HashMap::with_hasher(FnvBuildHasher::default()); // map[old_wire] -> new_wire
-    let mut max_mem = 0;
-
     let mut new_circuit = Vec::with_capacity(circuit.len());
 
     let mut new_wins = Vec::with_capacity(4);
     let mut new_wouts = Vec::with_capacity(4);
 
     log::debug!("Aliasing registers...");
-    let progress = ProgressBar::new(circuit.len() as u64);
-    progress.set_draw_rate(4);
+    let progress = ProgressBar::with_draw_target(
+        Some(circuit.len() as u64),
+        ProgressDrawTarget::stderr_with_hz(4),
+    );
 
     for (i, op) in circuit.iter().enumerate().progress_with(progress) {
         new_wins.clear();
         new_wouts.clear();
 
-        // translate inputs
+        // B2A gates read boolean wires and write an arithmetic one
+        let (readers, writers) = match op {
+            CombineOperation::GF2(_) => (&mut boolean, None),
+            CombineOperation::Z64(_) => (&mut arithmetic, None),
+            _ => (&mut boolean, Some(&mut arithmetic)),
+        };
+
+        // translate inputs, then try to garbage collect them
         for win in op.inputs() {
-            new_wins.push(
-                *alias
-                    .get(&win)
-                    .expect("No alias assigned to this wire yet!"),
-            );
+            new_wins.push(readers.translate(win));
         }
-
-        // try to garbage collect inputs
         for win in op.inputs() {
-            if i >= last_use[&win] {
-                let new_win = alias.remove(&win).unwrap();
-                free.push(Reverse(new_win));
-            }
+            readers.release(win, i);
         }
 
         // translate outputs
+        let writers = writers.unwrap_or(readers);
         for wout in op.outputs() {
-            // If this wire goes to a B2A gate, we need to translate the entire block of input
-            // wires so that they'll be contiguous
-            if contig_blocks.contains_key(&wout) {
-                let block_base = contig_blocks.remove(&wout).unwrap();
-
-                for w in block_base..block_base + 64 {
-                    alias.insert(w, next);
-                    next += 1;
-                    contig_blocks.remove(&w);
-                }
-            } else {
-                alias.entry(wout).or_insert_with(|| {
-                    let new_wout: usize = match free.pop() {
-                        Some(Reverse(new_wout)) => new_wout,
-                        None => {
-                            let l = next;
-                            next += 1;
-                            l
-                        }
-                    };
-                    new_wout
-                });
-            }
-            max_mem = max(max_mem, alias.len());
-            new_wouts.push(alias[&wout]);
+            new_wouts.push(writers.assign(wout));
         }
 
         // translate instruction
@@ -108,6 +148,14 @@ pub fn register_aliasing(circuit: &[Op], max_wire: usize) -> Vec<Op> {
         }
     }
 
+    // The old size hints describe the old wire numbering
+    for op in new_circuit.iter_mut() {
+        if let CombineOperation::SizeHint(z64_cells, gf2_cells) = op {
+            *z64_cells = arithmetic.next;
+            *gf2_cells = boolean.next;
+        }
+    }
+
     let (largest_arith, largest_bool) = largest_wires(&new_circuit);
     let max_mem = max(largest_bool, largest_arith);
 
@@ -120,3 +168,43 @@ pub fn register_aliasing(circuit: &[Op], max_wire: usize) -> Vec<Op> {
 
     new_circuit
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::evaluate_program;
+    use crate::optimize::ram::register_aliasing;
+    use mcircuit::{largest_wires, CombineOperation, Operation};
+
+    #[test]
+    fn test_composite() {
+        // The bits of the B2A block are computed out of order, starting from one that isn't the
+        // block's base wire, and the arithmetic wires reuse numbers the boolean circuit already
+        // uses
+        let mut program = vec![CombineOperation::SizeHint(3, 128)];
+        program.extend((0..64).map(|w| CombineOperation::GF2(Operation::Input(w))));
+        program.extend((0..64).map(|k| {
+            let bit = (k * 37 + 5) % 64;
+            CombineOperation::GF2(Operation::AddConst(64 + bit, bit, bit % 3 == 0))
+        }));
+        program.extend([
+            CombineOperation::B2A(0, 64),
+            CombineOperation::Z64(Operation::Input(1)),
+            CombineOperation::Z64(Operation::Mul(2, 0, 1)),
+            CombineOperation::Z64(Operation::AssertZero(2)),
+        ]);
+
+        let aliased = register_aliasing(&program, 128);
+        assert_eq!(aliased.len(), program.len());
+        // Each domain is numbered from zero, and sized by what it uses
+        assert_eq!(aliased[0], CombineOperation::SizeHint(2, 128));
+        assert_eq!(largest_wires(&aliased), (1, 127));
+
+        let boolean: Vec<bool> = (0..64).map(|w| w % 5 == 0).collect();
+        for arithmetic in [[0], [3]] {
+            assert_eq!(
+                evaluate_program(&aliased, &boolean, &arithmetic).unwrap(),
+                evaluate_program(&program, &boolean, &arithmetic).unwrap()
+            );
+        }
+    }
+}
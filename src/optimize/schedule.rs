@@ -0,0 +1,378 @@
+//! Reorders gates to keep as few wires live at once as possible. `ram::register_aliasing` can only
+//! reuse a register once the wire in it is dead, so the order gates run in bounds how much memory
+//! the prover needs no matter how registers are allocated.
+//!
+//! Finding the best order is NP-hard, so this is a greedy list scheduler: of the gates whose
+//! inputs are ready, it always runs the one that frees the most wires, net of the wires it
+//! allocates. Ties go to the gate that became ready most recently, which finishes off one cone of
+//! logic before starting on the next.
+
+use std::cmp::max;
+use std::collections::hash_map::Entry;
+use std::collections::{BinaryHeap, HashMap};
+use std::hash::Hash;
+
+// since the keys are machine words
+use fnv::FnvBuildHasher;
+use mcircuit::CombineOperation as Op;
+use mcircuit::{HasIO, Operation};
+
+type Map<K, V> = HashMap<K, V, FnvBuildHasher>;
+
+/// The wires a gate reads and writes, along with the gate that must run before it regardless of
+/// data dependencies (if any)
+pub(crate) struct Node<K> {
+    pub reads: Vec<K>,
+    pub writes: Vec<K>,
+    pub after: Option<usize>,
+}
+
+impl<K: Copy + Eq> Node<K> {
+    pub fn new(reads: impl Iterator<Item = K>, writes: impl Iterator<Item = K>) -> Self {
+        let mut unique: Vec<K> = Vec::with_capacity(2);
+        for wire in reads {
+            if !unique.contains(&wire) {
+                unique.push(wire);
+            }
+        }
+        Node {
+            reads: unique,
+            writes: writes.collect(),
+            after: None,
+        }
+    }
+}
+
+/// Number of gates left to read each wire. Wires in `pinned` stay live to the end, as though some
+/// gate that never runs reads them.
+fn reader_counts<K: Copy + Eq + Hash>(nodes: &[Node<K>], pinned: &[K]) -> Map<K, usize> {
+    let mut remaining: Map<K, usize> = Map::default();
+    for wire in nodes
+        .iter()
+        .flat_map(|node| node.reads.iter())
+        .chain(pinned)
+    {
+        *remaining.entry(*wire).or_insert(0) += 1;
+    }
+    remaining
+}
+
+/// The largest number of wires live at once when the gates run in `order`. Wires that are read
+/// but never written (circuit inputs) are live from the start. A gate's outputs are counted
+/// alongside its inputs, since both are held while it runs.
+pub(crate) fn peak_live<K: Copy + Eq + Hash>(
+    nodes: &[Node<K>],
+    order: &[usize],
+    pinned: &[K],
+) -> usize {
+    let mut remaining = reader_counts(nodes, pinned);
+    let written: Map<K, ()> = nodes
+        .iter()
+        .flat_map(|node| node.writes.iter().map(|w| (*w, ())))
+        .collect();
+    let mut live = remaining
+        .keys()
+        .filter(|wire| !written.contains_key(wire))
+        .count();
+    let mut peak = live;
+
+    for node in order.iter().map(|i| &nodes[*i]) {
+        peak = max(peak, live + node.writes.len());
+        for wire in node.reads.iter() {
+            let count = remaining.get_mut(wire).unwrap();
+            *count -= 1;
+            if *count == 0 {
+                live -= 1;
+            }
+        }
+        live += node
+            .writes
+            .iter()
+            .filter(|wire| remaining.get(wire).is_some_and(|count| *count > 0))
+            .count();
+    }
+    peak
+}
+
+/// A topological order of `nodes` chosen to keep the number of live wires down. Returns `None` if
+/// some wire is written more than once, since the data dependencies are ambiguous then.
+pub(crate) fn order<K: Copy + Eq + Hash>(nodes: &[Node<K>], pinned: &[K]) -> Option<Vec<usize>> {
+    let mut writer: Map<K, usize> = Map::default();
+    for (i, node) in nodes.iter().enumerate() {
+        for wire in node.writes.iter() {
+            if let Entry::Vacant(entry) = writer.entry(*wire) {
+                entry.insert(i);
+            } else {
+                return None;
+            }
+        }
+    }
+
+    // Who reads each wire, and who has to wait on each gate
+    let mut readers: Map<K, Vec<usize>> = Map::default();
+    let mut successors: Vec<Vec<usize>> = vec![Vec::new(); nodes.len()];
+    let mut waiting: Vec<usize> = vec![0; nodes.len()];
+    for (i, node) in nodes.iter().enumerate() {
+        let predecessors = node
+            .reads
+            .iter()
+            .filter_map(|wire| writer.get(wire).copied())
+            .chain(node.after);
+        let mut unique: Vec<usize> = Vec::with_capacity(2);
+        for p in predecessors {
+            if !unique.contains(&p) {
+                unique.push(p);
+            }
+        }
+        for p in unique {
+            successors[p].push(i);
+            waiting[i] += 1;
+        }
+        for wire in node.reads.iter() {
+            readers.entry(*wire).or_default().push(i);
+        }
+    }
+
+    let mut remaining = reader_counts(nodes, pinned);
+    let mut scheduled: Vec<bool> = vec![false; nodes.len()];
+    let score = |i: usize, remaining: &Map<K, usize>| -> isize {
+        let node = &nodes[i];
+        let frees = node.reads.iter().filter(|w| remaining[*w] == 1).count();
+        let allocs = node
+            .writes
+            .iter()
+            .filter(|w| remaining.get(*w).is_some_and(|count| *count > 0))
+            .count();
+        frees as isize - allocs as isize
+    };
+
+    // Scores only go up as other readers of a gate's inputs run, so every ready gate gets a fresh
+    // entry whenever its score changes, and outdated entries are skipped when popped. Entries are
+    // numbered as they're pushed to break ties, starting with the gates that come first.
+    let mut ready: BinaryHeap<(isize, usize, usize)> = BinaryHeap::new();
+    let mut pushed = 0;
+    let mut push = |ready: &mut BinaryHeap<(isize, usize, usize)>, i: usize, score: isize| {
+        ready.push((score, pushed, i));
+        pushed += 1;
+    };
+    for i in (0..nodes.len()).rev().filter(|i| waiting[*i] == 0) {
+        push(&mut ready, i, score(i, &remaining));
+    }
+    let mut order = Vec::with_capacity(nodes.len());
+
+    while let Some((stored, _, i)) = ready.pop() {
+        if scheduled[i] || stored != score(i, &remaining) {
+            continue;
+        }
+        scheduled[i] = true;
+        order.push(i);
+
+        for wire in nodes[i].reads.iter() {
+            let count = remaining.get_mut(wire).unwrap();
+            *count -= 1;
+            // Whoever reads this wire last now frees it, which makes them a better choice
+            if *count == 1 {
+                let last = readers[wire]
+                    .iter()
+                    .find(|r| !scheduled[**r] && waiting[**r] == 0);
+                if let Some(&last) = last {
+                    push(&mut ready, last, score(last, &remaining));
+                }
+            }
+        }
+        for s in successors[i].iter() {
+            waiting[*s] -= 1;
+            if waiting[*s] == 0 {
+                push(&mut ready, *s, score(*s, &remaining));
+            }
+        }
+    }
+
+    Some(order)
+}
+
+/// Wires of a composite program, told apart by domain
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Domain {
+    Boolean(usize),
+    Arithmetic(usize),
+}
+
+fn node(op: &Op) -> Node<Domain> {
+    match op {
+        Op::GF2(gate) => Node::new(
+            gate.inputs().map(Domain::Boolean),
+            gate.outputs().map(Domain::Boolean),
+        ),
+        Op::Z64(gate) => Node::new(
+            gate.inputs().map(Domain::Arithmetic),
+            gate.outputs().map(Domain::Arithmetic),
+        ),
+        _ => Node::new(
+            op.inputs().map(Domain::Boolean),
+            op.outputs().map(Domain::Arithmetic),
+        ),
+    }
+}
+
+/// Input and random gates consume the witness in program order, so they can't be reordered with
+/// respect to others in the same domain
+fn consumes_witness(op: &Op) -> Option<bool> {
+    match op {
+        Op::GF2(Operation::Input(_) | Operation::Random(_)) => Some(true),
+        Op::Z64(Operation::Input(_) | Operation::Random(_)) => Some(false),
+        _ => None,
+    }
+}
+
+fn nodes(circuit: &[Op]) -> Vec<Node<Domain>> {
+    let mut last: [Option<usize>; 2] = [None, None];
+    circuit
+        .iter()
+        .enumerate()
+        .map(|(i, op)| {
+            let mut node = node(op);
+            if let Some(boolean) = consumes_witness(op) {
+                node.after = last[boolean as usize].replace(i);
+            }
+            node
+        })
+        .collect()
+}
+
+/// Peak number of live wires, across both domains, when `circuit` runs in program order
+pub fn peak_live_wires(circuit: &[Op]) -> usize {
+    let order: Vec<usize> = (0..circuit.len()).collect();
+    peak_live(&nodes(circuit), &order, &[])
+}
+
+/// Which section of a composite program an operation belongs to, numbered in the order
+/// `CircuitCompositor::operations` lays them out. Loaders check that sections come in this order.
+fn section(op: &Op) -> u8 {
+    match op {
+        Op::SizeHint(_, _) => 0,
+        Op::GF2(Operation::Input(_) | Operation::Random(_)) => 1,
+        Op::GF2(_) => 2,
+        Op::B2A(_, _) => 3,
+        Op::Z64(Operation::Input(_) | Operation::Random(_)) => 4,
+        Op::Z64(Operation::AssertZero(_)) => 6,
+        Op::Z64(_) => 5,
+    }
+}
+
+/// Reorders the gates of a composite program to reduce its peak number of live wires. Size hints
+/// stay at the front, input and random gates stay in order within each domain, and gates only
+/// move within the run of operations from the same section that they're in, so a program laid
+/// out by `CircuitCompositor::operations` can still be loaded afterwards.
+///
+/// This must run before `ram::register_aliasing`: a program that writes the same wire more than
+/// once is returned unchanged.
+pub fn schedule(circuit: &[Op]) -> Vec<Op> {
+    let (hints, gates): (Vec<Op>, Vec<Op>) = circuit
+        .iter()
+        .partition(|op| matches!(op, Op::SizeHint(_, _)));
+
+    let whole = nodes(&gates);
+    let mut written: Map<Domain, ()> = Map::default();
+    for wire in whole.iter().flat_map(|node| node.writes.iter()) {
+        if written.insert(*wire, ()).is_some() {
+            log::warn!("Not scheduling a program that writes the same wire twice");
+            return circuit.to_vec();
+        }
+    }
+
+    let mut scheduled: Vec<usize> = Vec::with_capacity(gates.len());
+    let mut start = 0;
+    while start < gates.len() {
+        let len = gates[start..]
+            .iter()
+            .take_while(|op| section(op) == section(&gates[start]))
+            .count();
+        let run = &gates[start..start + len];
+        let local = order(&nodes(run), &[]).expect("Wires were checked to be written once");
+        scheduled.extend(local.into_iter().map(|i| start + i));
+        start += len;
+    }
+
+    let identity: Vec<usize> = (0..gates.len()).collect();
+    let before = peak_live(&whole, &identity, &[]);
+    let after = peak_live(&whole, &scheduled, &[]);
+    log::info!(
+        "peak live wires: {} -> {} ({:.2}% reduction)",
+        before,
+        after,
+        (1. - (after as f64) / (before as f64)) * 100.
+    );
+
+    hints
+        .into_iter()
+        .chain(scheduled.into_iter().map(|i| gates[i]))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::optimize::schedule::{peak_live_wires, schedule};
+    use mcircuit::{CombineOperation, Operation};
+
+    /// Computes 0 ^ 1 `2 * pairs` times up front, then combines each pair of copies. Run in
+    /// program order, every intermediate result is live at once.
+    fn breadth_first(pairs: usize) -> Vec<CombineOperation> {
+        let mut program: Vec<CombineOperation> = (0..2)
+            .map(|w| CombineOperation::GF2(Operation::Input(w)))
+            .collect();
+        let first = 2;
+        for k in 0..2 * pairs {
+            program.push(CombineOperation::GF2(Operation::Add(first + k, 0, 1)));
+        }
+        let second = first + 2 * pairs;
+        for k in 0..pairs {
+            program.push(CombineOperation::GF2(Operation::Mul(
+                second + k,
+                first + 2 * k,
+                first + 2 * k + 1,
+            )));
+            program.push(CombineOperation::GF2(Operation::AssertZero(second + k)));
+        }
+        program
+    }
+
+    #[test]
+    fn test_schedule() {
+        let program = breadth_first(8);
+        let scheduled = schedule(&program);
+        assert_eq!(scheduled.len(), program.len());
+        assert!(peak_live_wires(&scheduled) < peak_live_wires(&program));
+
+        // Inputs stay ahead of the gates, in the same order, and every gate runs after what it
+        // reads
+        assert_eq!(scheduled[..2], program[..2]);
+        let inputs = |p: &[CombineOperation]| -> Vec<CombineOperation> {
+            p.iter()
+                .filter(|op| matches!(op, CombineOperation::GF2(Operation::Input(_))))
+                .copied()
+                .collect()
+        };
+        assert_eq!(inputs(&scheduled), inputs(&program));
+        let bits = [true, false];
+        let mut expected = crate::evaluate_program(&program, &bits, &[])
+            .unwrap()
+            .boolean;
+        let mut actual = crate::evaluate_program(&scheduled, &bits, &[])
+            .unwrap()
+            .boolean;
+        expected.sort();
+        actual.sort();
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_rewritten_wires() {
+        let program = vec![
+            CombineOperation::GF2(Operation::Input(0)),
+            CombineOperation::GF2(Operation::AddConst(1, 0, true)),
+            CombineOperation::GF2(Operation::AddConst(1, 1, true)),
+        ];
+        assert_eq!(schedule(&program), program);
+    }
+}
@@ -2,21 +2,36 @@ use anyhow::{anyhow, bail, Result};
 
 use crate::{Witness, WitnessStep};
 use std::convert::TryInto;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::io::BufRead;
 
 fn witness_line(step: String) -> Result<WitnessStep> {
     step.chars()
-        .flat_map(|c| match c {
+        .map(|c| match c {
             '0' => Ok(false),
             '1' => Ok(true),
             _ => bail!("bad bit {:?} in witness!", c),
         })
-        .collect::<Vec<_>>()
+        .collect::<Result<Vec<_>>>()?
         .try_into()
-        .map_err(|v| anyhow!("bad witness step {:?}", v))
+        .map_err(|v: Vec<bool>| anyhow!("bad witness step of length {}", v.len()))
 }
 
-pub fn witness(f: BufReader<File>) -> Result<Witness> {
-    f.lines().flat_map(|l| l.map(witness_line)).collect()
+pub fn witness<R: BufRead>(f: R) -> Result<Witness> {
+    f.lines().map(|l| witness_line(l?)).collect()
+}
+
+/// Reads a string of `0`s and `1`s, such as the boolean witness of a composite circuit.
+/// Whitespace is ignored, so the bits can be split across lines however is convenient.
+pub fn bits<R: BufRead>(f: R) -> Result<Vec<bool>> {
+    let mut bits = Vec::new();
+    for line in f.lines() {
+        for c in line?.chars().filter(|c| !c.is_whitespace()) {
+            match c {
+                '0' => bits.push(false),
+                '1' => bits.push(true),
+                _ => bail!("bad bit {:?} in witness!", c),
+            }
+        }
+    }
+    Ok(bits)
 }
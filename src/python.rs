@@ -0,0 +1,358 @@
+//! Python bindings, built with `--features python`. See `pyproject.toml` for building a wheel
+//! with maturin.
+//!
+//! Circuits are exposed as `BoolCircuit` and `ArithCircuit` classes, composite programs as
+//! `Program`. Errors are raised as `ValueError`, or `OSError` when a file can't be read or written.
+
+// The code generated by `#[pymethods]` converts every error, even those that are already `PyErr`s
+#![allow(clippy::useless_conversion)]
+
+use std::cmp::max;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+
+use mcircuit::parsers::blif::BlifParser;
+use mcircuit::{largest_wires, CombineOperation, Parse};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+use crate::container::ContainerError;
+use crate::dot::{self, DotOptions};
+use crate::export::{self, ExportOptions};
+use crate::optimize::bin::{load_serialized, store_serialized};
+use crate::optimize::dead::eliminate_dead_code;
+use crate::optimize::isolate::{
+    combine_arithmetic_namespace, insert_size_hint, isolate_arithmetic_wires,
+};
+use crate::optimize::ram::register_aliasing;
+use crate::optimize::schedule::{peak_live_wires, schedule};
+use crate::{aiger, blif, bristol, parse, verilog};
+use crate::{ArithCircuit, BoolCircuit, CircuitCompositor};
+
+fn value_error<E: Display>(e: E) -> PyErr {
+    PyValueError::new_err(e.to_string())
+}
+
+/// I/O failures become `OSError`s, like any other failure to read or write a file
+fn container_error(e: ContainerError) -> PyErr {
+    match e {
+        ContainerError::Io(e) => e.into(),
+        e => value_error(e),
+    }
+}
+
+fn create(path: &str) -> PyResult<BufWriter<File>> {
+    Ok(BufWriter::new(File::create(path)?))
+}
+
+/// Methods shared by boolean and arithmetic circuits, along with any specific to `$class`
+macro_rules! circuit_methods {
+    ($class:ident, $value:ty, { $($extra:item)* }) => {
+        #[pymethods]
+        impl $class {
+            #[getter]
+            fn name(&self) -> String {
+                self.inner.name.clone()
+            }
+
+            fn ngate(&self) -> usize {
+                self.inner.ngate()
+            }
+
+            fn nwire(&self) -> usize {
+                self.inner.nwire()
+            }
+
+            fn num_inputs(&self) -> usize {
+                self.inner.inputs.len()
+            }
+
+            fn num_outputs(&self) -> usize {
+                self.inner.outputs.len()
+            }
+
+            /// Number of gates of each type
+            fn gate_count(&self) -> HashMap<String, usize> {
+                self.inner
+                    .gate_count()
+                    .into_iter()
+                    .map(|(gate, count)| (gate.to_string(), count))
+                    .collect()
+            }
+
+            fn prune(&mut self) -> usize {
+                self.inner.prune()
+            }
+
+            fn curry(&mut self) -> usize {
+                self.inner.curry()
+            }
+
+            fn minimize_wires(&mut self) {
+                self.inner.minimize_wires()
+            }
+
+            /// Reorders gates to reduce the peak number of live wires, returning the peak before
+            /// and after
+            fn schedule(&mut self) -> (usize, usize) {
+                self.inner.schedule()
+            }
+
+            /// Outputs for a single input vector, both in order of ascending wire ID
+            fn simulate(&self, inputs: Vec<$value>) -> PyResult<Vec<$value>> {
+                self.inner.simulate(&inputs).map_err(value_error)
+            }
+
+            /// The logic driving `outputs`, as a circuit of its own
+            fn slice(&self, outputs: Vec<usize>) -> PyResult<Self> {
+                let inner = self.inner.slice(&outputs).map_err(value_error)?;
+                Ok(Self { inner })
+            }
+
+            fn write_verilog(&self, path: &str) -> PyResult<()> {
+                verilog::write(&mut create(path)?, &self.inner).map_err(value_error)
+            }
+
+            /// Draws the circuit, or just the logic that drives `cone`
+            #[pyo3(signature = (path, cluster = false, cone = Vec::new(), depth = None))]
+            fn write_dot(
+                &self,
+                path: &str,
+                cluster: bool,
+                cone: Vec<usize>,
+                depth: Option<usize>,
+            ) -> PyResult<()> {
+                let options = DotOptions {
+                    cluster,
+                    cone,
+                    depth,
+                };
+                Ok(dot::write(&mut create(path)?, &self.inner, &options)?)
+            }
+
+            $($extra)*
+        }
+    };
+}
+
+#[pyclass(name = "BoolCircuit")]
+pub struct PyBoolCircuit {
+    inner: BoolCircuit,
+}
+
+#[pyclass(name = "ArithCircuit")]
+pub struct PyArithCircuit {
+    inner: ArithCircuit,
+}
+
+circuit_methods!(PyBoolCircuit, bool, {
+    fn write_blif(&self, path: &str) -> PyResult<()> {
+        blif::write(&mut create(path)?, &self.inner).map_err(value_error)
+    }
+
+    #[pyo3(signature = (path, binary = false))]
+    fn write_aiger(&self, path: &str, binary: bool) -> PyResult<()> {
+        let mut writer = create(path)?;
+        if binary {
+            aiger::write_binary(&mut writer, &self.inner).map_err(value_error)
+        } else {
+            aiger::write_ascii(&mut writer, &self.inner).map_err(value_error)
+        }
+    }
+
+    fn write_bristol(&self, path: &str) -> PyResult<()> {
+        bristol::write(&mut create(path)?, &self.inner).map_err(value_error)
+    }
+
+    /// Writes the same files as `sv-compositor`: `{output}.circuit`, `{output}.symbols`,
+    /// `{output}.public_input` and `{output}.private_input`
+    #[pyo3(signature = (output, witness, annotate = false, delete = false))]
+    fn export(&self, output: &str, witness: &str, annotate: bool, delete: bool) -> PyResult<()> {
+        let witness = parse::witness(BufReader::new(File::open(witness)?)).map_err(value_error)?;
+        let options = ExportOptions { annotate, delete };
+        export::files(output, &self.inner, &witness, &options).map_err(value_error)
+    }
+});
+
+circuit_methods!(PyArithCircuit, u64, {});
+
+/// Flattens the top module of a BLIF file into a boolean circuit
+#[pyfunction]
+fn flatten(path: &str) -> PyResult<PyBoolCircuit> {
+    let parser = BlifParser::<bool>::new(BufReader::new(File::open(path)?));
+    let (inner, _, _) = crate::flatten(parser).map_err(value_error)?;
+    Ok(PyBoolCircuit { inner })
+}
+
+/// Flattens the top module of a BLIF file into an arithmetic circuit
+#[pyfunction]
+fn flatten_arithmetic(path: &str) -> PyResult<PyArithCircuit> {
+    let parser = BlifParser::<u64>::new(BufReader::new(File::open(path)?));
+    let (inner, _, _) = crate::flatten(parser).map_err(value_error)?;
+    Ok(PyArithCircuit { inner })
+}
+
+#[pyclass(name = "Compositor")]
+pub struct PyCompositor {
+    inner: CircuitCompositor,
+}
+
+#[pymethods]
+impl PyCompositor {
+    /// Takes ownership of both circuits, leaving empty ones behind in Python
+    #[new]
+    fn new(mut boolean: PyRefMut<PyBoolCircuit>, mut arithmetic: PyRefMut<PyArithCircuit>) -> Self {
+        PyCompositor {
+            inner: CircuitCompositor::new(
+                std::mem::take(&mut boolean.inner),
+                std::mem::take(&mut arithmetic.inner),
+            ),
+        }
+    }
+
+    /// Reads a composite program written by `sv-composite`, `Compositor.save` or `Program.save`
+    #[staticmethod]
+    fn load(path: &str) -> PyResult<Self> {
+        let reader = BufReader::new(File::open(path)?);
+        let inner = CircuitCompositor::from_reader(reader).map_err(value_error)?;
+        Ok(PyCompositor { inner })
+    }
+
+    fn connect(&mut self, arith_wire: usize, lo: usize) -> PyResult<()> {
+        self.inner.connect(arith_wire, lo).map_err(value_error)
+    }
+
+    fn decompose(&mut self, arith_wire: usize, lo: usize) -> PyResult<()> {
+        self.inner.decompose(arith_wire, lo).map_err(value_error)
+    }
+
+    fn validate(&self) -> PyResult<()> {
+        self.inner.validate().map_err(value_error)
+    }
+
+    fn challenge(&mut self, dst: usize) -> PyResult<()> {
+        self.inner.challenge(dst).map_err(value_error)
+    }
+
+    fn challenge_all(&mut self, dsts: Vec<usize>) -> PyResult<()> {
+        self.inner.challenge_all(dsts).map_err(value_error)
+    }
+
+    /// Generates the composite witness for the given boolean inputs and challenges, and writes
+    /// it to `path`
+    fn write_witness(&self, path: &str, boolean: Vec<bool>, challenges: Vec<u64>) -> PyResult<()> {
+        let witness = self
+            .inner
+            .witness(&boolean, &challenges)
+            .map_err(value_error)?;
+        witness.write(create(path)?).map_err(value_error)
+    }
+
+    /// Validates the compositor and writes its program to `path`, as `sv-composite` does
+    #[pyo3(signature = (path, checksum = false))]
+    fn save(&self, path: &str, checksum: bool) -> PyResult<()> {
+        self.inner
+            .write(create(path)?, checksum)
+            .map_err(value_error)
+    }
+
+    fn gate_stats(&self) -> (usize, usize, usize, usize, usize) {
+        self.inner.gate_stats()
+    }
+
+    fn program(&self) -> PyResult<PyProgram> {
+        self.inner.validate().map_err(value_error)?;
+        Ok(PyProgram {
+            operations: self.inner.operations().collect(),
+        })
+    }
+}
+
+/// A composite program, as consumed by the prover. The optimization passes each return a new
+/// program.
+#[pyclass(name = "Program")]
+pub struct PyProgram {
+    operations: Vec<CombineOperation>,
+}
+
+impl PyProgram {
+    /// One above the largest wire in either domain
+    fn max_wire(&self) -> usize {
+        let (largest_arith, largest_bool) = largest_wires(&self.operations);
+        max(largest_arith, largest_bool) + 1
+    }
+}
+
+#[pymethods]
+impl PyProgram {
+    #[staticmethod]
+    fn load(path: &str) -> PyResult<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        Ok(PyProgram {
+            operations: load_serialized(&mut reader).map_err(container_error)?,
+        })
+    }
+
+    #[pyo3(signature = (path, checksum = false))]
+    fn save(&self, path: &str, checksum: bool) -> PyResult<()> {
+        store_serialized(&mut create(path)?, &self.operations, checksum).map_err(container_error)
+    }
+
+    fn __len__(&self) -> usize {
+        self.operations.len()
+    }
+
+    fn eliminate_dead_code(&self) -> Self {
+        PyProgram {
+            operations: eliminate_dead_code(&self.operations, self.max_wire()),
+        }
+    }
+
+    /// Peak number of live wires when the program runs in order
+    fn peak_live_wires(&self) -> usize {
+        peak_live_wires(&self.operations)
+    }
+
+    fn schedule(&self) -> Self {
+        PyProgram {
+            operations: schedule(&self.operations),
+        }
+    }
+
+    fn register_aliasing(&self) -> Self {
+        PyProgram {
+            operations: register_aliasing(&self.operations, self.max_wire()),
+        }
+    }
+
+    fn isolate_arithmetic_wires(&self) -> Self {
+        PyProgram {
+            operations: isolate_arithmetic_wires(self.operations.clone()),
+        }
+    }
+
+    fn combine_arithmetic_namespace(&self) -> Self {
+        PyProgram {
+            operations: combine_arithmetic_namespace(self.operations.clone()),
+        }
+    }
+
+    fn insert_size_hint(&self) -> Self {
+        let mut operations = self.operations.clone();
+        insert_size_hint(&mut operations);
+        PyProgram { operations }
+    }
+}
+
+#[pymodule]
+fn sv_circuit(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(flatten, m)?)?;
+    m.add_function(wrap_pyfunction!(flatten_arithmetic, m)?)?;
+    m.add_class::<PyBoolCircuit>()?;
+    m.add_class::<PyArithCircuit>()?;
+    m.add_class::<PyCompositor>()?;
+    m.add_class::<PyProgram>()?;
+    Ok(())
+}
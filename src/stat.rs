@@ -2,11 +2,13 @@ use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io::BufReader;
 
+use anyhow::{Context, Result};
 use clap::{App, Arg};
 
 use mcircuit::{CombineOperation, HasIO, Identity, Operation};
+use sv_circuit::container;
 
-fn main() {
+fn main() -> Result<()> {
     let matches = App::new("sv-bin-stat")
         .version(env!("CARGO_PKG_VERSION"))
         .author("Eric Hennenfent <eric.hennenfent@trailofbits.com>")
@@ -33,11 +35,11 @@ fn main() {
         .value_of("circuit")
         .expect("No circuit file provided");
 
-    let reader = BufReader::new(File::open(circuit_file).expect("Failed to open circuit file"));
+    let reader = BufReader::new(File::open(circuit_file).context("Failed to open circuit file")?);
 
-    let gates: Vec<CombineOperation> = bincode::deserialize_from(reader).expect(
-        "Failed to deserialize composite circuit. Is this binary file in the correct format?",
-    );
+    let (header, gates) = container::read(reader)
+        .with_context(|| format!("Failed to read composite circuit from {}", circuit_file))?;
+    log::debug!("Written by {}", header.generator);
 
     match matches.value_of("operation").unwrap() {
         "gates" => {
@@ -72,6 +74,8 @@ fn main() {
             log::debug!("Count options are: gates, wires")
         }
     }
+
+    Ok(())
 }
 
 fn gate_to_str(gate: &CombineOperation) -> &str {
@@ -0,0 +1,331 @@
+//! Reads and writes containers a chunk at a time, for composite programs too large to hold in
+//! memory. Only one chunk needs to be in memory at once. See `container` for the layout.
+
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use mcircuit::CombineOperation;
+
+use crate::container::{
+    self, Chunks, ContainerError, Header, Summary, DEFAULT_CHUNK_SIZE, PREAMBLE_LEN,
+};
+
+fn read_u64<R: Read>(reader: &mut R) -> Result<u64, ContainerError> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+/// Writes a container a chunk at a time. The header can't be known until the last operation is
+/// in, so a placeholder goes out first and `finish` goes back to fill it in. `finish` must be
+/// called to close the container; one that isn't finished can't be read back.
+pub struct StreamWriter<W: Write + Seek> {
+    writer: W,
+    chunk: Vec<CombineOperation>,
+    chunk_size: usize,
+    chunks: Chunks,
+    summary: Summary,
+    with_checksum: bool,
+}
+
+impl<W: Write + Seek> StreamWriter<W> {
+    pub fn new(writer: W, with_checksum: bool) -> Result<Self, ContainerError> {
+        Self::with_chunk_size(writer, with_checksum, DEFAULT_CHUNK_SIZE)
+    }
+
+    pub fn with_chunk_size(
+        mut writer: W,
+        with_checksum: bool,
+        chunk_size: usize,
+    ) -> Result<Self, ContainerError> {
+        let summary = Summary::default();
+        let placeholder = summary.header(with_checksum);
+        container::write_header(&mut writer, &placeholder)?;
+        Ok(StreamWriter {
+            writer,
+            chunk: Vec::with_capacity(chunk_size),
+            chunk_size: chunk_size.max(1),
+            chunks: Chunks::new(PREAMBLE_LEN + placeholder.encoded_len()?),
+            summary,
+            with_checksum,
+        })
+    }
+
+    pub fn push(&mut self, op: CombineOperation) -> Result<(), ContainerError> {
+        self.chunk.push(op);
+        if self.chunk.len() >= self.chunk_size {
+            self.flush_chunk()?;
+        }
+        Ok(())
+    }
+
+    pub fn extend<I: IntoIterator<Item = CombineOperation>>(
+        &mut self,
+        ops: I,
+    ) -> Result<(), ContainerError> {
+        ops.into_iter().try_for_each(|op| self.push(op))
+    }
+
+    fn flush_chunk(&mut self) -> Result<(), ContainerError> {
+        if self.chunk.is_empty() {
+            return Ok(());
+        }
+        let encoded = bincode::serialize(&self.chunk)?;
+        self.summary.add(&self.chunk);
+        self.summary.hash(&encoded);
+        self.chunks.write(&mut self.writer, &encoded)?;
+        self.chunk.clear();
+        Ok(())
+    }
+
+    /// Writes out the last chunk and the index, then the real header over the placeholder.
+    /// Returns the underlying writer, positioned at the end of the container.
+    pub fn finish(mut self) -> Result<W, ContainerError> {
+        self.flush_chunk()?;
+        self.chunks.finish(&mut self.writer)?;
+        let end = self.writer.stream_position()?;
+
+        let header = self.summary.header(self.with_checksum);
+        self.writer.seek(SeekFrom::Start(0))?;
+        container::write_header(&mut self.writer, &header)?;
+        self.writer.seek(SeekFrom::Start(end))?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Reads a container front to back, one chunk at a time. Iterating yields each operation in turn.
+/// Once the last chunk is in, the operations are checked against the header, as `container::read`
+/// does, and a mismatch is the last item.
+pub struct StreamReader<R: Read> {
+    reader: R,
+    header: Header,
+    chunk: std::vec::IntoIter<CombineOperation>,
+    position: u64,
+    summary: Summary,
+    chunks: usize,
+    done: bool,
+}
+
+impl<R: Read> StreamReader<R> {
+    /// Reads the header, leaving the reader at the first chunk
+    pub fn new(mut reader: R) -> Result<Self, ContainerError> {
+        let header = container::read_header(&mut reader)?;
+        let position = PREAMBLE_LEN + header.encoded_len()?;
+        Ok(StreamReader {
+            reader,
+            header,
+            chunk: Vec::new().into_iter(),
+            position,
+            summary: Summary::default(),
+            chunks: 0,
+            done: false,
+        })
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// The next chunk of operations, or `None` after the last one
+    pub fn next_chunk(&mut self) -> Result<Option<Vec<CombineOperation>>, ContainerError> {
+        if self.done {
+            return Ok(None);
+        }
+        let offset = self.position;
+        let len = read_u64(&mut self.reader)?;
+        if len == 0 {
+            self.done = true;
+            self.finish()?;
+            return Ok(None);
+        }
+
+        let mut encoded = Vec::new();
+        (&mut self.reader).take(len).read_to_end(&mut encoded)?;
+        if (encoded.len() as u64) < len {
+            return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+        }
+        let chunk: Vec<CombineOperation> =
+            bincode::deserialize(&encoded).map_err(|source| ContainerError::Operations {
+                generator: self.header.generator.clone(),
+                offset,
+                source,
+            })?;
+        self.summary.add(&chunk);
+        self.summary.hash(&encoded);
+        self.chunks += 1;
+        self.position += 8 + len;
+        Ok(Some(chunk))
+    }
+
+    /// Reads the index past the last chunk and checks everything against the header
+    fn finish(&mut self) -> Result<(), ContainerError> {
+        let offsets: Vec<u64> =
+            bincode::deserialize_from(&mut self.reader).map_err(ContainerError::Index)?;
+        read_u64(&mut self.reader)?;
+        self.header
+            .verify(&self.summary.header(self.header.checksum.is_some()))?;
+        if offsets.len() != self.chunks {
+            return Err(ContainerError::CountMismatch {
+                what: "chunks",
+                expected: offsets.len(),
+                actual: self.chunks,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Iterator for StreamReader<R> {
+    type Item = Result<CombineOperation, ContainerError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(op) = self.chunk.next() {
+                return Some(Ok(op));
+            }
+            match self.next_chunk() {
+                Ok(Some(chunk)) => self.chunk = chunk.into_iter(),
+                Ok(None) => return None,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+/// Reads the chunks of a container from last to first, using the index at its end. The operations
+/// within each chunk are still in program order. Nothing is checked against the header, so passes
+/// that walk a container backwards should also read it forwards at some point.
+pub struct ReverseChunks<R: Read + Seek> {
+    reader: R,
+    header: Header,
+    offsets: Vec<u64>,
+}
+
+impl<R: Read + Seek> ReverseChunks<R> {
+    pub fn new(mut reader: R) -> Result<Self, ContainerError> {
+        reader.seek(SeekFrom::Start(0))?;
+        let header = container::read_header(&mut reader)?;
+        reader.seek(SeekFrom::End(-8))?;
+        let index_offset = read_u64(&mut reader)?;
+        reader.seek(SeekFrom::Start(index_offset))?;
+        let offsets = bincode::deserialize_from(&mut reader).map_err(ContainerError::Index)?;
+        Ok(ReverseChunks {
+            reader,
+            header,
+            offsets,
+        })
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    fn read_chunk(&mut self, offset: u64) -> Result<Vec<CombineOperation>, ContainerError> {
+        self.reader.seek(SeekFrom::Start(offset))?;
+        let len = read_u64(&mut self.reader)?;
+        bincode::deserialize_from((&mut self.reader).take(len)).map_err(|source| {
+            ContainerError::Operations {
+                generator: self.header.generator.clone(),
+                offset,
+                source,
+            }
+        })
+    }
+}
+
+impl<R: Read + Seek> Iterator for ReverseChunks<R> {
+    type Item = Result<Vec<CombineOperation>, ContainerError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let offset = self.offsets.pop()?;
+        let chunk = self.read_chunk(offset);
+        if chunk.is_err() {
+            self.offsets.clear();
+        }
+        Some(chunk)
+    }
+}
+
+/// Rewinds `reader` and reads it from the start. Passes that need more than one look at the
+/// program call this once per pass.
+pub fn rewind<R: Read + Seek>(reader: &mut R) -> Result<StreamReader<&mut R>, ContainerError> {
+    reader.seek(SeekFrom::Start(0))?;
+    StreamReader::new(reader)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::container::{self, ContainerError};
+    use crate::stream::*;
+    use mcircuit::Operation;
+    use std::io::Cursor;
+
+    fn program(len: usize) -> Vec<CombineOperation> {
+        (0..len)
+            .map(|i| CombineOperation::GF2(Operation::AddConst(i + 1, i, i % 2 == 0)))
+            .collect()
+    }
+
+    #[test]
+    fn test_roundtrip() -> Result<(), ContainerError> {
+        for (len, chunks) in [(0, 0), (1, 1), (6, 2), (7, 3), (20, 7)] {
+            let mut writer = StreamWriter::with_chunk_size(Cursor::new(Vec::new()), true, 3)?;
+            writer.extend(program(len))?;
+            let mut stream = writer.finish()?;
+
+            let read: Vec<CombineOperation> = rewind(&mut stream)?.collect::<Result<_, _>>()?;
+            assert_eq!(read, program(len));
+
+            // The whole container reader reads what the streaming writer wrote, and vice versa
+            let (header, operations) = container::read(stream.get_ref().as_slice())?;
+            assert_eq!(operations, program(len));
+            assert!(header.checksum.is_some());
+            let mut whole = Cursor::new(Vec::new());
+            container::write(&mut whole, &program(len), false)?;
+            let read: Vec<CombineOperation> = rewind(&mut whole)?.collect::<Result<_, _>>()?;
+            assert_eq!(read, program(len));
+
+            let reversed: Vec<Vec<CombineOperation>> =
+                ReverseChunks::new(&mut stream)?.collect::<Result<_, _>>()?;
+            assert_eq!(reversed.len(), chunks);
+            assert!(reversed.iter().all(|chunk| chunk.len() <= 3));
+            let unreversed: Vec<CombineOperation> = reversed.into_iter().rev().flatten().collect();
+            assert_eq!(unreversed, program(len));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_rejects() -> Result<(), ContainerError> {
+        let mut writer = StreamWriter::with_chunk_size(Cursor::new(Vec::new()), false, 4)?;
+        writer.extend(program(10))?;
+        let written = writer.finish()?.into_inner();
+
+        let mut bad_magic = written.clone();
+        bad_magic[0] = b'X';
+        assert!(matches!(
+            StreamReader::new(bad_magic.as_slice()),
+            Err(ContainerError::BadMagic { .. })
+        ));
+
+        // Cut off in the middle of the first chunk
+        let header = container::read_header(&mut written.as_slice())?;
+        let offset = PREAMBLE_LEN + header.encoded_len()?;
+        let truncated = &written[..offset as usize + 12];
+        let read: Result<Vec<_>, _> = StreamReader::new(truncated)?.collect();
+        assert!(matches!(read, Err(ContainerError::Io(_))));
+
+        // A chunk that doesn't decode
+        let mut garbled = written.clone();
+        garbled[offset as usize + 8..offset as usize + 16].copy_from_slice(&[0xff; 8]);
+        let read: Result<Vec<_>, _> = StreamReader::new(garbled.as_slice())?.collect();
+        assert!(matches!(
+            read,
+            Err(ContainerError::Operations { offset: o, .. }) if o == offset
+        ));
+        Ok(())
+    }
+}
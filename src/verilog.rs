@@ -0,0 +1,364 @@
+//! Writes flat circuits out as structural Verilog, so that they can be dropped into an existing
+//! testbench and simulated with a standard HDL simulator.
+//!
+//! Each gate becomes a single continuous assignment to a wire of its own. Ports keep their names
+//! and widths; multi-bit ports of arithmetic circuits are packed into one vector, 64 bits per
+//! element.
+
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
+
+use mcircuit::{Operation, WireValue};
+use thiserror::Error;
+
+use crate::generic::circuit::GenericCircuit;
+use crate::generic::ports::{Direction, Port};
+use crate::generic::Wire;
+use crate::SVCircuitError;
+
+#[derive(Error, Debug)]
+pub enum VerilogError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("Gate {0} can't be represented in Verilog")]
+    UnsupportedGate(String),
+
+    #[error(transparent)]
+    Circuit(#[from] SVCircuitError),
+}
+
+/// How the values carried by a circuit's wires are represented in Verilog. Boolean circuits
+/// compute over GF(2), so addition is XOR and multiplication is AND; arithmetic circuits use
+/// 64-bit vectors, which wrap around just like Z_2^64.
+pub trait VerilogValue: WireValue {
+    /// Width of a single wire, in bits
+    const WIDTH: usize;
+    const ADD: &'static str;
+    const SUB: &'static str;
+    const MUL: &'static str;
+
+    /// Formats a constant as a sized Verilog literal
+    fn literal(self) -> String;
+}
+
+impl VerilogValue for bool {
+    const WIDTH: usize = 1;
+    const ADD: &'static str = "^";
+    const SUB: &'static str = "^";
+    const MUL: &'static str = "&";
+
+    fn literal(self) -> String {
+        format!("1'b{}", self as u8)
+    }
+}
+
+impl VerilogValue for u64 {
+    const WIDTH: usize = 64;
+    const ADD: &'static str = "+";
+    const SUB: &'static str = "-";
+    const MUL: &'static str = "*";
+
+    fn literal(self) -> String {
+        format!("64'd{}", self)
+    }
+}
+
+const KEYWORDS: &[&str] = &[
+    "always",
+    "and",
+    "assign",
+    "begin",
+    "buf",
+    "case",
+    "default",
+    "else",
+    "end",
+    "endcase",
+    "endfunction",
+    "endmodule",
+    "for",
+    "function",
+    "if",
+    "initial",
+    "inout",
+    "input",
+    "integer",
+    "module",
+    "nand",
+    "nor",
+    "not",
+    "or",
+    "output",
+    "parameter",
+    "reg",
+    "signed",
+    "wire",
+    "xnor",
+    "xor",
+];
+
+/// Turns a name into a Verilog identifier, escaping it if it isn't already a valid one
+fn identifier(name: &str) -> String {
+    let mut chars = name.chars();
+    let simple = matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$');
+    if simple && !KEYWORDS.contains(&name) {
+        name.to_string()
+    } else {
+        // Escaped identifiers run until the next whitespace
+        let escaped: String = name
+            .chars()
+            .map(|c| if c.is_whitespace() { '_' } else { c })
+            .collect();
+        format!("\\{} ", escaped)
+    }
+}
+
+/// Ports in one direction. Circuits that don't record their ports get a single port holding every
+/// wire in that direction.
+fn ports<T: WireValue>(circuit: &GenericCircuit<T>, direction: Direction) -> Vec<Port> {
+    let ports: Vec<Port> = circuit
+        .ports
+        .iter()
+        .filter(|port| port.direction == direction)
+        .cloned()
+        .collect();
+    if !ports.is_empty() {
+        return ports;
+    }
+
+    let wires = circuit.interface(direction);
+    if wires.is_empty() {
+        return Vec::new();
+    }
+    vec![Port {
+        name: match direction {
+            Direction::Input => "in".to_string(),
+            Direction::Output => "out".to_string(),
+        },
+        direction,
+        wires,
+    }]
+}
+
+/// Verilog expression for each bit of a port
+fn port_bits<T: VerilogValue>(port: &Port) -> Vec<String> {
+    let name = identifier(&port.name);
+    (0..port.width())
+        .map(|idx| match (port.width(), T::WIDTH) {
+            (1, _) => name.clone(),
+            (_, 1) => format!("{}[{}]", name, idx),
+            (_, width) => format!("{}[{} +: {}]", name, idx * width, width),
+        })
+        .collect()
+}
+
+fn range(width: usize) -> String {
+    if width == 1 {
+        String::new()
+    } else {
+        format!("[{}:0] ", width - 1)
+    }
+}
+
+/// Writes a flat circuit as a Verilog module with the same name and ports
+pub fn write<T: VerilogValue, W: Write>(
+    writer: &mut W,
+    circuit: &GenericCircuit<T>,
+) -> Result<(), VerilogError> {
+    if !circuit.flat {
+        return Err(SVCircuitError::NotFlat {
+            name: circuit.name.clone(),
+        }
+        .into());
+    }
+
+    let inputs = ports(circuit, Direction::Input);
+    let outputs = ports(circuit, Direction::Output);
+
+    // Inputs are read straight from their ports, everything else gets a wire named after its ID
+    let mut names: HashMap<Wire, String> = HashMap::new();
+    for port in inputs.iter() {
+        for (wire, bit) in port.wires.iter().zip(port_bits::<T>(port)) {
+            names.entry(*wire).or_insert(bit);
+        }
+    }
+    let taken: HashSet<String> = inputs
+        .iter()
+        .chain(outputs.iter())
+        .map(|port| identifier(&port.name))
+        .collect();
+    let local_name = |wire: Wire| {
+        let mut name = format!("w{}", wire);
+        while taken.contains(&name) {
+            name.push('_');
+        }
+        name
+    };
+
+    let mut assignments: Vec<(String, String)> = Vec::new();
+    for gate in circuit.topo_iter() {
+        let name = |wire: &Wire| {
+            names
+                .get(wire)
+                .cloned()
+                .ok_or(SVCircuitError::UnassignedWire { wire: *wire })
+        };
+        let (dst, expression) = match gate {
+            Operation::Add(o, a, b) => (o, format!("{} {} {}", name(a)?, T::ADD, name(b)?)),
+            Operation::Sub(o, a, b) => (o, format!("{} {} {}", name(a)?, T::SUB, name(b)?)),
+            Operation::Mul(o, a, b) => (o, format!("{} {} {}", name(a)?, T::MUL, name(b)?)),
+            Operation::AddConst(o, i, c) => (o, format!("{} {} {}", name(i)?, T::ADD, c.literal())),
+            Operation::SubConst(o, i, c) => (o, format!("{} {} {}", name(i)?, T::SUB, c.literal())),
+            Operation::MulConst(o, i, c) => (o, format!("{} {} {}", name(i)?, T::MUL, c.literal())),
+            Operation::Const(o, c) => (o, c.literal()),
+            Operation::Input(_) | Operation::Random(_) | Operation::AssertZero(_) => {
+                return Err(VerilogError::UnsupportedGate(format!("{:?}", gate)))
+            }
+        };
+        let local = local_name(*dst);
+        names.insert(*dst, local.clone());
+        assignments.push((local, expression));
+    }
+
+    writeln!(writer, "// Generated by sv_circuit")?;
+    let declarations: Vec<String> = inputs
+        .iter()
+        .map(|port| ("input", port))
+        .chain(outputs.iter().map(|port| ("output", port)))
+        .map(|(direction, port)| {
+            format!(
+                "{} wire {}{}",
+                direction,
+                range(port.width() * T::WIDTH),
+                identifier(&port.name)
+            )
+        })
+        .collect();
+    writeln!(writer, "module {}(", identifier(&circuit.name))?;
+    writeln!(writer, "    {}", declarations.join(",\n    "))?;
+    writeln!(writer, ");")?;
+    writeln!(writer)?;
+
+    for (local, _) in assignments.iter() {
+        writeln!(writer, "    wire {}{};", range(T::WIDTH), local)?;
+    }
+    writeln!(writer)?;
+    for (local, expression) in assignments.iter() {
+        writeln!(writer, "    assign {} = {};", local, expression)?;
+    }
+    writeln!(writer)?;
+
+    for port in outputs.iter() {
+        for (wire, bit) in port.wires.iter().zip(port_bits::<T>(port)) {
+            let source = names
+                .get(wire)
+                .ok_or(SVCircuitError::UnassignedWire { wire: *wire })?;
+            writeln!(writer, "    assign {} = {};", bit, source)?;
+        }
+    }
+    writeln!(writer, "endmodule")?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::generic::circuit::GenericCircuit;
+    use crate::verilog::{identifier, write, VerilogError};
+    use crate::{BoolCircuit, Direction, Port};
+    use mcircuit::Operation;
+    use std::collections::HashSet;
+    use std::iter::FromIterator;
+
+    #[test]
+    fn test_identifier() {
+        assert_eq!(identifier("step_3"), "step_3");
+        assert_eq!(identifier("top.i0.out"), "\\top.i0.out ");
+        assert_eq!(identifier("3d"), "\\3d ");
+        assert_eq!(identifier("wire"), "\\wire ");
+    }
+
+    #[test]
+    fn test_write_bool() -> Result<(), VerilogError> {
+        let mut circuit = BoolCircuit {
+            name: "check".to_string(),
+            inputs: HashSet::from_iter([2, 3, 4]),
+            outputs: HashSet::from_iter([3, 6]),
+            ports: vec![
+                Port {
+                    name: "step".to_string(),
+                    direction: Direction::Input,
+                    wires: vec![2, 3],
+                },
+                Port {
+                    name: "en".to_string(),
+                    direction: Direction::Input,
+                    wires: vec![4],
+                },
+                Port {
+                    name: "out".to_string(),
+                    direction: Direction::Output,
+                    wires: vec![6, 3],
+                },
+            ],
+            ..Default::default()
+        };
+        circuit._add_gate(Operation::Add(5, 2, 3))?;
+        circuit._add_gate(Operation::Mul(6, 5, 4))?;
+        circuit._build()?;
+
+        let mut written: Vec<u8> = Vec::new();
+        write(&mut written, &circuit)?;
+        let text = String::from_utf8(written).unwrap();
+
+        assert!(text.contains("module check(\n    input wire [1:0] step,\n    input wire en,\n    output wire [1:0] out\n);"), "{}", text);
+        assert!(
+            text.contains("    assign w5 = step[0] ^ step[1];\n"),
+            "{}",
+            text
+        );
+        assert!(text.contains("    assign w6 = w5 & en;\n"), "{}", text);
+        assert!(
+            text.contains("    assign out[0] = w6;\n    assign out[1] = step[1];\n"),
+            "{}",
+            text
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_arith() -> Result<(), VerilogError> {
+        let mut circuit: GenericCircuit<u64> = GenericCircuit {
+            name: "arith".to_string(),
+            inputs: HashSet::from_iter([0, 1]),
+            outputs: HashSet::from_iter([3]),
+            ..Default::default()
+        };
+        circuit._add_gate(Operation::Mul(2, 0, 1))?;
+        circuit._add_gate(Operation::SubConst(3, 2, 7))?;
+        circuit._build()?;
+
+        let mut written: Vec<u8> = Vec::new();
+        write(&mut written, &circuit)?;
+        let text = String::from_utf8(written).unwrap();
+
+        assert!(
+            text.contains("input wire [127:0] in,\n    output wire [63:0] out\n"),
+            "{}",
+            text
+        );
+        assert!(text.contains("    wire [63:0] w2;\n"), "{}", text);
+        assert!(
+            text.contains("    assign w2 = in[0 +: 64] * in[64 +: 64];\n"),
+            "{}",
+            text
+        );
+        assert!(text.contains("    assign w3 = w2 - 64'd7;\n"), "{}", text);
+        assert!(text.contains("    assign out = w3;\n"), "{}", text);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,641 @@
+//! Front end for netlists produced by Yosys' `write_json`. Unlike BLIF, these keep the names,
+//! order and widths of each module's ports, as well as the names of module instances.
+//!
+//! Only boolean circuits are supported, and designs must be mapped down to simple gates first,
+//! e.g. with `techmap; opt` or `abc -g AND,NAND,OR,NOR,XOR,XNOR,ANDNOT,ORNOT,MUX`.
+
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::Read;
+use std::marker::PhantomData;
+
+use mcircuit::{Identity, Operation};
+use serde::de::{MapAccess, Visitor};
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::generic::ports::{Direction, Port};
+use crate::generic::Wire;
+use crate::{BoolCircuit, CircuitFlattener, SVCircuitError};
+
+#[derive(Error, Debug)]
+pub enum YosysError {
+    #[error("Failed to parse JSON netlist: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Cell '{cell}' in module '{module}' has unsupported type {kind}")]
+    UnsupportedCell {
+        module: String,
+        cell: String,
+        kind: String,
+    },
+
+    #[error("Cell '{cell}' in module '{module}' has no single-bit connection to pin {pin}")]
+    MissingConnection {
+        module: String,
+        cell: String,
+        pin: String,
+    },
+
+    #[error("Port '{port}' of module '{module}' has unsupported direction {direction}")]
+    UnsupportedDirection {
+        module: String,
+        port: String,
+        direction: String,
+    },
+
+    #[error("Module '{module}' uses bit {bit:?} somewhere it can't be represented")]
+    UnsupportedBit { module: String, bit: String },
+
+    #[error("Couldn't work out which module is the top of this design")]
+    NoTop,
+
+    #[error(transparent)]
+    Circuit(#[from] SVCircuitError),
+}
+
+/// A JSON object whose entries we need in the order they were written
+struct Ordered<T>(Vec<(String, T)>);
+
+impl<T> Default for Ordered<T> {
+    fn default() -> Self {
+        Ordered(Vec::new())
+    }
+}
+
+impl<T> Ordered<T> {
+    fn get(&self, key: &str) -> Option<&T> {
+        self.0.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Ordered<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct OrderedVisitor<T>(PhantomData<T>);
+
+        impl<'de, T: Deserialize<'de>> Visitor<'de> for OrderedVisitor<T> {
+            type Value = Ordered<T>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a JSON object")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let mut entries = Vec::with_capacity(map.size_hint().unwrap_or(0));
+                while let Some(entry) = map.next_entry()? {
+                    entries.push(entry);
+                }
+                Ok(Ordered(entries))
+            }
+        }
+
+        deserializer.deserialize_map(OrderedVisitor(PhantomData))
+    }
+}
+
+/// Yosys numbers every bit in a module from 2 upwards, and writes constants as strings
+#[derive(Deserialize, Clone, Debug)]
+#[serde(untagged)]
+enum Bit {
+    Wire(Wire),
+    Const(String),
+}
+
+#[derive(Deserialize)]
+struct Netlist {
+    modules: Ordered<Module>,
+}
+
+#[derive(Deserialize)]
+struct Module {
+    #[serde(default)]
+    attributes: HashMap<String, Value>,
+    #[serde(default)]
+    ports: Ordered<PortDesc>,
+    #[serde(default)]
+    cells: Ordered<Cell>,
+    #[serde(default)]
+    netnames: Ordered<NetName>,
+}
+
+#[derive(Deserialize)]
+struct PortDesc {
+    direction: String,
+    bits: Vec<Bit>,
+}
+
+#[derive(Deserialize)]
+struct Cell {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    connections: HashMap<String, Vec<Bit>>,
+}
+
+#[derive(Deserialize)]
+struct NetName {
+    bits: Vec<Bit>,
+    #[serde(default)]
+    hide_name: u8,
+    #[serde(default)]
+    offset: i64,
+}
+
+/// Accumulates the gates of a single module
+struct ModuleBuilder<'a> {
+    name: &'a str,
+    circuit: BoolCircuit,
+    /// Next wire ID that isn't used by any bit in the module
+    next: Wire,
+    /// Constant wires that something reads from
+    constants: HashSet<bool>,
+}
+
+impl<'a> ModuleBuilder<'a> {
+    fn wire(&mut self, bit: &Bit) -> Result<Wire, YosysError> {
+        match bit {
+            Bit::Wire(w) => Ok(*w),
+            Bit::Const(c) if c == "0" || c == "1" => {
+                let value = c == "1";
+                self.constants.insert(value);
+                Ok(value as Wire)
+            }
+            Bit::Const(c) => Err(YosysError::UnsupportedBit {
+                module: self.name.to_string(),
+                bit: c.clone(),
+            }),
+        }
+    }
+
+    fn fresh(&mut self) -> Wire {
+        self.next += 1;
+        self.next - 1
+    }
+
+    fn gate(&mut self, gate: Operation<bool>) -> Result<(), YosysError> {
+        self.circuit._add_gate(gate)?;
+        Ok(())
+    }
+
+    fn not(&mut self, dst: Wire, src: Wire) -> Result<(), YosysError> {
+        self.gate(Operation::AddConst(dst, src, true))
+    }
+
+    /// a | b = a ^ b ^ ab
+    fn or(&mut self, dst: Wire, a: Wire, b: Wire) -> Result<(), YosysError> {
+        let (xor, and) = (self.fresh(), self.fresh());
+        self.gate(Operation::Add(xor, a, b))?;
+        self.gate(Operation::Mul(and, a, b))?;
+        self.gate(Operation::Add(dst, xor, and))
+    }
+
+    /// Lowers a single-bit gate from Yosys' internal cell library
+    fn cell(&mut self, name: &str, cell: &Cell) -> Result<(), YosysError> {
+        let mut pin = |pin: &str| -> Result<Wire, YosysError> {
+            match cell.connections.get(pin).map(Vec::as_slice) {
+                Some([bit]) => self.wire(bit),
+                _ => Err(YosysError::MissingConnection {
+                    module: self.name.to_string(),
+                    cell: name.to_string(),
+                    pin: pin.to_string(),
+                }),
+            }
+        };
+
+        match cell.kind.as_str() {
+            "$_BUF_" => {
+                let (a, y) = (pin("A")?, pin("Y")?);
+                self.gate(Operation::identity(y, a))
+            }
+            "$_NOT_" => {
+                let (a, y) = (pin("A")?, pin("Y")?);
+                self.not(y, a)
+            }
+            "$_AND_" => {
+                let (a, b, y) = (pin("A")?, pin("B")?, pin("Y")?);
+                self.gate(Operation::Mul(y, a, b))
+            }
+            "$_XOR_" => {
+                let (a, b, y) = (pin("A")?, pin("B")?, pin("Y")?);
+                self.gate(Operation::Add(y, a, b))
+            }
+            "$_OR_" => {
+                let (a, b, y) = (pin("A")?, pin("B")?, pin("Y")?);
+                self.or(y, a, b)
+            }
+            "$_NAND_" => {
+                let (a, b, y) = (pin("A")?, pin("B")?, pin("Y")?);
+                let and = self.fresh();
+                self.gate(Operation::Mul(and, a, b))?;
+                self.not(y, and)
+            }
+            "$_NOR_" => {
+                let (a, b, y) = (pin("A")?, pin("B")?, pin("Y")?);
+                let or = self.fresh();
+                self.or(or, a, b)?;
+                self.not(y, or)
+            }
+            "$_XNOR_" => {
+                let (a, b, y) = (pin("A")?, pin("B")?, pin("Y")?);
+                let xor = self.fresh();
+                self.gate(Operation::Add(xor, a, b))?;
+                self.not(y, xor)
+            }
+            "$_ANDNOT_" => {
+                let (a, b, y) = (pin("A")?, pin("B")?, pin("Y")?);
+                let not_b = self.fresh();
+                self.not(not_b, b)?;
+                self.gate(Operation::Mul(y, a, not_b))
+            }
+            "$_ORNOT_" => {
+                let (a, b, y) = (pin("A")?, pin("B")?, pin("Y")?);
+                let not_b = self.fresh();
+                self.not(not_b, b)?;
+                self.or(y, a, not_b)
+            }
+            // y = s ? b : a = a ^ s(a ^ b)
+            "$_MUX_" => {
+                let (a, b, s, y) = (pin("A")?, pin("B")?, pin("S")?, pin("Y")?);
+                let (diff, select) = (self.fresh(), self.fresh());
+                self.gate(Operation::Add(diff, a, b))?;
+                self.gate(Operation::Mul(select, s, diff))?;
+                self.gate(Operation::Add(y, a, select))
+            }
+            _ => Err(YosysError::UnsupportedCell {
+                module: self.name.to_string(),
+                cell: name.to_string(),
+                kind: cell.kind.clone(),
+            }),
+        }
+    }
+
+    /// Records an instance of another module in the design
+    fn instance(&mut self, name: &str, cell: &Cell, model: &Module) -> Result<(), YosysError> {
+        let mut inputs: Vec<(Wire, Wire)> = Vec::new();
+        let mut outputs: Vec<(Wire, Wire)> = Vec::new();
+
+        for (pin, bits) in cell.connections.iter() {
+            let port = model
+                .ports
+                .get(pin)
+                .ok_or_else(|| YosysError::MissingConnection {
+                    module: self.name.to_string(),
+                    cell: name.to_string(),
+                    pin: pin.clone(),
+                })?;
+
+            for (parent, child) in bits.iter().zip(port.bits.iter()) {
+                let child = match child {
+                    Bit::Wire(w) => *w,
+                    // Constant bits in the submodule's port aren't wired to anything
+                    Bit::Const(_) => continue,
+                };
+                match port.direction.as_str() {
+                    "input" => inputs.push((self.wire(parent)?, child)),
+                    _ => match parent {
+                        Bit::Wire(w) => outputs.push((*w, child)),
+                        Bit::Const(c) => {
+                            return Err(YosysError::UnsupportedBit {
+                                module: self.name.to_string(),
+                                bit: c.clone(),
+                            })
+                        }
+                    },
+                }
+            }
+        }
+
+        self.circuit
+            .add_named_subcircuit(cell.kind.clone(), name.to_string(), inputs, outputs);
+        Ok(())
+    }
+}
+
+/// Builds the (hierarchical) circuit for a single module
+fn build_module(
+    name: &str,
+    module: &Module,
+    modules: &Ordered<Module>,
+) -> Result<BoolCircuit, YosysError> {
+    let bits = module
+        .ports
+        .0
+        .iter()
+        .flat_map(|(_, port)| port.bits.iter())
+        .chain(
+            module
+                .cells
+                .0
+                .iter()
+                .flat_map(|(_, cell)| cell.connections.values().flatten()),
+        )
+        .chain(
+            module
+                .netnames
+                .0
+                .iter()
+                .flat_map(|(_, net)| net.bits.iter()),
+        );
+    let largest = bits
+        .filter_map(|bit| match bit {
+            Bit::Wire(w) => Some(*w),
+            Bit::Const(_) => None,
+        })
+        .max()
+        .unwrap_or(1);
+
+    let mut builder = ModuleBuilder {
+        name,
+        circuit: BoolCircuit {
+            name: name.to_string(),
+            ..Default::default()
+        },
+        next: largest + 1,
+        constants: HashSet::new(),
+    };
+
+    for (port_name, port) in module.ports.0.iter() {
+        let direction = match port.direction.as_str() {
+            "input" => Direction::Input,
+            "output" => Direction::Output,
+            _ => {
+                return Err(YosysError::UnsupportedDirection {
+                    module: name.to_string(),
+                    port: port_name.clone(),
+                    direction: port.direction.clone(),
+                })
+            }
+        };
+
+        let mut wires = Vec::with_capacity(port.bits.len());
+        for bit in port.bits.iter() {
+            let wire = match (bit, direction) {
+                (Bit::Const(c), Direction::Input) => {
+                    return Err(YosysError::UnsupportedBit {
+                        module: name.to_string(),
+                        bit: c.clone(),
+                    })
+                }
+                _ => builder.wire(bit)?,
+            };
+            match direction {
+                Direction::Input => builder.circuit.inputs.insert(wire),
+                Direction::Output => builder.circuit.outputs.insert(wire),
+            };
+            wires.push(wire);
+        }
+
+        builder.circuit.ports.push(Port {
+            name: port_name.clone(),
+            direction,
+            wires,
+        });
+    }
+
+    for (cell_name, cell) in module.cells.0.iter() {
+        if cell.kind.starts_with('$') {
+            builder.cell(cell_name, cell)?;
+        } else {
+            let model =
+                modules
+                    .get(&cell.kind)
+                    .ok_or_else(|| SVCircuitError::MissingDependency {
+                        dependency: cell.kind.clone(),
+                        parent: name.to_string(),
+                    })?;
+            builder.instance(cell_name, cell, model)?;
+        }
+    }
+
+    for value in builder.constants.clone() {
+        builder.gate(Operation::Const(value as Wire, value))?;
+    }
+
+    // Prefer the names the user wrote over the ones Yosys made up
+    for hidden in [false, true] {
+        for (net_name, net) in module.netnames.0.iter() {
+            if (net.hide_name != 0) != hidden {
+                continue;
+            }
+            for (idx, bit) in net.bits.iter().enumerate() {
+                if let Bit::Wire(w) = bit {
+                    let bit_name = if net.bits.len() == 1 && net.offset == 0 {
+                        net_name.clone()
+                    } else {
+                        format!("{}[{}]", net_name, idx as i64 + net.offset)
+                    };
+                    builder.circuit.symbols.insert(*w, bit_name);
+                }
+            }
+        }
+    }
+
+    Ok(builder.circuit)
+}
+
+/// Picks the module marked with the `top` attribute, or failing that, the only module that isn't
+/// instantiated anywhere
+fn find_top(modules: &Ordered<Module>) -> Result<String, YosysError> {
+    let marked = modules
+        .0
+        .iter()
+        .find(|(_, module)| match module.attributes.get("top") {
+            Some(Value::String(s)) => s.chars().any(|c| c == '1'),
+            Some(Value::Number(n)) => n.as_u64() != Some(0),
+            _ => false,
+        });
+    if let Some((name, _)) = marked {
+        return Ok(name.clone());
+    }
+
+    let instantiated: HashSet<&str> = modules
+        .0
+        .iter()
+        .flat_map(|(_, module)| module.cells.0.iter().map(|(_, cell)| cell.kind.as_str()))
+        .collect();
+    let mut roots = modules
+        .0
+        .iter()
+        .filter(|(name, _)| !instantiated.contains(name.as_str()));
+
+    match (roots.next(), roots.next()) {
+        (Some((name, _)), None) => Ok(name.clone()),
+        _ => Err(YosysError::NoTop),
+    }
+}
+
+/// Reads a Yosys JSON netlist and flattens it. If `top` isn't given, we try to find it ourselves.
+/// Returns the flat circuit and the name of the top module.
+pub fn flatten<R: Read>(reader: R, top: Option<&str>) -> Result<(BoolCircuit, String), YosysError> {
+    let netlist: Netlist = serde_json::from_reader(reader)?;
+
+    let top = match top {
+        Some(top) => top.to_string(),
+        None => find_top(&netlist.modules)?,
+    };
+
+    let mut circuits: HashMap<String, BoolCircuit> = HashMap::new();
+    for (name, module) in netlist.modules.0.iter() {
+        circuits.insert(name.clone(), build_module(name, module, &netlist.modules)?);
+    }
+
+    let flat = CircuitFlattener::try_from((top.clone(), circuits))?.flatten()?;
+    Ok((flat, top))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::yosys::{flatten, YosysError};
+    use crate::{Direction, SVCircuitError};
+
+    /// Wraps a single cell in a module with inputs a, b, s and output y
+    fn single_cell(kind: &str) -> String {
+        format!(
+            r#"{{"modules": {{"top": {{
+                "ports": {{
+                    "a": {{"direction": "input", "bits": [2]}},
+                    "b": {{"direction": "input", "bits": [3]}},
+                    "s": {{"direction": "input", "bits": [4]}},
+                    "y": {{"direction": "output", "bits": [5]}}
+                }},
+                "cells": {{"g": {{"type": "{}", "connections": {{
+                    "A": [2], "B": [3], "S": [4], "Y": [5]
+                }}}}}}
+            }}}}}}"#,
+            kind
+        )
+    }
+
+    /// Reference semantics of a cell, given its `A`, `B` and `S` inputs
+    type Model = fn(bool, bool, bool) -> bool;
+
+    #[test]
+    fn test_cells() -> Result<(), YosysError> {
+        let cells: [(&str, Model); 11] = [
+            ("$_BUF_", |a, _, _| a),
+            ("$_NOT_", |a, _, _| !a),
+            ("$_AND_", |a, b, _| a & b),
+            ("$_XOR_", |a, b, _| a ^ b),
+            ("$_OR_", |a, b, _| a | b),
+            ("$_NAND_", |a, b, _| !(a & b)),
+            ("$_NOR_", |a, b, _| !(a | b)),
+            ("$_XNOR_", |a, b, _| !(a ^ b)),
+            ("$_ANDNOT_", |a, b, _| a & !b),
+            ("$_ORNOT_", |a, b, _| a | !b),
+            ("$_MUX_", |a, b, s| if s { b } else { a }),
+        ];
+
+        for (kind, expected) in cells.iter() {
+            let (circuit, _) = flatten(single_cell(kind).as_bytes(), None)?;
+            for bits in 0..8u8 {
+                let (a, b, s) = (bits & 1 == 1, bits & 2 == 2, bits & 4 == 4);
+                assert_eq!(
+                    circuit.simulate(&[a, b, s])?,
+                    vec![expected(a, b, s)],
+                    "{} on {:?}",
+                    kind,
+                    (a, b, s)
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_hierarchy() -> Result<(), YosysError> {
+        let netlist = r#"{"modules": {
+            "inv": {
+                "ports": {
+                    "i": {"direction": "input", "bits": [2]},
+                    "o": {"direction": "output", "bits": [3]}
+                },
+                "cells": {"$not": {"type": "$_NOT_", "connections": {"A": [2], "Y": [3]}}},
+                "netnames": {
+                    "i": {"hide_name": 0, "bits": [2]},
+                    "o": {"hide_name": 0, "bits": [3]}
+                }
+            },
+            "top": {
+                "attributes": {"top": "00000000000000000000000000000001"},
+                "ports": {
+                    "b": {"direction": "input", "bits": [4, 5]},
+                    "a": {"direction": "input", "bits": [2, 3]},
+                    "y": {"direction": "output", "bits": [6, 7, "1"]}
+                },
+                "cells": {
+                    "u_low": {"type": "inv", "connections": {"i": [2], "o": [8]}},
+                    "$and": {"type": "$_AND_", "connections": {"A": [8], "B": [4], "Y": [6]}},
+                    "$xor": {"type": "$_XOR_", "connections": {"A": [3], "B": [5], "Y": [7]}}
+                },
+                "netnames": {
+                    "a": {"hide_name": 0, "bits": [2, 3]},
+                    "b": {"hide_name": 0, "bits": [4, 5]},
+                    "y": {"hide_name": 0, "bits": [6, 7, "1"]},
+                    "$auto": {"hide_name": 1, "bits": [8]}
+                }
+            }
+        }}"#;
+
+        let (circuit, top) = flatten(netlist.as_bytes(), None)?;
+        assert_eq!(top, "top");
+
+        // Ports keep their declaration order and widths
+        let ports: Vec<(&str, Direction, usize)> = circuit
+            .ports
+            .iter()
+            .map(|p| (p.name.as_str(), p.direction, p.width()))
+            .collect();
+        assert_eq!(
+            ports,
+            vec![
+                ("b", Direction::Input, 2),
+                ("a", Direction::Input, 2),
+                ("y", Direction::Output, 3),
+            ]
+        );
+
+        // y = {1, a[1] ^ b[1], !a[0] & b[0]}
+        let wire = |port: usize, bit: usize| circuit.ports[port].wires[bit];
+        for bits in 0..16usize {
+            let (a0, a1, b0, b1) = (bits & 1 == 1, bits & 2 == 2, bits & 4 == 4, bits & 8 == 8);
+            let mut values = std::collections::HashMap::new();
+            values.insert(wire(1, 0), a0);
+            values.insert(wire(1, 1), a1);
+            values.insert(wire(0, 0), b0);
+            values.insert(wire(0, 1), b1);
+            circuit.evaluate(&mut values)?;
+            assert_eq!(values[&wire(2, 0)], !a0 & b0);
+            assert_eq!(values[&wire(2, 1)], a1 ^ b1);
+            assert!(values[&wire(2, 2)]);
+        }
+
+        assert_eq!(circuit.symbols.get(wire(1, 1)), Some("top.a[1]"));
+        assert!(circuit
+            .symbols
+            .iter()
+            .any(|(_, name)| name == "top.u_low.o"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(matches!(
+            flatten(single_cell("$_DFF_P_").as_bytes(), None),
+            Err(YosysError::UnsupportedCell { .. })
+        ));
+        assert!(matches!(
+            flatten(single_cell("missing").as_bytes(), None),
+            Err(YosysError::Circuit(
+                SVCircuitError::MissingDependency { .. }
+            ))
+        ));
+        assert!(matches!(
+            flatten(&b"{\"modules\": "[..], None),
+            Err(YosysError::Json(_))
+        ));
+    }
+}
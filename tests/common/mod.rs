@@ -0,0 +1,299 @@
+//! Random hierarchical circuit generator shared by the property tests. Designs are emitted as BLIF
+//! with nested `.subckt`s, and can also be evaluated directly so that we have a reference to check
+//! flattened circuits against.
+
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+/// A net in the local namespace of a model
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Net {
+    Input(usize),
+    Output(usize),
+    Wire(usize),
+}
+
+impl Net {
+    fn name(&self) -> String {
+        match self {
+            Net::Input(i) => format!("in[{}]", i),
+            Net::Output(i) => format!("out[{}]", i),
+            Net::Wire(i) => format!("n{}", i),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum GateKind {
+    And,
+    Xor,
+    Not,
+    Buf,
+}
+
+#[derive(Clone, Debug)]
+pub enum Cell {
+    Gate {
+        kind: GateKind,
+        inputs: Vec<Net>,
+        output: Net,
+    },
+    Instance {
+        model: usize,
+        inputs: Vec<Net>,
+        outputs: Vec<Net>,
+    },
+}
+
+#[derive(Clone, Debug)]
+pub struct Model {
+    pub name: String,
+    pub inputs: usize,
+    pub outputs: usize,
+    /// Cells in topological order: each one only reads nets driven by an earlier one
+    pub cells: Vec<Cell>,
+}
+
+/// A hierarchical design. Models may only instantiate models that come before them, and the last
+/// model is the top.
+#[derive(Clone, Debug)]
+pub struct Design {
+    pub models: Vec<Model>,
+}
+
+impl Design {
+    pub fn random(seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let count = rng.gen_range(1, 5);
+        let mut models: Vec<Model> = Vec::with_capacity(count);
+
+        for idx in 0..count {
+            let name = if idx + 1 == count {
+                "top".to_string()
+            } else {
+                format!("m{}", idx)
+            };
+            let inputs = rng.gen_range(1, 7);
+            let outputs = rng.gen_range(1, 4);
+
+            let mut cells = Vec::new();
+            let mut readable: Vec<Net> = (0..inputs).map(Net::Input).collect();
+            let mut driven_outputs: Vec<usize> = Vec::new();
+            let mut next_wire = 0;
+
+            for _ in 0..rng.gen_range(1, 10) {
+                // Every so often, instantiate one of the models we've already built
+                if idx > 0 && rng.gen_bool(0.3) {
+                    let model = rng.gen_range(0, idx);
+                    let sub_inputs = (0..models[model].inputs)
+                        .map(|_| *readable.choose(&mut rng).unwrap())
+                        .collect();
+                    let sub_outputs: Vec<Net> = (0..models[model].outputs)
+                        .map(|_| {
+                            // Sometimes drive one of our outputs directly from the subcircuit
+                            let free = (0..outputs).find(|o| !driven_outputs.contains(o));
+                            match free {
+                                Some(o) if rng.gen_bool(0.2) => {
+                                    driven_outputs.push(o);
+                                    Net::Output(o)
+                                }
+                                _ => {
+                                    next_wire += 1;
+                                    Net::Wire(next_wire - 1)
+                                }
+                            }
+                        })
+                        .collect();
+                    readable.extend(sub_outputs.iter().filter(|n| matches!(n, Net::Wire(_))));
+                    cells.push(Cell::Instance {
+                        model,
+                        inputs: sub_inputs,
+                        outputs: sub_outputs,
+                    });
+                } else {
+                    let kind = *[GateKind::And, GateKind::Xor, GateKind::Not, GateKind::Buf]
+                        .choose(&mut rng)
+                        .unwrap();
+                    let arity = match kind {
+                        GateKind::And | GateKind::Xor => 2,
+                        GateKind::Not | GateKind::Buf => 1,
+                    };
+                    let inputs = (0..arity)
+                        .map(|_| *readable.choose(&mut rng).unwrap())
+                        .collect();
+                    let output = Net::Wire(next_wire);
+                    next_wire += 1;
+                    readable.push(output);
+                    cells.push(Cell::Gate {
+                        kind,
+                        inputs,
+                        output,
+                    });
+                }
+            }
+
+            // Drive whatever outputs are left over from arbitrary nets
+            for o in 0..outputs {
+                if !driven_outputs.contains(&o) {
+                    cells.push(Cell::Gate {
+                        kind: GateKind::Buf,
+                        inputs: vec![*readable.choose(&mut rng).unwrap()],
+                        output: Net::Output(o),
+                    });
+                }
+            }
+
+            models.push(Model {
+                name,
+                inputs,
+                outputs,
+                cells,
+            });
+        }
+
+        Design { models }
+    }
+
+    pub fn top(&self) -> &Model {
+        self.models.last().unwrap()
+    }
+
+    /// Emits the design as BLIF, top model first. Cells are shuffled so that nothing relies on them
+    /// appearing in topological order.
+    pub fn to_blif(&self, seed: u64) -> String {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut blif = String::new();
+
+        for model in self.models.iter().rev() {
+            writeln!(blif, ".model {}", model.name).unwrap();
+            let inputs: Vec<String> = (0..model.inputs).map(|i| Net::Input(i).name()).collect();
+            writeln!(blif, ".inputs {}", inputs.join(" ")).unwrap();
+            let outputs: Vec<String> = (0..model.outputs).map(|o| Net::Output(o).name()).collect();
+            writeln!(blif, ".outputs {}", outputs.join(" ")).unwrap();
+
+            let mut cells: Vec<&Cell> = model.cells.iter().collect();
+            cells.shuffle(&mut rng);
+            for cell in cells {
+                match cell {
+                    Cell::Gate {
+                        kind,
+                        inputs,
+                        output,
+                    } => match kind {
+                        GateKind::And | GateKind::Xor => writeln!(
+                            blif,
+                            ".gate {} A={} B={} OUT={}",
+                            if let GateKind::And = kind {
+                                "AND"
+                            } else {
+                                "XOR"
+                            },
+                            inputs[0].name(),
+                            inputs[1].name(),
+                            output.name()
+                        ),
+                        GateKind::Not | GateKind::Buf => writeln!(
+                            blif,
+                            ".gate {} IN={} OUT={}",
+                            if let GateKind::Not = kind {
+                                "NOT"
+                            } else {
+                                "BUF"
+                            },
+                            inputs[0].name(),
+                            output.name()
+                        ),
+                    }
+                    .unwrap(),
+                    Cell::Instance {
+                        model: sub,
+                        inputs,
+                        outputs,
+                    } => {
+                        let pins: Vec<String> =
+                            inputs
+                                .iter()
+                                .enumerate()
+                                .map(|(i, n)| format!("{}={}", Net::Input(i).name(), n.name()))
+                                .chain(outputs.iter().enumerate().map(|(o, n)| {
+                                    format!("{}={}", Net::Output(o).name(), n.name())
+                                }))
+                                .collect();
+                        writeln!(
+                            blif,
+                            ".subckt {} {}",
+                            self.models[*sub].name,
+                            pins.join(" ")
+                        )
+                        .unwrap();
+                    }
+                }
+            }
+            writeln!(blif, ".end\n").unwrap();
+        }
+
+        blif
+    }
+
+    /// Reference semantics for the top model
+    pub fn evaluate(&self, inputs: &[bool]) -> Vec<bool> {
+        self.evaluate_model(self.models.len() - 1, inputs)
+    }
+
+    fn evaluate_model(&self, idx: usize, inputs: &[bool]) -> Vec<bool> {
+        let model = &self.models[idx];
+        let mut values: HashMap<Net, bool> = HashMap::new();
+        for (i, v) in inputs.iter().enumerate() {
+            values.insert(Net::Input(i), *v);
+        }
+
+        for cell in model.cells.iter() {
+            match cell {
+                Cell::Gate {
+                    kind,
+                    inputs,
+                    output,
+                } => {
+                    let a = values[&inputs[0]];
+                    let value = match kind {
+                        GateKind::And => a & values[&inputs[1]],
+                        GateKind::Xor => a ^ values[&inputs[1]],
+                        GateKind::Not => !a,
+                        GateKind::Buf => a,
+                    };
+                    values.insert(*output, value);
+                }
+                Cell::Instance {
+                    model: sub,
+                    inputs,
+                    outputs,
+                } => {
+                    let sub_inputs: Vec<bool> = inputs.iter().map(|n| values[n]).collect();
+                    let sub_outputs = self.evaluate_model(*sub, &sub_inputs);
+                    for (net, value) in outputs.iter().zip(sub_outputs) {
+                        values.insert(*net, value);
+                    }
+                }
+            }
+        }
+
+        (0..model.outputs)
+            .map(|o| values[&Net::Output(o)])
+            .collect()
+    }
+}
+
+/// Recovers the index of a port from its BLIF name, e.g. `in[3]` -> 3
+pub fn port_index(name: &str, port: &str) -> Option<usize> {
+    name.strip_prefix(port)?
+        .strip_prefix('[')?
+        .strip_suffix(']')?
+        .parse()
+        .ok()
+}
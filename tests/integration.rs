@@ -1,8 +1,15 @@
 use mcircuit::parsers::blif::BlifParser;
 use mcircuit::{Operation, Parse};
 use std::fs::File;
-use std::io::BufReader;
+use std::io::{BufReader, BufWriter};
+use std::path::PathBuf;
 use sv_circuit;
+use sv_circuit::connection::ConnectionSpec;
+use sv_circuit::dot::{self, DotOptions};
+use sv_circuit::equiv::{self, EquivalenceConfig};
+use sv_circuit::export::{self, ExportOptions};
+use sv_circuit::{aiger, blif, bristol, verilog, yosys, BoolCircuit, SVCircuitError};
+use sv_circuit::{CircuitCompositor, CompositorError, Direction, Port};
 
 #[test]
 fn test_flatten_simple() {
@@ -14,19 +21,583 @@ fn test_flatten_multi() {
     _test_in_folder("multi");
 }
 
+#[test]
+fn test_equivalent_simple() {
+    _test_equivalent_in_folder("simple");
+}
+
+#[test]
+fn test_equivalent_multi() {
+    _test_equivalent_in_folder("multi");
+}
+
 fn _test_in_folder(folder: &str) {
     let reader = BufReader::new(
         File::open(format!("tests/data/{}/src.blif", folder)).expect("Failed to open input file"),
     );
-    let (flat, _, _) = sv_circuit::flatten(BlifParser::<bool>::new(reader));
+    let (flat, _, _) =
+        sv_circuit::flatten(BlifParser::<bool>::new(reader)).expect("Failed to flatten");
 
     let reader = BufReader::new(
         File::open(format!("tests/data/{}/flat.blif", folder)).expect("Failed to open target file"),
     );
-    let (expected, _, _) = sv_circuit::flatten(BlifParser::<bool>::new(reader));
+    let (expected, _, _) =
+        sv_circuit::flatten(BlifParser::<bool>::new(reader)).expect("Failed to flatten");
 
     let flat_topo: Vec<Operation<bool>> = flat.topo_iter().cloned().collect();
     let expected_topo: Vec<Operation<bool>> = expected.topo_iter().cloned().collect();
 
     assert_eq!(flat_topo, expected_topo);
 }
+
+fn _test_equivalent_in_folder(folder: &str) {
+    let reader = BufReader::new(
+        File::open(format!("tests/data/{}/src.blif", folder)).expect("Failed to open input file"),
+    );
+    let (flat, _, _) =
+        sv_circuit::flatten(BlifParser::<bool>::new(reader)).expect("Failed to flatten");
+
+    let reader = BufReader::new(
+        File::open(format!("tests/data/{}/flat.blif", folder)).expect("Failed to open target file"),
+    );
+    let (expected, _, _) =
+        sv_circuit::flatten(BlifParser::<bool>::new(reader)).expect("Failed to flatten");
+
+    let result = equiv::check(&flat, &expected, &EquivalenceConfig::default())
+        .expect("Failed to compare circuits");
+    assert!(result.is_equivalent(), "{:?}", result);
+}
+
+#[test]
+fn test_blif_roundtrip_simple() {
+    _test_blif_roundtrip_in_folder("simple");
+}
+
+#[test]
+fn test_blif_roundtrip_multi() {
+    _test_blif_roundtrip_in_folder("multi");
+}
+
+fn _test_blif_roundtrip_in_folder(folder: &str) {
+    let reader = BufReader::new(
+        File::open(format!("tests/data/{}/src.blif", folder)).expect("Failed to open input file"),
+    );
+    let (flat, _, _) =
+        sv_circuit::flatten(BlifParser::<bool>::new(reader)).expect("Failed to flatten");
+
+    let path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(format!("{}_flat.blif", folder));
+    let mut writer = BufWriter::new(File::create(&path).expect("Failed to create output file"));
+    blif::write(&mut writer, &flat).expect("Failed to write BLIF");
+    drop(writer);
+
+    let reader = BufReader::new(File::open(&path).expect("Failed to open output file"));
+    let (read_back, top, _) =
+        sv_circuit::flatten(BlifParser::<bool>::new(reader)).expect("Failed to flatten");
+    assert_eq!(top, flat.name);
+    assert_eq!(read_back.ports, flat.ports);
+
+    let result = equiv::check(&flat, &read_back, &EquivalenceConfig::default())
+        .expect("Failed to compare circuits");
+    assert!(result.is_equivalent(), "{:?}", result);
+}
+
+#[test]
+fn test_yosys_matches_blif() {
+    let reader = BufReader::new(
+        File::open("tests/data/simple/src.blif").expect("Failed to open input file"),
+    );
+    let (expected, _, _) =
+        sv_circuit::flatten(BlifParser::<bool>::new(reader)).expect("Failed to flatten");
+
+    let reader = BufReader::new(
+        File::open("tests/data/simple/src.json").expect("Failed to open input file"),
+    );
+    let (flat, top) = yosys::flatten(reader, None).expect("Failed to flatten");
+    assert_eq!(top, "top");
+
+    // Both front ends agree on the interface, though not necessarily on the wire IDs behind it
+    let interface = |ports: &[Port]| -> Vec<(String, Direction, usize)> {
+        ports
+            .iter()
+            .map(|port| (port.name.clone(), port.direction, port.width()))
+            .collect()
+    };
+    assert_eq!(interface(&flat.ports), interface(&expected.ports));
+
+    let aligned = equiv::align_ports(&flat, &expected).expect("Failed to align ports");
+    let result = equiv::check(&aligned, &expected, &EquivalenceConfig::default())
+        .expect("Failed to compare circuits");
+    assert!(result.is_equivalent(), "{:?}", result);
+
+    // Only Yosys knows the instance names
+    assert!(flat
+        .symbols
+        .iter()
+        .any(|(_, name)| name == "top.u_reduce.$abc$49$new_n5_"));
+}
+
+#[test]
+fn test_flatten_symbols() {
+    let reader = BufReader::new(
+        File::open("tests/data/simple/src.blif").expect("Failed to open input file"),
+    );
+    let (flat, _, _) =
+        sv_circuit::flatten(BlifParser::<bool>::new(reader)).expect("Failed to flatten");
+
+    let names: Vec<&str> = flat.symbols.iter().map(|(_, name)| name).collect();
+    assert!(names.contains(&"top.out"));
+    assert!(names.contains(&"top.in[8]"));
+    // The XOR inside each of the four instances of `inner` survives flattening
+    for instance in 0..4 {
+        let name = format!("top.inner_{}.$abc$49$new_n5_", instance);
+        assert!(names.contains(&name.as_str()), "missing {}", name);
+    }
+}
+
+fn _flatten_fuzz_seed(name: &str) -> Result<BoolCircuit, SVCircuitError> {
+    let reader = BufReader::new(
+        File::open(format!("tests/data/fuzz/flatten_blif/seed-{}.blif", name))
+            .expect("Failed to open seed file"),
+    );
+    sv_circuit::flatten(BlifParser::<bool>::new(reader)).map(|(flat, _, _)| flat)
+}
+
+#[test]
+fn test_flatten_rejects_bad_designs() {
+    assert!(matches!(
+        _flatten_fuzz_seed("loop"),
+        Err(SVCircuitError::CombinationalLoop { .. })
+    ));
+    assert!(matches!(
+        _flatten_fuzz_seed("feedback"),
+        Err(SVCircuitError::CombinationalLoop { .. })
+    ));
+    assert!(matches!(
+        _flatten_fuzz_seed("recursive"),
+        Err(SVCircuitError::RecursiveDependency { .. })
+    ));
+    assert!(matches!(
+        _flatten_fuzz_seed("missing"),
+        Err(SVCircuitError::MissingDependency { .. })
+    ));
+    assert!(matches!(
+        _flatten_fuzz_seed("undriven"),
+        Err(SVCircuitError::UndrivenGate { .. })
+    ));
+    assert!(matches!(
+        _flatten_fuzz_seed("empty"),
+        Err(SVCircuitError::NoCircuits)
+    ));
+}
+
+#[test]
+fn test_witness_rejects_bad_steps() {
+    let parse = |name: &str| {
+        File::open(format!("tests/data/fuzz/parse_witness/seed-{}.txt", name))
+            .map(BufReader::new)
+            .map(sv_circuit::parse::witness)
+            .expect("Failed to open seed file")
+    };
+
+    assert_eq!(parse("valid").expect("Failed to parse witness").len(), 3);
+    assert!(parse("short").is_err());
+    assert!(parse("badbit").is_err());
+}
+
+#[test]
+fn test_export_annotations() {
+    let reader = BufReader::new(
+        File::open("tests/data/simple/src.blif").expect("Failed to open input file"),
+    );
+    let (flat, _, _) =
+        sv_circuit::flatten(BlifParser::<bool>::new(reader)).expect("Failed to flatten");
+
+    let mut plain: Vec<u8> = Vec::new();
+    export::function(&mut plain, &flat, &ExportOptions::default()).expect("Failed to export");
+    let plain = String::from_utf8(plain).unwrap();
+    assert!(!plain.contains("//"));
+
+    let mut annotated: Vec<u8> = Vec::new();
+    let options = ExportOptions {
+        annotate: true,
+        ..Default::default()
+    };
+    export::function(&mut annotated, &flat, &options).expect("Failed to export");
+    let annotated = String::from_utf8(annotated).unwrap();
+
+    assert!(annotated.contains("// input top.in[0] ($"));
+    assert!(annotated.contains("// output top.out ($"));
+    for instance in 0..4 {
+        assert!(annotated.contains(&format!("  // top.inner_{}\n", instance)));
+    }
+
+    // Annotations are comments only, so stripping them gives back the plain export
+    let stripped: Vec<&str> = annotated
+        .lines()
+        .filter(|l| !l.trim_start().starts_with("//"))
+        .collect();
+    assert_eq!(stripped, plain.lines().collect::<Vec<_>>());
+}
+
+#[test]
+fn test_slice_exports() {
+    let reader =
+        BufReader::new(File::open("tests/data/multi/src.blif").expect("Failed to open input file"));
+    let (flat, _, _) =
+        sv_circuit::flatten(BlifParser::<bool>::new(reader)).expect("Failed to flatten");
+
+    let output = *flat.outputs.iter().min().unwrap();
+    let slice = flat.slice(&[output]).expect("Failed to slice");
+    assert!(slice.ngate() <= flat.ngate());
+    assert_eq!(slice.outputs.len(), 1);
+
+    // The slice is a circuit in its own right, so every exporter takes it
+    let mut written: Vec<u8> = Vec::new();
+    export::function(&mut written, &slice, &ExportOptions::default()).expect("Failed to export");
+    blif::write(&mut written, &slice).expect("Failed to write BLIF");
+    bristol::write(&mut written, &slice).expect("Failed to write Bristol");
+    verilog::write(&mut written, &slice).expect("Failed to write Verilog");
+    dot::write(&mut written, &slice, &DotOptions::default()).expect("Failed to write DOT");
+
+    let mut aag: Vec<u8> = Vec::new();
+    aiger::write_ascii(&mut aag, &slice).expect("Failed to write AIGER");
+    let read_back = aiger::read(aag.as_slice()).expect("Failed to read AIGER");
+    let result = equiv::check(&slice, &read_back, &EquivalenceConfig::default())
+        .expect("Failed to compare circuits");
+    assert!(result.is_equivalent(), "{:?}", result);
+}
+
+/// Flattens the hierarchical composite fixture, and finds the wire named `name` in it
+fn flattened_hierarchical(name: &str) -> (BoolCircuit, usize) {
+    let reader = BufReader::new(
+        File::open("tests/data/composite/hierarchical.blif").expect("Failed to open input file"),
+    );
+    let (flat, _, _) =
+        sv_circuit::flatten(BlifParser::<bool>::new(reader)).expect("Failed to flatten");
+    let wire = flat
+        .symbols
+        .iter()
+        .find(|(_, symbol)| *symbol == name)
+        .map(|(wire, _)| wire)
+        .unwrap_or_else(|| panic!("No wire named {}", name));
+    (flat, wire)
+}
+
+#[test]
+fn test_slice_flattened() {
+    // Flattening renumbers the internal wires, so the cone has to be found on the new IDs
+    let (flat, n) = flattened_hierarchical("checker.n[63]");
+    let slice = flat.slice(&[n]).expect("Failed to slice");
+    assert_eq!(slice.ngate(), 1);
+    assert_eq!(slice.inputs.len(), 2);
+    assert_eq!(slice.outputs.len(), 1);
+    for (a, b) in [(false, false), (false, true), (true, false), (true, true)] {
+        assert_eq!(
+            slice.simulate(&[a, b]).expect("Failed to simulate"),
+            vec![a ^ b]
+        );
+    }
+
+    // It feeds s[63] through two inverters
+    let fanout = flat.fanout(&[n]).expect("Failed to take fan-out");
+    assert_eq!(fanout.ngate(), 2);
+    assert_eq!(fanout.outputs.len(), 1);
+    assert_eq!(
+        fanout.simulate(&[true]).expect("Failed to simulate"),
+        vec![true]
+    );
+}
+
+#[test]
+fn test_dot_cone_flattened() {
+    let (flat, n) = flattened_hierarchical("checker.n[63]");
+    let options = DotOptions {
+        cone: vec![n],
+        ..Default::default()
+    };
+    let mut written: Vec<u8> = Vec::new();
+    dot::write(&mut written, &flat, &options).expect("Failed to write DOT");
+    let dot = String::from_utf8(written).unwrap();
+
+    // The XOR that drives n[63], reading a[63] and b[63]
+    assert!(dot.contains("[label=\"XOR\\nn[63]\"]"), "{}", dot);
+    assert!(
+        dot.contains("[label=\"a[63]\", shape=box, style=filled"),
+        "{}",
+        dot
+    );
+    assert!(
+        dot.contains("[label=\"b[63]\", shape=box, style=filled"),
+        "{}",
+        dot
+    );
+    assert_eq!(dot.matches(" -> ").count(), 3);
+}
+
+#[test]
+fn test_capi() {
+    use mcircuit::Operation;
+    use std::os::unix::io::AsRawFd;
+    use std::ptr;
+    use sv_circuit::capi::*;
+
+    let blif = std::fs::read("tests/data/simple/src.blif").expect("Failed to read input file");
+    let mut circuit: *mut SvcCircuit = ptr::null_mut();
+    let status = unsafe { svc_flatten_blif(blif.as_ptr(), blif.len(), &mut circuit) };
+    assert_eq!(status, SvcStatus::Ok);
+
+    let (flat, _, _) = sv_circuit::flatten(BlifParser::<bool>::new(BufReader::new(
+        File::open("tests/data/simple/src.blif").expect("Failed to open input file"),
+    )))
+    .expect("Failed to flatten");
+    unsafe {
+        assert_eq!(svc_circuit_gate_count(circuit), flat.ngate());
+        assert_eq!(svc_circuit_wire_count(circuit), flat.nwire());
+        assert_eq!(svc_circuit_input_count(circuit), flat.inputs.len());
+        assert_eq!(svc_circuit_output_count(circuit), flat.outputs.len());
+    }
+
+    let path = std::env::temp_dir().join(format!("sv_circuit_capi_{}.bin", std::process::id()));
+    let file = File::create(&path).expect("Failed to create output file");
+    let status = unsafe { svc_circuit_write_bincode(circuit, file.as_raw_fd()) };
+    assert_eq!(status, SvcStatus::Ok);
+    drop(file);
+    let operations: Vec<Operation<bool>> =
+        bincode::deserialize_from(BufReader::new(File::open(&path).unwrap()))
+            .expect("Failed to read back operations");
+    assert_eq!(
+        operations.len(),
+        flat.inputs.len() + flat.ngate() + flat.outputs.len()
+    );
+
+    // This circuit's single 9-bit input can't be called on pairs of witness steps
+    let witness = std::fs::read("tests/data/fuzz/parse_witness/seed-valid.txt").unwrap();
+    let mut parsed: *mut SvcWitness = ptr::null_mut();
+    let status = unsafe { svc_witness_parse(witness.as_ptr(), witness.len(), &mut parsed) };
+    assert_eq!(status, SvcStatus::Ok);
+    let file = File::create(&path).expect("Failed to create output file");
+    let status = unsafe { svc_circuit_write_ir(circuit, parsed, file.as_raw_fd(), false) };
+    assert_eq!(status, SvcStatus::Circuit);
+    assert!(!svc_last_error().is_null());
+    let options = SvcExportOptions {
+        delete_dead: true,
+        ..svc_export_options_default()
+    };
+    let status =
+        unsafe { svc_circuit_write_ir_with_options(circuit, parsed, file.as_raw_fd(), &options) };
+    assert_eq!(status, SvcStatus::Circuit);
+
+    // A circuit comparing two steps of the witness's width exports, and one comparing narrower
+    // steps is refused
+    let check = |width: usize| {
+        let bits = |name: &str| {
+            (0..width)
+                .map(|i| format!(" {}[{}]", name, i))
+                .collect::<String>()
+        };
+        let blif = format!(
+            ".model check\n.inputs{}{}\n.outputs ok\n.gate XOR A=a[0] B=b[0] OUT=ok\n.end\n",
+            bits("a"),
+            bits("b")
+        );
+        let mut circuit: *mut SvcCircuit = ptr::null_mut();
+        let status = unsafe { svc_flatten_blif(blif.as_ptr(), blif.len(), &mut circuit) };
+        assert_eq!(status, SvcStatus::Ok);
+        let file = File::create(&path).expect("Failed to create output file");
+        let status = unsafe { svc_circuit_write_ir(circuit, parsed, file.as_raw_fd(), false) };
+        unsafe { svc_circuit_free(circuit) };
+        status
+    };
+    assert_eq!(check(sv_circuit::WITNESS_LEN), SvcStatus::Ok);
+    let written = std::fs::read_to_string(&path).expect("Failed to read back IR");
+    assert!(written.contains("<- @call(check, $"));
+    assert_eq!(check(8), SvcStatus::Circuit);
+    let message = unsafe { std::ffi::CStr::from_ptr(svc_last_error()) };
+    assert!(message.to_string_lossy().contains("8 bits wide"));
+
+    unsafe {
+        svc_witness_free(parsed);
+        svc_circuit_free(circuit);
+    }
+    std::fs::remove_file(&path).ok();
+}
+
+#[test]
+fn test_composite_from_blif() {
+    use mcircuit::CombineOperation;
+    use std::path::Path;
+
+    let spec = File::open("tests/data/composite/connections.json")
+        .map(BufReader::new)
+        .map(ConnectionSpec::from_reader)
+        .expect("Failed to open connection spec")
+        .expect("Failed to parse connection spec");
+    let compositor = CircuitCompositor::from_blif(
+        Path::new("tests/data/composite/boolean.blif"),
+        Path::new("tests/data/composite/arithmetic.blif"),
+        &spec,
+    )
+    .expect("Failed to compose circuits");
+
+    let operations: Vec<CombineOperation> = compositor.operations().collect();
+    let bool_dsts: Vec<usize> = operations
+        .iter()
+        .filter_map(|op| match op {
+            CombineOperation::GF2(Operation::Add(dst, _, _)) => Some(*dst),
+            _ => None,
+        })
+        .collect();
+    let b2a: Vec<(usize, usize)> = operations
+        .iter()
+        .filter_map(|op| match op {
+            CombineOperation::B2A(arith, lo) => Some((*arith, *lo)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(b2a.len(), 1);
+    let (x, lo) = b2a[0];
+    // The bundle is the sum computed by the boolean circuit
+    for bit in lo..lo + 64 {
+        assert!(bool_dsts.contains(&bit), "bit {} isn't a sum bit", bit);
+    }
+    let randoms: Vec<usize> = operations
+        .iter()
+        .filter_map(|op| match op {
+            CombineOperation::Z64(Operation::Random(dst)) => Some(*dst),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(randoms.len(), 1);
+    assert_ne!(randoms[0], x);
+
+    // Ports have to exist on the right side, and be inputs on the arithmetic side
+    let bad = ConnectionSpec {
+        challenges: vec!["y".to_string()],
+        ..spec
+    };
+    let result = CircuitCompositor::from_blif(
+        Path::new("tests/data/composite/boolean.blif"),
+        Path::new("tests/data/composite/arithmetic.blif"),
+        &bad,
+    );
+    assert!(matches!(result, Err(CompositorError::UnknownPort { .. })));
+}
+
+#[test]
+fn test_schedule_composite() {
+    use std::convert::TryFrom;
+    use std::path::Path;
+    use sv_circuit::evaluate_program;
+    use sv_circuit::optimize::schedule::schedule;
+
+    let spec = File::open("tests/data/composite/connections.json")
+        .map(BufReader::new)
+        .map(ConnectionSpec::from_reader)
+        .expect("Failed to open connection spec")
+        .expect("Failed to parse connection spec");
+    let compositor = CircuitCompositor::from_blif(
+        Path::new("tests/data/composite/hierarchical.blif"),
+        Path::new("tests/data/composite/arithmetic.blif"),
+        &spec,
+    )
+    .expect("Failed to compose circuits");
+    let program: Vec<_> = compositor.operations().collect();
+    let scheduled = schedule(&program);
+
+    // Gates only move within their section, so the scheduled program still loads
+    let loaded = CircuitCompositor::try_from(scheduled.clone()).expect("Failed to load program");
+    assert_eq!(loaded.gate_stats(), compositor.gate_stats());
+
+    let bits =
+        sv_circuit::parse::bits(format!("{}\n{}\n", "01".repeat(32), "01".repeat(32)).as_bytes())
+            .expect("Failed to parse bits");
+    let witness = compositor
+        .witness(&bits, &[7])
+        .expect("Failed to generate witness");
+    let expected = evaluate_program(&program, &witness.boolean, &witness.arithmetic)
+        .expect("Failed to evaluate program");
+    let actual = evaluate_program(&scheduled, &witness.boolean, &witness.arithmetic)
+        .expect("Failed to evaluate scheduled program");
+    assert_eq!(actual, expected);
+}
+
+#[test]
+fn test_composite_scattered_bundle() {
+    use std::path::Path;
+    use sv_circuit::evaluate_program;
+
+    // `s` is interleaved with `c` and computed through internal wires, so its bits come out of
+    // flattening scattered, and have to be copied onto consecutive wires for the B2A
+    let spec = File::open("tests/data/composite/connections.json")
+        .map(BufReader::new)
+        .map(ConnectionSpec::from_reader)
+        .expect("Failed to open connection spec")
+        .expect("Failed to parse connection spec");
+    let compositor = CircuitCompositor::from_blif(
+        Path::new("tests/data/composite/hierarchical.blif"),
+        Path::new("tests/data/composite/arithmetic.blif"),
+        &spec,
+    )
+    .expect("Failed to compose circuits");
+
+    let bits =
+        sv_circuit::parse::bits(format!("{}\n{}\n", "01".repeat(32), "01".repeat(32)).as_bytes())
+            .expect("Failed to parse bits");
+    let witness = compositor
+        .witness(&bits, &[7])
+        .expect("Failed to generate witness");
+    let program: Vec<_> = compositor.operations().collect();
+    let outputs = evaluate_program(&program, &witness.boolean, &witness.arithmetic)
+        .expect("Failed to evaluate program");
+    assert!(outputs.arithmetic.iter().all(|v| *v == 0));
+
+    let mut unequal = bits.clone();
+    unequal[63] = !unequal[63];
+    assert!(matches!(
+        compositor.witness(&unequal, &[7]),
+        Err(CompositorError::UnsatisfiedWitness { failed: 1 })
+    ));
+}
+
+#[test]
+fn test_composite_witness() {
+    use std::path::Path;
+    use sv_circuit::{evaluate_program, CompositeWitness};
+
+    let spec = File::open("tests/data/composite/connections.json")
+        .map(BufReader::new)
+        .map(ConnectionSpec::from_reader)
+        .expect("Failed to open connection spec")
+        .expect("Failed to parse connection spec");
+    let compositor = CircuitCompositor::from_blif(
+        Path::new("tests/data/composite/boolean.blif"),
+        Path::new("tests/data/composite/arithmetic.blif"),
+        &spec,
+    )
+    .expect("Failed to compose circuits");
+
+    // The arithmetic circuit asserts that x * r = 0, where x = a ^ b
+    let bits =
+        sv_circuit::parse::bits(format!("{}\n{}\n", "01".repeat(32), "01".repeat(32)).as_bytes())
+            .expect("Failed to parse bits");
+    let witness = compositor
+        .witness(&bits, &[7])
+        .expect("Failed to generate witness");
+    let mut written: Vec<u8> = Vec::new();
+    witness
+        .write(&mut written)
+        .expect("Failed to write witness");
+    let witness = CompositeWitness::read(written.as_slice()).expect("Failed to read witness");
+
+    let program: Vec<_> = compositor.operations().collect();
+    let outputs = evaluate_program(&program, &witness.boolean, &witness.arithmetic)
+        .expect("Failed to evaluate program");
+    assert!(outputs.arithmetic.iter().all(|v| *v == 0));
+
+    let mut unequal = bits.clone();
+    unequal[0] = !unequal[0];
+    assert!(matches!(
+        compositor.witness(&unequal, &[7]),
+        Err(CompositorError::UnsatisfiedWitness { failed: 1 })
+    ));
+}
@@ -0,0 +1,390 @@
+mod common;
+
+use std::fs::File;
+use std::io::{BufReader, Cursor, Write};
+use std::path::PathBuf;
+
+use itertools::Itertools;
+use mcircuit::parsers::blif::BlifParser;
+use mcircuit::parsers::WireHasher;
+use mcircuit::{largest_wires, CombineOperation, HasIO, Operation, Parse};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use common::{port_index, Design};
+use sv_circuit::container;
+use sv_circuit::optimize::dead::{eliminate_dead_code, eliminate_dead_code_stream};
+use sv_circuit::optimize::isolate::{
+    combine_arithmetic_namespace, insert_size_hint, insert_size_hint_stream,
+    isolate_arithmetic_wires, isolate_arithmetic_wires_stream,
+};
+use sv_circuit::optimize::ram::register_aliasing;
+use sv_circuit::optimize::schedule::schedule;
+use sv_circuit::stream::StreamWriter;
+use sv_circuit::{aiger, blif, bristol, evaluate_program, BoolCircuit};
+
+const SEEDS: u64 = 64;
+
+/// Writes a random design out to BLIF and flattens it
+fn flatten_design(design: &Design, seed: u64) -> (BoolCircuit, WireHasher) {
+    let path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(format!("design_{}.blif", seed));
+    File::create(&path)
+        .and_then(|mut f| f.write_all(design.to_blif(seed).as_bytes()))
+        .expect("Failed to write design");
+
+    let reader = BufReader::new(File::open(&path).expect("Failed to open design"));
+    let (flat, _, hasher) =
+        sv_circuit::flatten(BlifParser::<bool>::new(reader)).expect("Failed to flatten design");
+    (flat, hasher)
+}
+
+/// Maps the position of each flattened I/O wire (in ascending wire order) to the index of the port
+/// it corresponds to in the design
+fn port_order(wires: impl Iterator<Item = usize>, hasher: &WireHasher, port: &str) -> Vec<usize> {
+    wires
+        .sorted()
+        .map(|w| {
+            let name = hasher.backref(w).expect("Unnamed I/O wire");
+            port_index(name, port).expect("Unexpected I/O wire")
+        })
+        .collect()
+}
+
+/// Checks `check` against the reference semantics of `design` on every input vector. `check`
+/// receives inputs and returns outputs in ascending wire order.
+fn assert_matches_design<F>(design: &Design, flat: &BoolCircuit, hasher: &WireHasher, check: F)
+where
+    F: Fn(&[bool]) -> Vec<bool>,
+{
+    let input_order = port_order(flat.inputs.iter().copied(), hasher, "in");
+    let output_order = port_order(flat.outputs.iter().copied(), hasher, "out");
+
+    let width = design.top().inputs;
+    for assignment in 0..(1u64 << width) {
+        let inputs: Vec<bool> = (0..width).map(|i| (assignment >> i) & 1 == 1).collect();
+        let expected = design.evaluate(&inputs);
+
+        let wire_inputs: Vec<bool> = input_order.iter().map(|i| inputs[*i]).collect();
+        let actual = check(&wire_inputs);
+        let expected: Vec<bool> = output_order.iter().map(|o| expected[*o]).collect();
+
+        assert_eq!(actual, expected, "Mismatch on inputs {:?}", inputs);
+    }
+}
+
+/// Checks a circuit whose inputs and outputs are numbered in the design's bit order, as they are
+/// after a round trip through a format that doesn't keep wire IDs
+fn assert_simulates_design(design: &Design, circuit: &BoolCircuit) {
+    let width = design.top().inputs;
+    for assignment in 0..(1u64 << width) {
+        let inputs: Vec<bool> = (0..width).map(|i| (assignment >> i) & 1 == 1).collect();
+        assert_eq!(
+            circuit.simulate(&inputs).expect("Failed to simulate"),
+            design.evaluate(&inputs),
+            "Mismatch on inputs {:?}",
+            inputs
+        );
+    }
+}
+
+/// Lowers a flat boolean circuit to the program representation the `optimize` passes work on
+fn to_program(circuit: &BoolCircuit) -> Vec<CombineOperation> {
+    circuit.into_iter().map(CombineOperation::GF2).collect()
+}
+
+/// Extends a boolean program with a B2A conversion of 64 fresh input bits and a few arithmetic gates
+/// that consume the result, placed well away from the bottom of the arithmetic wire space
+fn to_composite_program(circuit: &BoolCircuit, seed: u64) -> Vec<CombineOperation> {
+    let mut program = to_program(circuit);
+    let (_, largest_bool) = largest_wires(&program);
+    let base = largest_bool + 1;
+
+    program.extend((base..base + 64).map(|w| CombineOperation::GF2(Operation::Input(w))));
+    program.push(CombineOperation::B2A(1000, base));
+    program.extend([
+        CombineOperation::Z64(Operation::Input(1001)),
+        CombineOperation::Z64(Operation::Mul(1002, 1000, 1001)),
+        CombineOperation::Z64(Operation::AddConst(1003, 1002, seed)),
+        CombineOperation::Z64(Operation::AssertZero(1003)),
+    ]);
+    program
+}
+
+#[test]
+fn prop_flatten_preserves_behaviour() {
+    for seed in 0..SEEDS {
+        let design = Design::random(seed);
+        let (flat, hasher) = flatten_design(&design, seed);
+
+        assert_matches_design(&design, &flat, &hasher, |inputs| {
+            flat.simulate(inputs).expect("Failed to simulate")
+        });
+    }
+}
+
+#[test]
+fn prop_circuit_passes_preserve_behaviour() {
+    for seed in 0..SEEDS {
+        let design = Design::random(seed);
+        let (mut flat, hasher) = flatten_design(&design, seed);
+
+        flat.prune();
+        flat.curry();
+        flat.minimize_wires();
+        flat.schedule();
+
+        assert_matches_design(&design, &flat, &hasher, |inputs| {
+            flat.simulate(inputs).expect("Failed to simulate")
+        });
+    }
+}
+
+#[test]
+fn prop_program_passes_preserve_behaviour() {
+    for seed in 0..SEEDS {
+        let design = Design::random(seed);
+        let (flat, hasher) = flatten_design(&design, seed);
+
+        let program = to_program(&flat);
+        let (_, largest_bool) = largest_wires(&program);
+
+        let dce = eliminate_dead_code(&program, largest_bool + 1);
+        let aliased = register_aliasing(&program, largest_bool + 1);
+
+        for optimized in [dce, aliased] {
+            assert_matches_design(&design, &flat, &hasher, |inputs| {
+                evaluate_program(&optimized, inputs, &[])
+                    .expect("Failed to evaluate program")
+                    .boolean
+            });
+        }
+    }
+}
+
+#[test]
+fn prop_isolate_preserves_behaviour() {
+    for seed in 0..SEEDS {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let design = Design::random(seed);
+        let (flat, _) = flatten_design(&design, seed);
+
+        let program = to_composite_program(&flat, seed);
+        let isolated = isolate_arithmetic_wires(program.clone());
+        let combined = combine_arithmetic_namespace(isolated.clone());
+
+        for _ in 0..16 {
+            let boolean: Vec<bool> = (0..flat.inputs.len() + 64).map(|_| rng.gen()).collect();
+            let arithmetic: Vec<u64> = vec![rng.gen()];
+
+            let expected = evaluate_program(&program, &boolean, &arithmetic)
+                .expect("Failed to evaluate program");
+            for optimized in [&isolated, &combined] {
+                assert_eq!(
+                    evaluate_program(optimized, &boolean, &arithmetic)
+                        .expect("Failed to evaluate program"),
+                    expected
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn prop_schedule_preserves_behaviour() {
+    for seed in 0..SEEDS {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let design = Design::random(seed);
+        let (flat, _) = flatten_design(&design, seed);
+
+        let program = to_composite_program(&flat, seed);
+        let scheduled = schedule(&program);
+        assert_eq!(scheduled.len(), program.len());
+
+        // Assertions may run in a different order, but inputs are consumed in the same one
+        let sorted = |mut outputs: sv_circuit::ProgramOutputs| {
+            outputs.boolean.sort();
+            outputs.arithmetic.sort();
+            outputs
+        };
+        for _ in 0..16 {
+            let boolean: Vec<bool> = (0..flat.inputs.len() + 64).map(|_| rng.gen()).collect();
+            let arithmetic: Vec<u64> = vec![rng.gen()];
+
+            let expected = evaluate_program(&program, &boolean, &arithmetic)
+                .expect("Failed to evaluate program");
+            let actual = evaluate_program(&scheduled, &boolean, &arithmetic)
+                .expect("Failed to evaluate program");
+            assert_eq!(sorted(actual), sorted(expected));
+        }
+    }
+}
+
+/// Writes `program` out as a container, alternately all at once and a chunk at a time. Streamed
+/// chunks are small enough that every pass crosses a few.
+fn to_container(program: &[CombineOperation], seed: u64) -> Cursor<Vec<u8>> {
+    let mut written = Cursor::new(Vec::new());
+    match seed % 2 {
+        0 => {
+            container::write(&mut written, program, true).expect("Failed to write container");
+            written
+        }
+        _ => {
+            let mut writer =
+                StreamWriter::with_chunk_size(written, true, 7).expect("Failed to open stream");
+            writer
+                .extend(program.iter().copied())
+                .expect("Failed to write stream");
+            writer.finish().expect("Failed to finish stream")
+        }
+    }
+}
+
+fn from_container(written: Cursor<Vec<u8>>) -> Vec<CombineOperation> {
+    let (header, operations) =
+        container::read(written.get_ref().as_slice()).expect("Failed to read container");
+    assert!(header.checksum.is_some());
+    operations
+}
+
+#[test]
+fn prop_streaming_passes_match() {
+    for seed in 0..SEEDS {
+        let design = Design::random(seed);
+        let (flat, _) = flatten_design(&design, seed);
+
+        let program = to_composite_program(&flat, seed);
+        let (largest_arith, largest_bool) = largest_wires(&program);
+        let max_wire = largest_arith.max(largest_bool) + 1;
+
+        let dce = eliminate_dead_code_stream(
+            &mut to_container(&program, seed),
+            Cursor::new(Vec::new()),
+            max_wire,
+        )
+        .expect("Failed to eliminate dead code");
+        assert_eq!(from_container(dce), eliminate_dead_code(&program, max_wire));
+
+        let isolated = isolate_arithmetic_wires_stream(
+            &mut to_container(&program, seed),
+            Cursor::new(Vec::new()),
+        )
+        .expect("Failed to isolate arithmetic wires");
+        assert_eq!(
+            from_container(isolated),
+            isolate_arithmetic_wires(program.clone())
+        );
+
+        let hinted =
+            insert_size_hint_stream(&mut to_container(&program, seed), Cursor::new(Vec::new()))
+                .expect("Failed to insert size hint");
+        let mut expected = program.clone();
+        insert_size_hint(&mut expected);
+        assert_eq!(from_container(hinted), expected);
+    }
+}
+
+#[test]
+fn prop_flatten_names_every_wire() {
+    for seed in 0..SEEDS {
+        let design = Design::random(seed);
+        let (flat, hasher) = flatten_design(&design, seed);
+
+        // Ports keep their local names, qualified with the top module
+        for wire in flat.inputs.iter().chain(flat.outputs.iter()) {
+            let local = hasher.backref(*wire).expect("Unnamed I/O wire");
+            assert_eq!(
+                flat.symbols.get(*wire),
+                Some(format!("top.{}", local).as_str())
+            );
+        }
+
+        // Every wire that survives flattening was driven by some gate in the design
+        for gate in flat.topo_iter() {
+            for wire in gate.inputs().chain(gate.outputs()) {
+                let name = flat.symbols.get(wire).expect("Unnamed wire");
+                assert!(name.starts_with("top."), "{}", name);
+            }
+        }
+    }
+}
+
+#[test]
+fn prop_aiger_roundtrip_preserves_behaviour() {
+    for seed in 0..SEEDS {
+        let design = Design::random(seed);
+        let (flat, _) = flatten_design(&design, seed);
+
+        for binary in [false, true] {
+            let mut written: Vec<u8> = Vec::new();
+            if binary {
+                aiger::write_binary(&mut written, &flat).expect("Failed to write AIGER");
+            } else {
+                aiger::write_ascii(&mut written, &flat).expect("Failed to write AIGER");
+            }
+            let read_back = aiger::read(written.as_slice()).expect("Failed to read AIGER");
+
+            // AIGER numbers inputs and outputs in port order, which is the design's bit order
+            assert_simulates_design(&design, &read_back);
+        }
+    }
+}
+
+#[test]
+fn prop_bristol_roundtrip_preserves_behaviour() {
+    for seed in 0..SEEDS {
+        let design = Design::random(seed);
+        let (flat, _) = flatten_design(&design, seed);
+
+        let mut written: Vec<u8> = Vec::new();
+        bristol::write(&mut written, &flat).expect("Failed to write Bristol");
+        let read_back = bristol::read(written.as_slice()).expect("Failed to read Bristol");
+
+        assert_simulates_design(&design, &read_back);
+    }
+}
+
+#[test]
+fn prop_blif_roundtrip_preserves_behaviour() {
+    for seed in 0..SEEDS {
+        let design = Design::random(seed);
+        let (flat, _) = flatten_design(&design, seed);
+
+        let path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(format!("flat_{}.blif", seed));
+        let mut file = File::create(&path).expect("Failed to create BLIF");
+        blif::write(&mut file, &flat).expect("Failed to write BLIF");
+
+        // Port names survive the round trip, so we can match wires up with the design by name
+        let reader = BufReader::new(File::open(&path).expect("Failed to open BLIF"));
+        let (read_back, _, hasher) =
+            sv_circuit::flatten(BlifParser::<bool>::new(reader)).expect("Failed to flatten BLIF");
+        assert_matches_design(&design, &read_back, &hasher, |inputs| {
+            read_back.simulate(inputs).expect("Failed to simulate")
+        });
+    }
+}
+
+#[test]
+fn prop_slices_preserve_behaviour() {
+    for seed in 0..SEEDS {
+        let design = Design::random(seed);
+        let (flat, hasher) = flatten_design(&design, seed);
+
+        let width = design.top().inputs;
+        for output in flat.outputs.iter() {
+            let slice = flat.slice(&[*output]).expect("Failed to slice");
+            let input_order = port_order(slice.inputs.iter().copied(), &hasher, "in");
+            let output_index = port_order(std::iter::once(*output), &hasher, "out")[0];
+
+            for assignment in 0..(1u64 << width) {
+                let inputs: Vec<bool> = (0..width).map(|i| (assignment >> i) & 1 == 1).collect();
+                let wire_inputs: Vec<bool> = input_order.iter().map(|i| inputs[*i]).collect();
+                assert_eq!(
+                    slice.simulate(&wire_inputs).expect("Failed to simulate"),
+                    vec![design.evaluate(&inputs)[output_index]],
+                    "Mismatch on inputs {:?}",
+                    inputs
+                );
+            }
+        }
+    }
+}